@@ -0,0 +1,97 @@
+// Timing of the enumeration against the simulated USB stack, run with `cargo bench`.
+// The latencies are rough figures of a busy machine, only the ratios are meaningful.
+
+use std::time::{Duration, Instant};
+
+use lswinusb::backend::Backend;
+use lswinusb::mock::MockBackend;
+use lswinusb::property::DeviceProperties;
+use lswinusb::{enumerate_parallel_with, get_all_hubs_with_devices_with};
+
+const RUNS: usize = 5;
+
+/// Fastest of `RUNS` runs
+fn time(mut f: impl FnMut()) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+/// A machine with `devices` USB devices among 500 other device nodes, a SetupDi
+/// scan takes 20 µs per device node
+fn machine(devices: u8) -> MockBackend {
+    let mut backend = MockBackend::generate(2, 1, devices / 4);
+    backend.other_devices = (0..500)
+        .map(|i| DeviceProperties {
+            driver_key: Some(format!(
+                r"{{4d36e97d-e325-11ce-bfc1-08002be10318}}\{:04}",
+                i
+            )),
+            ..Default::default()
+        })
+        .collect();
+    backend.scan_latency = Duration::from_micros(20);
+    backend
+}
+
+/// The device index is built with one scan for the whole enumeration. Before, every
+/// device looked up its device node with a scan of its own, which is what `per_device`
+/// adds on top of the IOCTLs.
+fn device_index() {
+    println!("device index: devices, one scan, one scan per device");
+    for devices in [8, 32, 128] {
+        let backend = machine(devices);
+        let found = get_all_hubs_with_devices_with(&backend, 0x0409)
+            .iter()
+            .map(|h| h.devices.len())
+            .sum::<usize>();
+        let indexed = time(|| {
+            get_all_hubs_with_devices_with(&backend, 0x0409);
+        });
+        let per_device = time(|| {
+            get_all_hubs_with_devices_with(&backend, 0x0409);
+            for _ in 1..found {
+                backend.device_properties().unwrap();
+            }
+        });
+        println!("{:>7} {:>12.2?} {:>20.2?}", found, indexed, per_device);
+    }
+}
+
+/// Hubs behind hubs are read concurrently. Every IOCTL takes 200 µs, the hub driver
+/// usually has to ask the device.
+fn parallel() {
+    println!("parallel: hubs, devices, sequential, 2 threads, 8 threads");
+    for (controllers, depth) in [(1, 1), (2, 3), (4, 4)] {
+        let mut backend = MockBackend::generate(controllers, depth, 3);
+        backend.ioctl_latency = Duration::from_micros(200);
+        let hubs = get_all_hubs_with_devices_with(&backend, 0x0409);
+        let devices = hubs.iter().map(|h| h.devices.len()).sum::<usize>();
+        let sequential = time(|| {
+            get_all_hubs_with_devices_with(&backend, 0x0409);
+        });
+        let threads = [2, 8].map(|threads| {
+            time(|| {
+                enumerate_parallel_with(&backend, 0x0409, threads);
+            })
+        });
+        println!(
+            "{:>4} {:>8} {:>12.2?} {:>12.2?} {:>12.2?}",
+            hubs.len(),
+            devices,
+            sequential,
+            threads[0],
+            threads[1]
+        );
+    }
+}
+
+fn main() {
+    device_index();
+    parallel();
+}
@@ -0,0 +1,249 @@
+use std::ffi::c_void;
+
+use windows::core::{HSTRING, PCWSTR};
+use windows::Win32::Foundation::{CloseHandle, BOOL, HANDLE};
+use windows::Win32::Storage::FileSystem::{
+    CreateFileW, FILE_GENERIC_WRITE, FILE_SHARE_WRITE, OPEN_EXISTING, SECURITY_ANONYMOUS,
+};
+use windows::Win32::System::IO::DeviceIoControl;
+
+use crate::driver::{get_device_properties, get_pci_device_ids};
+use crate::helper::get_error;
+use crate::layout::LAYOUT;
+use crate::property::DeviceProperties;
+
+/// Everything the enumeration needs from the system: device handles, IOCTLs and SetupDi
+/// properties. IOCTL buffers are passed as bytes, so the requests can be recorded and replayed.
+pub trait Backend: Sync {
+    /// Opens a hub or host controller, e.g. `HCD0` or the name returned for a downstream hub
+    fn open(&self, name: &str) -> Result<HANDLE, String>;
+    fn close(&self, handle: HANDLE);
+    /// Returns the number of bytes written to `output`
+    fn ioctl(
+        &self,
+        handle: HANDLE,
+        code: u32,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<usize, String>;
+    /// Properties of all present USB devnodes
+    fn device_properties(&self) -> Result<Vec<DeviceProperties>, String>;
+    /// Hardware IDs and location paths of the PCI device with the driver key
+    fn pci_device_ids(&self, driver_key: &str) -> Result<(Vec<String>, Vec<String>), String>;
+}
+
+/// The USB stack of the running system
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Win32Backend;
+
+impl Backend for Win32Backend {
+    fn open(&self, name: &str) -> Result<HANDLE, String> {
+        let path = HSTRING::from(format!(r"\\.\{}", name));
+        let result = unsafe {
+            CreateFileW(
+                PCWSTR(path.as_ptr()),
+                FILE_GENERIC_WRITE.0,
+                FILE_SHARE_WRITE,
+                None,
+                OPEN_EXISTING,
+                SECURITY_ANONYMOUS,
+                None,
+            )
+        };
+        result.map_err(|err| err.to_string())
+    }
+
+    fn close(&self, handle: HANDLE) {
+        unsafe { CloseHandle(handle) };
+    }
+
+    fn ioctl(
+        &self,
+        handle: HANDLE,
+        code: u32,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<usize, String> {
+        let mut returned: u32 = 0;
+        let result = unsafe {
+            DeviceIoControl(
+                handle,
+                code,
+                if input.is_empty() {
+                    None
+                } else {
+                    Some(input.as_ptr() as *const c_void)
+                },
+                input.len() as u32,
+                Some(output.as_mut_ptr() as *mut c_void),
+                output.len() as u32,
+                Some(&mut returned),
+                None,
+            )
+        };
+        if result == BOOL(1) {
+            Ok(returned as usize)
+        } else {
+            Err(get_error())
+        }
+    }
+
+    fn device_properties(&self) -> Result<Vec<DeviceProperties>, String> {
+        get_device_properties()
+    }
+
+    fn pci_device_ids(&self, driver_key: &str) -> Result<(Vec<String>, Vec<String>), String> {
+        get_pci_device_ids(driver_key)
+    }
+}
+
+/// Largest answer accepted by `sized_ioctl`, the longest descriptor behind the request header
+pub(crate) const MAX_SIZED_OUTPUT: usize = u16::MAX as usize + LAYOUT.descriptor_data_offset;
+
+/// Sends a request with a variable length answer in two steps: the first answer of
+/// `initial_size` bytes tells the required size (ActualLength, wTotalLength, ...), then the
+/// request is sent again with an exactly sized buffer. `request` builds the input for an
+/// output size, the first answer is used as is if it is already complete.
+pub(crate) fn sized_ioctl(
+    backend: &dyn Backend,
+    handle: HANDLE,
+    code: u32,
+    initial_size: usize,
+    request: impl Fn(usize) -> Vec<u8>,
+    required_size: impl Fn(&[u8]) -> Result<usize, String>,
+) -> Result<Vec<u8>, String> {
+    let mut outbuf = vec![0u8; initial_size];
+    let returned = backend.ioctl(handle, code, &request(initial_size), &mut outbuf)?;
+    outbuf.truncate(returned);
+    let size = required_size(&outbuf)?;
+    if size <= outbuf.len() {
+        outbuf.truncate(size);
+        return Ok(outbuf);
+    }
+    if size > MAX_SIZED_OUTPUT {
+        return Err(format!(
+            "IOCTL {:#x} needs {} bytes, more than the limit of {}",
+            code, size, MAX_SIZED_OUTPUT
+        ));
+    }
+
+    let mut outbuf = vec![0u8; size];
+    let returned = backend.ioctl(handle, code, &request(size), &mut outbuf)?;
+    if returned < size {
+        return Err(format!(
+            "IOCTL {:#x} returned {} of {} bytes",
+            code, returned, size
+        ));
+    }
+    Ok(outbuf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout;
+    use std::sync::Mutex;
+
+    /// Answers every IOCTL with a name structure of `reported` bytes, of which it
+    /// only writes `returned` bytes
+    struct NameBackend {
+        reported: usize,
+        returned: usize,
+        requests: Mutex<Vec<usize>>,
+    }
+
+    impl NameBackend {
+        fn new(reported: usize, returned: usize) -> NameBackend {
+            NameBackend {
+                reported,
+                returned,
+                requests: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn sized_ioctl(&self) -> Result<Vec<u8>, String> {
+            sized_ioctl(
+                self,
+                HANDLE(1),
+                0x220408,
+                LAYOUT.name_size,
+                |size| vec![0; size],
+                layout::name_length,
+            )
+        }
+    }
+
+    impl Backend for NameBackend {
+        fn open(&self, _name: &str) -> Result<HANDLE, String> {
+            Ok(HANDLE(1))
+        }
+
+        fn close(&self, _handle: HANDLE) {}
+
+        fn ioctl(
+            &self,
+            _handle: HANDLE,
+            _code: u32,
+            _input: &[u8],
+            output: &mut [u8],
+        ) -> Result<usize, String> {
+            self.requests.lock().unwrap().push(output.len());
+            let returned = self.returned.min(output.len()).min(self.reported.max(4));
+            let header = (self.reported as u32).to_le_bytes();
+            for (i, byte) in output[..returned].iter_mut().enumerate() {
+                *byte = header.get(i).copied().unwrap_or(b'A');
+            }
+            Ok(returned)
+        }
+
+        fn device_properties(&self) -> Result<Vec<DeviceProperties>, String> {
+            Ok(Vec::new())
+        }
+
+        fn pci_device_ids(&self, _driver_key: &str) -> Result<(Vec<String>, Vec<String>), String> {
+            Err("Not a PCI device".to_string())
+        }
+    }
+
+    #[test]
+    fn uses_the_first_answer_if_it_is_complete() {
+        let backend = NameBackend::new(6, usize::MAX);
+        assert_eq!(backend.sized_ioctl().unwrap().len(), 6);
+        assert_eq!(*backend.requests.lock().unwrap(), vec![6]);
+    }
+
+    #[test]
+    fn requests_the_reported_size() {
+        let backend = NameBackend::new(200, usize::MAX);
+        let buf = backend.sized_ioctl().unwrap();
+        assert_eq!(buf.len(), 200);
+        assert_eq!(*backend.requests.lock().unwrap(), vec![6, 200]);
+    }
+
+    #[test]
+    fn rejects_sizes_above_the_limit() {
+        let backend = NameBackend::new(MAX_SIZED_OUTPUT + 1, usize::MAX);
+        let err = backend.sized_ioctl().unwrap_err();
+        assert!(err.contains("more than the limit"), "{}", err);
+        // The oversized buffer is never allocated
+        assert_eq!(*backend.requests.lock().unwrap(), vec![6]);
+
+        let backend = NameBackend::new(u32::MAX as usize, usize::MAX);
+        assert!(backend.sized_ioctl().is_err());
+    }
+
+    #[test]
+    fn rejects_answers_shorter_than_the_header() {
+        for returned in 0..4 {
+            let backend = NameBackend::new(200, returned);
+            assert!(backend.sized_ioctl().is_err(), "{}", returned);
+        }
+    }
+
+    #[test]
+    fn rejects_incomplete_second_answers() {
+        let backend = NameBackend::new(200, 100);
+        let err = backend.sized_ioctl().unwrap_err();
+        assert!(err.contains("returned 100 of 200 bytes"), "{}", err);
+    }
+}
@@ -0,0 +1,208 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::descriptor::{UsbConfigurationDescriptor, UsbDeviceDescriptor};
+use crate::instance_id::InstanceId;
+use crate::property::PnpProperties;
+
+// https://learn.microsoft.com/en-us/windows-hardware/drivers/usbcon/usb-common-class-generic-parent-driver
+
+/// A function of a composite device, either a single interface or a group of
+/// interfaces described by an interface association descriptor (IAD).
+/// Windows creates one child device node (`...&MI_xx`) per function.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct DeviceFunction {
+    pub first_interface: u8,
+    pub interface_count: u8,
+    pub function_class: u8,
+    pub function_subclass: u8,
+    pub function_protocol: u8,
+    /// Driver key of the child device node, None if no driver is installed
+    pub driver_key_name: Option<String>,
+    /// Properties of the child device node, None if Windows did not create one
+    pub pnp: Option<PnpProperties>,
+}
+
+/// Same rules the USB hub driver uses to load the generic parent driver (usbccgp)
+pub fn is_composite(device: &UsbDeviceDescriptor, config: &UsbConfigurationDescriptor) -> bool {
+    let class_ok = device.bDeviceClass == 0x00
+        || (device.bDeviceClass == 0xEF
+            && device.bDeviceSubClass == 0x02
+            && device.bDeviceProtocol == 0x01);
+    class_ok && device.bNumConfigurations == 1 && config.bNumInterfaces > 1
+}
+
+/// Splits the interfaces of a configuration into functions, grouped by IADs
+pub fn device_functions(config: &UsbConfigurationDescriptor) -> Vec<DeviceFunction> {
+    let mut results: Vec<DeviceFunction> = Vec::new();
+    let mut interfaces: Vec<_> = config
+        .interfaces
+        .iter()
+        .filter(|i| i.bAlternateSetting == 0)
+        .collect();
+    interfaces.sort_by_key(|i| i.bInterfaceNumber);
+
+    for interface in interfaces {
+        let number = interface.bInterfaceNumber;
+        if results
+            .iter()
+            .any(|f| number >= f.first_interface && number - f.first_interface < f.interface_count)
+        {
+            continue; // Part of an IAD function
+        }
+        let association = config.associations.iter().find(|a| {
+            number >= a.bFirstInterface && number - a.bFirstInterface < a.bInterfaceCount
+        });
+        let function = match association {
+            Some(iad) => DeviceFunction {
+                first_interface: iad.bFirstInterface,
+                interface_count: iad.bInterfaceCount,
+                function_class: iad.bFunctionClass,
+                function_subclass: iad.bFunctionSubClass,
+                function_protocol: iad.bFunctionProtocol,
+                driver_key_name: None,
+                pnp: None,
+            },
+            None => DeviceFunction {
+                first_interface: number,
+                interface_count: 1,
+                function_class: interface.bInterfaceClass,
+                function_subclass: interface.bInterfaceSubClass,
+                function_protocol: interface.bInterfaceProtocol,
+                driver_key_name: None,
+                pnp: None,
+            },
+        };
+        results.push(function);
+    }
+    results
+}
+
+/// Attaches the child device nodes (driver key and properties) to the functions by their `MI_xx` number
+pub fn assign_children(
+    functions: &mut [DeviceFunction],
+    children: Vec<(Option<String>, PnpProperties)>,
+) {
+    for (driver_key_name, pnp) in children {
+        let number = match pnp.instance_id.as_deref().map(InstanceId::parse) {
+            Some(Ok(InstanceId { mi: Some(mi), .. })) => mi,
+            _ => continue,
+        };
+        if let Some(function) = functions.iter_mut().find(|f| f.first_interface == number) {
+            function.driver_key_name = driver_key_name;
+            function.pnp = Some(pnp);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// UVC camera with an IAD over interfaces 0 and 1 and a HID button on interface 2
+    fn camera_configuration() -> UsbConfigurationDescriptor {
+        let raw = [
+            9, 2, 74, 0, 3, 1, 0, 0x80, 250, // Configuration
+            8, 11, 0, 2, 0x0e, 0x03, 0x00, 0, // IAD, video interface collection
+            9, 4, 0, 0, 1, 0x0e, 0x01, 0x00, 0, // Video control
+            7, 5, 0x83, 3, 16, 0, 6, // Endpoint 3 IN
+            9, 4, 1, 0, 0, 0x0e, 0x02, 0x00, 0, // Video streaming
+            9, 4, 1, 1, 1, 0x0e, 0x02, 0x00, 0, // Video streaming, alternate setting 1
+            7, 5, 0x81, 5, 0x00, 0x14, 1, // Endpoint 1 IN
+            9, 4, 2, 0, 1, 0x03, 0x00, 0x00, 0, // HID
+            7, 5, 0x82, 3, 8, 0, 10, // Endpoint 2 IN
+        ];
+        UsbConfigurationDescriptor::from_bytes(&raw).unwrap()
+    }
+
+    /// Serial adapter and mass storage without IADs, listed out of order
+    fn plain_configuration() -> UsbConfigurationDescriptor {
+        let raw = [
+            9, 2, 55, 0, 2, 1, 0, 0x80, 100, // Configuration
+            9, 4, 1, 0, 2, 0x08, 0x06, 0x50, 0, // Mass storage
+            7, 5, 0x83, 2, 64, 0, 0, // Endpoint 3 IN
+            7, 5, 0x03, 2, 64, 0, 0, // Endpoint 3 OUT
+            9, 4, 0, 0, 2, 0xff, 0x00, 0x00, 0, // Vendor specific
+            7, 5, 0x81, 2, 64, 0, 0, // Endpoint 1 IN
+            7, 5, 0x02, 2, 64, 0, 0, // Endpoint 2 OUT
+        ];
+        UsbConfigurationDescriptor::from_bytes(&raw).unwrap()
+    }
+
+    fn child(driver_key: &str, instance_id: &str) -> (Option<String>, PnpProperties) {
+        let pnp = PnpProperties {
+            instance_id: Some(instance_id.to_string()),
+            ..Default::default()
+        };
+        (Some(driver_key.to_string()), pnp)
+    }
+
+    fn summary(functions: &[DeviceFunction]) -> Vec<(u8, u8, u8)> {
+        functions
+            .iter()
+            .map(|f| (f.first_interface, f.interface_count, f.function_class))
+            .collect()
+    }
+
+    #[test]
+    fn groups_interfaces_of_an_iad() {
+        let functions = device_functions(&camera_configuration());
+        assert_eq!(summary(&functions), vec![(0, 2, 0x0e), (2, 1, 0x03)]);
+        assert_eq!(functions[0].function_subclass, 0x03);
+    }
+
+    #[test]
+    fn splits_composite_without_iads() {
+        let config = plain_configuration();
+        let descriptor = UsbDeviceDescriptor {
+            bNumConfigurations: 1,
+            ..Default::default()
+        };
+        assert!(is_composite(&descriptor, &config));
+        let functions = device_functions(&config);
+        assert_eq!(summary(&functions), vec![(0, 1, 0xff), (1, 1, 0x08)]);
+        assert_eq!(functions[1].function_subclass, 0x06);
+        assert_eq!(functions[1].function_protocol, 0x50);
+    }
+
+    #[test]
+    fn assigns_children_by_interface_number() {
+        let mut functions = device_functions(&camera_configuration());
+        assign_children(
+            &mut functions,
+            vec![
+                child(
+                    r"{745a17a0-74d3-11d0-b6fe-00a0c90f57da}\0012",
+                    r"USB\VID_046D&PID_085C&MI_02\7&2b7c5e1&0&0002",
+                ),
+                child(
+                    r"{ca3e7ab9-b4c3-4ae6-8251-579ef933890f}\0003",
+                    r"USB\VID_046D&PID_085C&MI_00\7&2b7c5e1&0&0000",
+                ),
+                // Streaming interface, part of the camera function
+                child(
+                    r"{36fc9e60-c465-11cf-8056-444553540000}\0040",
+                    r"USB\VID_046D&PID_085C&MI_01\7&2b7c5e1&0&0001",
+                ),
+                // Not a function node
+                child(r"{36fc9e60-c465-11cf-8056-444553540000}\0041", "garbage"),
+            ],
+        );
+        let keys: Vec<Option<&str>> = functions
+            .iter()
+            .map(|f| f.driver_key_name.as_deref())
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                Some(r"{ca3e7ab9-b4c3-4ae6-8251-579ef933890f}\0003"),
+                Some(r"{745a17a0-74d3-11d0-b6fe-00a0c90f57da}\0012"),
+            ]
+        );
+        assert_eq!(
+            functions[1].pnp.as_ref().unwrap().instance_id.as_deref(),
+            Some(r"USB\VID_046D&PID_085C&MI_02\7&2b7c5e1&0&0002")
+        );
+    }
+}
@@ -0,0 +1,303 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+// https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/usbioctl/ns-usbioctl-_usb_node_connection_information_ex_v2
+
+/// Size of USB_NODE_CONNECTION_INFORMATION_EX_V2, the struct is packed and consists of four ULONGs
+pub const CONNECTION_INFORMATION_EX_V2_SIZE: usize = 16;
+
+const USB_PROTOCOL_110: u32 = 1 << 0;
+const USB_PROTOCOL_200: u32 = 1 << 1;
+const USB_PROTOCOL_300: u32 = 1 << 2;
+
+const FLAG_OPERATING_AT_SUPERSPEED: u32 = 1 << 0;
+const FLAG_SUPERSPEED_CAPABLE: u32 = 1 << 1;
+const FLAG_OPERATING_AT_SUPERSPEED_PLUS: u32 = 1 << 2;
+const FLAG_SUPERSPEED_PLUS_CAPABLE: u32 = 1 << 3;
+
+/// Bus speed as reported by USB_NODE_CONNECTION_INFORMATION_EX.Speed (USB_DEVICE_SPEED)
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UsbSpeed {
+    Low,
+    Full,
+    High,
+    Super,
+}
+
+impl UsbSpeed {
+    pub fn from_raw(speed: u8) -> Option<UsbSpeed> {
+        match speed {
+            0 => Some(UsbSpeed::Low),
+            1 => Some(UsbSpeed::Full),
+            2 => Some(UsbSpeed::High),
+            3 => Some(UsbSpeed::Super),
+            _ => None,
+        }
+    }
+}
+
+/// USB protocols supported by a hub port (USB_PROTOCOLS)
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UsbProtocols {
+    pub usb110: bool,
+    pub usb200: bool,
+    pub usb300: bool,
+}
+
+impl UsbProtocols {
+    pub fn from_bits(bits: u32) -> UsbProtocols {
+        UsbProtocols {
+            usb110: bits & USB_PROTOCOL_110 != 0,
+            usb200: bits & USB_PROTOCOL_200 != 0,
+            usb300: bits & USB_PROTOCOL_300 != 0,
+        }
+    }
+
+    pub fn to_bits(&self) -> u32 {
+        let mut bits = 0;
+        if self.usb110 {
+            bits |= USB_PROTOCOL_110;
+        }
+        if self.usb200 {
+            bits |= USB_PROTOCOL_200;
+        }
+        if self.usb300 {
+            bits |= USB_PROTOCOL_300;
+        }
+        bits
+    }
+}
+
+/// Decoded USB_NODE_CONNECTION_INFORMATION_EX_V2
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionInfoV2 {
+    pub supported_protocols: UsbProtocols,
+    pub operating_at_superspeed: bool,
+    pub superspeed_capable: bool,
+    pub operating_at_superspeed_plus: bool,
+    pub superspeed_plus_capable: bool,
+}
+
+impl ConnectionInfoV2 {
+    /// Builds the input buffer for IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX_V2.
+    /// The caller has to announce the protocols it understands, otherwise the hub driver reports nothing.
+    pub fn request(port_number: u8) -> [u8; CONNECTION_INFORMATION_EX_V2_SIZE] {
+        let mut buf = [0u8; CONNECTION_INFORMATION_EX_V2_SIZE];
+        let protocols = UsbProtocols {
+            usb110: true,
+            usb200: true,
+            usb300: true,
+        };
+        buf[0..4].copy_from_slice(&(port_number as u32).to_le_bytes());
+        buf[4..8].copy_from_slice(&(CONNECTION_INFORMATION_EX_V2_SIZE as u32).to_le_bytes());
+        buf[8..12].copy_from_slice(&protocols.to_bits().to_le_bytes());
+        buf
+    }
+
+    /// Decodes the output buffer of IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX_V2
+    pub fn from_bytes(buf: &[u8]) -> Result<ConnectionInfoV2, String> {
+        if buf.len() < CONNECTION_INFORMATION_EX_V2_SIZE {
+            return Err(format!(
+                "Connection information is {} bytes, expected {}",
+                buf.len(),
+                CONNECTION_INFORMATION_EX_V2_SIZE
+            ));
+        }
+        let length = u32::from_le_bytes(buf[4..8].try_into().unwrap());
+        if (length as usize) < CONNECTION_INFORMATION_EX_V2_SIZE {
+            return Err(format!("Invalid connection information length {}", length));
+        }
+        let protocols = u32::from_le_bytes(buf[8..12].try_into().unwrap());
+        let flags = u32::from_le_bytes(buf[12..16].try_into().unwrap());
+        Ok(ConnectionInfoV2 {
+            supported_protocols: UsbProtocols::from_bits(protocols),
+            operating_at_superspeed: flags & FLAG_OPERATING_AT_SUPERSPEED != 0,
+            superspeed_capable: flags & FLAG_SUPERSPEED_CAPABLE != 0,
+            operating_at_superspeed_plus: flags & FLAG_OPERATING_AT_SUPERSPEED_PLUS != 0,
+            superspeed_plus_capable: flags & FLAG_SUPERSPEED_PLUS_CAPABLE != 0,
+        })
+    }
+
+    /// True if the device could run faster than it currently does,
+    /// e.g. a SuperSpeed device behind a USB 2.0 cable or hub.
+    pub fn is_speed_downgraded(&self) -> bool {
+        (self.superspeed_capable && !self.operating_at_superspeed)
+            || (self.superspeed_plus_capable && !self.operating_at_superspeed_plus)
+    }
+}
+
+// https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/usbioctl/ns-usbioctl-_usb_port_connector_properties
+
+/// Offset of CompanionHubSymbolicLinkName in USB_PORT_CONNECTOR_PROPERTIES
+pub const PORT_CONNECTOR_PROPERTIES_NAME_OFFSET: usize = 16;
+
+const PORT_USER_CONNECTABLE: u32 = 1 << 0;
+const PORT_DEBUG_CAPABLE: u32 = 1 << 1;
+const PORT_HAS_MULTIPLE_COMPANIONS: u32 = 1 << 2;
+const PORT_CONNECTOR_IS_TYPE_C: u32 = 1 << 3;
+
+/// Decoded USB_PORT_CONNECTOR_PROPERTIES of a single hub port
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PortConnectorProperties {
+    pub port_number: u8,
+    pub user_connectable: bool,
+    pub debug_capable: bool,
+    pub has_multiple_companions: bool,
+    pub type_c: bool,
+    /// Index of the companion port, 0 if there is no companion
+    pub companion_index: u16,
+    pub companion_port_number: u16,
+    pub companion_hub: Option<String>,
+}
+
+impl PortConnectorProperties {
+    /// Builds the input buffer for IOCTL_USB_GET_PORT_CONNECTOR_PROPERTIES
+    pub fn request(port_number: u8) -> [u8; PORT_CONNECTOR_PROPERTIES_NAME_OFFSET + 2] {
+        let mut buf = [0u8; PORT_CONNECTOR_PROPERTIES_NAME_OFFSET + 2];
+        buf[0..4].copy_from_slice(&(port_number as u32).to_le_bytes());
+        buf
+    }
+
+    /// Size of the output including the companion hub symbolic link name (ActualLength)
+    pub fn required_size(buf: &[u8]) -> Result<usize, String> {
+        if buf.len() < 8 {
+            return Err(format!(
+                "Port connector properties are {} bytes, expected at least 8",
+                buf.len()
+            ));
+        }
+        Ok(u32::from_le_bytes(buf[4..8].try_into().unwrap()) as usize)
+    }
+
+    /// Decodes the output buffer of IOCTL_USB_GET_PORT_CONNECTOR_PROPERTIES
+    pub fn from_bytes(buf: &[u8]) -> Result<PortConnectorProperties, String> {
+        if buf.len() < PORT_CONNECTOR_PROPERTIES_NAME_OFFSET {
+            return Err(format!(
+                "Port connector properties are {} bytes, expected at least {}",
+                buf.len(),
+                PORT_CONNECTOR_PROPERTIES_NAME_OFFSET
+            ));
+        }
+        let port_number = u32::from_le_bytes(buf[0..4].try_into().unwrap());
+        let actual_length = u32::from_le_bytes(buf[4..8].try_into().unwrap()) as usize;
+        let properties = u32::from_le_bytes(buf[8..12].try_into().unwrap());
+        let companion_index = u16::from_le_bytes(buf[12..14].try_into().unwrap());
+        let companion_port_number = u16::from_le_bytes(buf[14..16].try_into().unwrap());
+
+        let end = actual_length.clamp(PORT_CONNECTOR_PROPERTIES_NAME_OFFSET, buf.len());
+        let name: Vec<u16> = buf[PORT_CONNECTOR_PROPERTIES_NAME_OFFSET..end]
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        let name = String::from_utf16_lossy(&name);
+        let name = name.trim_end_matches('\0');
+
+        Ok(PortConnectorProperties {
+            port_number: port_number as u8,
+            user_connectable: properties & PORT_USER_CONNECTABLE != 0,
+            debug_capable: properties & PORT_DEBUG_CAPABLE != 0,
+            has_multiple_companions: properties & PORT_HAS_MULTIPLE_COMPANIONS != 0,
+            type_c: properties & PORT_CONNECTOR_IS_TYPE_C != 0,
+            companion_index,
+            companion_port_number,
+            companion_hub: if name.is_empty() {
+                None
+            } else {
+                Some(name.to_string())
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// USB_NODE_CONNECTION_INFORMATION_EX_V2 of port 3: USB 2.0 and 3.0, SuperSpeed capable
+    const CONNECTION_INFO_V2_DOWNGRADED: [u8; 16] = [
+        0x03, 0x00, 0x00, 0x00, // ConnectionIndex
+        0x10, 0x00, 0x00, 0x00, // Length
+        0x06, 0x00, 0x00, 0x00, // SupportedUsbProtocols
+        0x02, 0x00, 0x00, 0x00, // Flags
+    ];
+
+    #[test]
+    fn request_announces_all_protocols() {
+        let request = ConnectionInfoV2::request(3);
+        assert_eq!(request, [3, 0, 0, 0, 16, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn decodes_protocols_and_downgrade() {
+        let info = ConnectionInfoV2::from_bytes(&CONNECTION_INFO_V2_DOWNGRADED).unwrap();
+        assert_eq!(
+            info.supported_protocols,
+            UsbProtocols {
+                usb110: false,
+                usb200: true,
+                usb300: true,
+            }
+        );
+        assert!(info.superspeed_capable);
+        assert!(!info.operating_at_superspeed);
+        assert!(!info.superspeed_plus_capable);
+        assert!(info.is_speed_downgraded());
+    }
+
+    #[test]
+    fn decodes_every_flag() {
+        let mut buf = CONNECTION_INFO_V2_DOWNGRADED;
+        for (bit, expected) in [
+            (0, (true, false, false, false)),
+            (1, (false, true, false, false)),
+            (2, (false, false, true, false)),
+            (3, (false, false, false, true)),
+        ] {
+            buf[12..16].copy_from_slice(&(1u32 << bit).to_le_bytes());
+            let info = ConnectionInfoV2::from_bytes(&buf).unwrap();
+            assert_eq!(
+                (
+                    info.operating_at_superspeed,
+                    info.superspeed_capable,
+                    info.operating_at_superspeed_plus,
+                    info.superspeed_plus_capable,
+                ),
+                expected,
+                "flag bit {}",
+                bit
+            );
+        }
+    }
+
+    #[test]
+    fn superspeed_plus_device_at_superspeed_is_downgraded() {
+        let mut buf = CONNECTION_INFO_V2_DOWNGRADED;
+        buf[12..16].copy_from_slice(&0x0bu32.to_le_bytes());
+        let info = ConnectionInfoV2::from_bytes(&buf).unwrap();
+        assert!(info.operating_at_superspeed);
+        assert!(info.is_speed_downgraded());
+
+        buf[12..16].copy_from_slice(&0x0fu32.to_le_bytes());
+        let info = ConnectionInfoV2::from_bytes(&buf).unwrap();
+        assert!(!info.is_speed_downgraded());
+    }
+
+    #[test]
+    fn rejects_short_buffers_and_lengths() {
+        assert!(ConnectionInfoV2::from_bytes(&CONNECTION_INFO_V2_DOWNGRADED[..15]).is_err());
+        let mut buf = CONNECTION_INFO_V2_DOWNGRADED;
+        buf[4] = 8;
+        assert!(ConnectionInfoV2::from_bytes(&buf).is_err());
+    }
+
+    #[test]
+    fn protocols_round_trip() {
+        for bits in 0..8 {
+            assert_eq!(UsbProtocols::from_bits(bits).to_bits(), bits);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::descriptor::{Device, Hub};
+use crate::guid::Guid;
+
+/// All devices of one physical product, e.g. the hub and the card reader of a docking station
+#[derive(Debug)]
+pub struct ContainerGroup<'a> {
+    pub container_id: Guid,
+    pub devices: Vec<&'a Device>,
+}
+
+/// Groups devices by container ID in enumeration order.
+/// Devices built into the computer share one container and are not grouped.
+pub fn group_by_container(hubs: &[Hub]) -> Vec<ContainerGroup<'_>> {
+    let mut results: Vec<ContainerGroup> = Vec::new();
+    for device in hubs.iter().flat_map(|h| h.devices.iter()) {
+        let container_id = match device.container_id {
+            Some(id) if id != Guid::LOCAL_MACHINE && !id.is_nil() => id,
+            _ => continue,
+        };
+        match results.iter_mut().find(|g| g.container_id == container_id) {
+            Some(group) => group.devices.push(device),
+            None => results.push(ContainerGroup {
+                container_id,
+                devices: vec![device],
+            }),
+        }
+    }
+    results
+}
+
+/// Result of comparing the container ID of the device node with the BOS descriptor
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerIdCheck {
+    /// The device has no Container ID capability, Windows generated the ID
+    NotReported,
+    Match,
+    /// Windows ignored the Container ID of the device, e.g. because it is not removable
+    /// or another device already uses the same ID
+    Mismatch {
+        bos: Guid,
+        pnp: Option<Guid>,
+    },
+}
+
+pub fn check_container_id(device: &Device) -> ContainerIdCheck {
+    let bos = match device.bos.as_ref().and_then(|b| b.container_id()) {
+        Some(id) => id,
+        None => return ContainerIdCheck::NotReported,
+    };
+    if device.container_id == Some(bos) {
+        ContainerIdCheck::Match
+    } else {
+        ContainerIdCheck::Mismatch {
+            bos,
+            pnp: device.container_id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::{UsbBosDescriptor, UsbDeviceDescriptor};
+
+    const DOCK: &str = "{6a3c0b1e-8f2d-5a4b-9c7e-1d2f3a4b5c6d}";
+
+    fn device(product_id: u16, container_id: Option<&str>) -> Device {
+        let descriptor = UsbDeviceDescriptor {
+            idVendor: 0x17ef,
+            idProduct: product_id,
+            ..Default::default()
+        };
+        let mut device = Device::from_descriptors(1, None, descriptor, None);
+        device.container_id = container_id.map(|id| id.parse().unwrap());
+        device
+    }
+
+    fn hub(hub_id: &str, devices: Vec<Device>) -> Hub {
+        Hub {
+            hub_id: hub_id.to_string(),
+            number_of_ports: 4,
+            devices,
+            ports: Vec::new(),
+            parent_hub: None,
+            parent_port: None,
+            host_controller: None,
+            descriptor: None,
+        }
+    }
+
+    /// BOS descriptor with a Container ID capability
+    fn bos(container_id: &str) -> UsbBosDescriptor {
+        let guid: Guid = container_id.parse().unwrap();
+        let mut raw = vec![
+            5, 15, 25, 0, 1, // BOS
+            20, 16, 4, 0, // Container ID capability
+        ];
+        raw.extend_from_slice(&guid.to_bytes());
+        UsbBosDescriptor::from_bytes(&raw).unwrap()
+    }
+
+    #[test]
+    fn groups_devices_of_one_container() {
+        let hubs = vec![
+            hub(
+                "usb1",
+                vec![
+                    device(0x1010, Some(DOCK)),
+                    device(0x2020, Some("{00000000-0000-0000-ffff-ffffffffffff}")),
+                    device(0x3030, Some("{00000000-0000-0000-0000-000000000000}")),
+                    device(0x4040, None),
+                ],
+            ),
+            hub(
+                "1-1",
+                vec![
+                    device(0x5050, Some("{11111111-2222-3333-4444-555555555555}")),
+                    device(0x6060, Some(DOCK)),
+                ],
+            ),
+        ];
+        let groups: Vec<(String, Vec<u16>)> = group_by_container(&hubs)
+            .iter()
+            .map(|g| {
+                let products = g.devices.iter().map(|d| d.descriptor.idProduct).collect();
+                (g.container_id.to_string(), products)
+            })
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                (DOCK.to_string(), vec![0x1010, 0x6060]),
+                (
+                    "{11111111-2222-3333-4444-555555555555}".to_string(),
+                    vec![0x5050]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_container_id_of_bos() {
+        let mut device = device(0x1010, Some(DOCK));
+        assert_eq!(check_container_id(&device), ContainerIdCheck::NotReported);
+
+        device.bos = Some(bos(DOCK));
+        assert_eq!(check_container_id(&device), ContainerIdCheck::Match);
+
+        let machine: Guid = "{00000000-0000-0000-ffff-ffffffffffff}".parse().unwrap();
+        device.container_id = Some(machine);
+        assert_eq!(
+            check_container_id(&device),
+            ContainerIdCheck::Mismatch {
+                bos: DOCK.parse().unwrap(),
+                pnp: Some(machine),
+            }
+        );
+        device.container_id = None;
+        assert_eq!(
+            check_container_id(&device),
+            ContainerIdCheck::Mismatch {
+                bos: DOCK.parse().unwrap(),
+                pnp: None,
+            }
+        );
+    }
+}
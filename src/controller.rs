@@ -0,0 +1,320 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+// https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/usbuser/ns-usbuser-_usbuser_controller_info_0
+
+/// CTL_CODE(FILE_DEVICE_USB, HCD_USER_REQUEST, METHOD_BUFFERED, FILE_ANY_ACCESS), not exported by the windows crate
+pub const IOCTL_USB_USER_REQUEST: u32 = 0x220438;
+const USBUSER_GET_CONTROLLER_INFO_0: u32 = 0x00000001;
+const USBUSER_SUCCESS: u32 = 0;
+
+/// USBUSER_REQUEST_HEADER followed by USB_CONTROLLER_INFO_0, both packed
+pub const CONTROLLER_INFO_0_SIZE: usize = 16 + 24;
+
+/// Host controller interface, derived from the PCI class code or USB_CONTROLLER_FLAVOR
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControllerFlavor {
+    Uhci,
+    Ohci,
+    Ehci,
+    Xhci,
+    Unknown,
+}
+
+impl ControllerFlavor {
+    /// PCI programming interface of class 0x0C03 (serial bus, USB)
+    pub fn from_prog_if(prog_if: u8) -> ControllerFlavor {
+        match prog_if {
+            0x00 => ControllerFlavor::Uhci,
+            0x10 => ControllerFlavor::Ohci,
+            0x20 => ControllerFlavor::Ehci,
+            0x30 => ControllerFlavor::Xhci,
+            _ => ControllerFlavor::Unknown,
+        }
+    }
+
+    /// USB_CONTROLLER_FLAVOR only knows the legacy controllers, xHCI reports USB_HcGeneric
+    pub fn from_raw(flavor: u32) -> ControllerFlavor {
+        match flavor {
+            // OHCI_Generic, OHCI_Hydra, OHCI_NEC
+            100..=102 => ControllerFlavor::Ohci,
+            // UHCI_Generic to UHCI_Ich6, UHCI_Intel, UHCI_VIA to UHCI_VIA_x04, UHCI_VIA_x0E_FIFO
+            200..=209 | 249..=254 | 264 => ControllerFlavor::Uhci,
+            // EHCI_Generic, EHCI_NEC, EHCI_Lucent, EHCI_NVIDIA_Tegra2/3, EHCI_Intel_Medfield
+            1000 | 2000 | 3000 | 4000 | 4001 | 5001 => ControllerFlavor::Ehci,
+            _ => ControllerFlavor::Unknown,
+        }
+    }
+}
+
+/// Decoded USB_CONTROLLER_INFO_0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControllerInfo {
+    pub pci_vendor_id: u32,
+    pub pci_device_id: u32,
+    pub pci_revision: u32,
+    pub number_of_root_ports: u32,
+    pub controller_flavor: u32,
+}
+
+impl ControllerInfo {
+    /// Builds the input buffer for IOCTL_USB_USER_REQUEST with USBUSER_GET_CONTROLLER_INFO_0
+    pub fn request() -> [u8; CONTROLLER_INFO_0_SIZE] {
+        let mut buf = [0u8; CONTROLLER_INFO_0_SIZE];
+        buf[0..4].copy_from_slice(&USBUSER_GET_CONTROLLER_INFO_0.to_le_bytes());
+        buf[8..12].copy_from_slice(&(CONTROLLER_INFO_0_SIZE as u32).to_le_bytes());
+        buf
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Result<ControllerInfo, String> {
+        if buf.len() < CONTROLLER_INFO_0_SIZE {
+            return Err(format!(
+                "Controller information is {} bytes, expected {}",
+                buf.len(),
+                CONTROLLER_INFO_0_SIZE
+            ));
+        }
+        let field = |offset: usize| u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap());
+        let status = field(4);
+        if status != USBUSER_SUCCESS {
+            return Err(format!("User request failed with status {:#x}", status));
+        }
+        Ok(ControllerInfo {
+            pci_vendor_id: field(16),
+            pci_device_id: field(20),
+            pci_revision: field(24),
+            number_of_root_ports: field(28),
+            controller_flavor: field(32),
+        })
+    }
+}
+
+/// PCI identity of a host controller
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PciIdentity {
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub subsystem_vendor_id: Option<u16>,
+    pub subsystem_id: Option<u16>,
+    pub revision: u8,
+    /// Class code with programming interface, e.g. 0x0C0330 for xHCI
+    pub class_code: Option<u32>,
+}
+
+fn hex_field(id: &str, key: &str, len: usize) -> Option<u32> {
+    let id = id.to_uppercase();
+    for part in id.split(['\\', '&']) {
+        if let Some(value) = part.strip_prefix(key) {
+            if value.len() == len {
+                return u32::from_str_radix(value, 16).ok();
+            }
+        }
+    }
+    None
+}
+
+impl PciIdentity {
+    /// Parses PCI hardware IDs like `PCI\VEN_8086&DEV_A36D&SUBSYS_86941043&REV_10`
+    /// and `PCI\VEN_8086&DEV_A36D&CC_0C0330`. The most specific value of every field wins.
+    pub fn from_hardware_ids(ids: &[String]) -> Option<PciIdentity> {
+        let mut identity: Option<PciIdentity> = None;
+        for id in ids {
+            if !id.to_uppercase().starts_with(r"PCI\") {
+                continue;
+            }
+            let result = identity.get_or_insert_with(PciIdentity::default);
+            if let Some(vendor) = hex_field(id, "VEN_", 4) {
+                result.vendor_id = vendor as u16;
+            }
+            if let Some(device) = hex_field(id, "DEV_", 4) {
+                result.device_id = device as u16;
+            }
+            if let Some(subsys) = hex_field(id, "SUBSYS_", 8) {
+                if result.subsystem_id.is_none() {
+                    // SUBSYS_ssssvvvv, subsystem ID first
+                    result.subsystem_id = Some((subsys >> 16) as u16);
+                    result.subsystem_vendor_id = Some(subsys as u16);
+                }
+            }
+            if let Some(revision) = hex_field(id, "REV_", 2) {
+                result.revision = revision as u8;
+            }
+            if let Some(class_code) = hex_field(id, "CC_", 6) {
+                if result.class_code.is_none() {
+                    result.class_code = Some(class_code);
+                }
+            }
+        }
+        identity
+    }
+}
+
+/// A USB host controller (`\\.\HCD{index}`)
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct HostController {
+    pub index: u8,
+    pub driver_key_name: String,
+    pub pci: Option<PciIdentity>,
+    pub flavor: ControllerFlavor,
+    pub number_of_root_ports: Option<u32>,
+    /// Windows location path of the controller, e.g. `PCIROOT(0)#PCI(1400)`
+    pub location_path: Option<String>,
+}
+
+impl HostController {
+    /// Prefers the PCI class code, the flavor reported by the USB stack is generic for xHCI
+    pub fn detect_flavor(
+        pci: &Option<PciIdentity>,
+        info: &Option<ControllerInfo>,
+        root_hub: &str,
+    ) -> ControllerFlavor {
+        if let Some(class_code) = pci.as_ref().and_then(|p| p.class_code) {
+            if class_code >> 8 == 0x0C03 {
+                return ControllerFlavor::from_prog_if(class_code as u8);
+            }
+        }
+        if let Some(info) = info {
+            let flavor = ControllerFlavor::from_raw(info.controller_flavor);
+            if flavor != ControllerFlavor::Unknown {
+                return flavor;
+            }
+        }
+        let root_hub = root_hub.to_uppercase();
+        if root_hub.contains("ROOT_HUB30") {
+            ControllerFlavor::Xhci
+        } else if root_hub.contains("ROOT_HUB20") {
+            ControllerFlavor::Ehci
+        } else {
+            ControllerFlavor::Unknown
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flavor_from_raw_matches_enum_values() {
+        assert_eq!(ControllerFlavor::from_raw(0), ControllerFlavor::Unknown);
+        assert_eq!(ControllerFlavor::from_raw(101), ControllerFlavor::Ohci);
+        assert_eq!(ControllerFlavor::from_raw(205), ControllerFlavor::Uhci);
+        assert_eq!(ControllerFlavor::from_raw(252), ControllerFlavor::Uhci);
+        assert_eq!(ControllerFlavor::from_raw(264), ControllerFlavor::Uhci);
+        assert_eq!(ControllerFlavor::from_raw(1000), ControllerFlavor::Ehci);
+        assert_eq!(ControllerFlavor::from_raw(4001), ControllerFlavor::Ehci);
+        assert_eq!(ControllerFlavor::from_raw(5001), ControllerFlavor::Ehci);
+        for unknown in [103, 199, 210, 248, 263, 999, 1001, 4002, 5000, u32::MAX] {
+            assert_eq!(
+                ControllerFlavor::from_raw(unknown),
+                ControllerFlavor::Unknown,
+                "{}",
+                unknown
+            );
+        }
+    }
+
+    #[test]
+    fn decodes_controller_info() {
+        let mut buf = [0u8; CONTROLLER_INFO_0_SIZE];
+        buf[16..20].copy_from_slice(&0x8086u32.to_le_bytes());
+        buf[20..24].copy_from_slice(&0x1e31u32.to_le_bytes());
+        buf[24..28].copy_from_slice(&4u32.to_le_bytes());
+        buf[28..32].copy_from_slice(&8u32.to_le_bytes());
+        buf[32..36].copy_from_slice(&1000u32.to_le_bytes());
+        let info = ControllerInfo::from_bytes(&buf).unwrap();
+        assert_eq!(info.pci_vendor_id, 0x8086);
+        assert_eq!(info.pci_device_id, 0x1e31);
+        assert_eq!(info.pci_revision, 4);
+        assert_eq!(info.number_of_root_ports, 8);
+        assert_eq!(
+            ControllerFlavor::from_raw(info.controller_flavor),
+            ControllerFlavor::Ehci
+        );
+    }
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_pci_hardware_ids() {
+        let identity = PciIdentity::from_hardware_ids(&ids(&[
+            r"PCI\VEN_8086&DEV_A36D&SUBSYS_86941043&REV_10",
+            r"PCI\VEN_8086&DEV_A36D&SUBSYS_86941043",
+            r"PCI\VEN_8086&DEV_A36D&CC_0C0330",
+            r"PCI\VEN_8086&DEV_A36D&CC_0C03",
+        ]))
+        .unwrap();
+        assert_eq!(
+            identity,
+            PciIdentity {
+                vendor_id: 0x8086,
+                device_id: 0xa36d,
+                subsystem_vendor_id: Some(0x1043),
+                subsystem_id: Some(0x8694),
+                revision: 0x10,
+                class_code: Some(0x0c0330),
+            }
+        );
+    }
+
+    #[test]
+    fn parses_pci_hardware_ids_without_subsystem() {
+        let identity =
+            PciIdentity::from_hardware_ids(&ids(&[r"pci\ven_1022&dev_149c", r"PCI\VEN_1022"]))
+                .unwrap();
+        assert_eq!((identity.vendor_id, identity.device_id), (0x1022, 0x149c));
+        assert_eq!(identity.subsystem_vendor_id, None);
+        assert_eq!(identity.subsystem_id, None);
+        assert_eq!(identity.revision, 0);
+        assert_eq!(identity.class_code, None);
+    }
+
+    #[test]
+    fn ignores_non_pci_hardware_ids() {
+        let usb = ids(&[r"USB\ROOT_HUB30&VID8086&PIDA36D&REV0010", r"USB\ROOT_HUB30"]);
+        assert_eq!(PciIdentity::from_hardware_ids(&usb), None);
+        assert_eq!(PciIdentity::from_hardware_ids(&[]), None);
+    }
+
+    #[test]
+    fn detects_flavor() {
+        let xhci = PciIdentity::from_hardware_ids(&ids(&[r"PCI\VEN_8086&DEV_A36D&CC_0C0330"]));
+        assert_eq!(
+            HostController::detect_flavor(&xhci, &None, ""),
+            ControllerFlavor::Xhci
+        );
+
+        // EHCI_NEC without a class code, the flavor of the USB stack is used
+        let nec = PciIdentity::from_hardware_ids(&ids(&[r"PCI\VEN_1033&DEV_00E0&REV_04"]));
+        let info = ControllerInfo {
+            pci_vendor_id: 0x1033,
+            pci_device_id: 0x00e0,
+            pci_revision: 4,
+            number_of_root_ports: 5,
+            controller_flavor: 2000,
+        };
+        assert_eq!(
+            HostController::detect_flavor(&nec, &Some(info), r"USB\ROOT_HUB"),
+            ControllerFlavor::Ehci
+        );
+
+        // USB_HcGeneric of xHCI, only the root hub tells
+        let generic = ControllerInfo {
+            controller_flavor: 0,
+            ..info
+        };
+        assert_eq!(
+            HostController::detect_flavor(&None, &Some(generic), r"USB\ROOT_HUB30\4&1"),
+            ControllerFlavor::Xhci
+        );
+        assert_eq!(
+            HostController::detect_flavor(&None, &None, r"USB\ROOT_HUB"),
+            ControllerFlavor::Unknown
+        );
+    }
+}
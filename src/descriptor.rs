@@ -1,8 +1,18 @@
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+use crate::composite;
+use crate::composite::DeviceFunction;
+use crate::connection::ConnectionInfoV2;
+use crate::connection::PortConnectorProperties;
+use crate::connection::UsbSpeed;
+use crate::controller::HostController;
+use crate::guid::Guid;
+use crate::property::PnpProperties;
+use crate::topology::{DeviceLocation, PhysicalConnector};
+
 #[allow(non_snake_case)]
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct UsbDeviceDescriptor {
     pub bLength: u8,
     pub bDescriptorType: u8,
@@ -59,21 +69,334 @@ impl fmt::Debug for UsbDeviceDescriptor {
     }
 }
 
+pub const USB_DEVICE_DESCRIPTOR_SIZE: usize = 18;
+
+impl UsbDeviceDescriptor {
+    /// Decodes a raw device descriptor, strings are not requested
+    pub fn from_bytes(data: &[u8]) -> Result<UsbDeviceDescriptor, String> {
+        if data.len() < USB_DEVICE_DESCRIPTOR_SIZE {
+            return Err(format!(
+                "Device descriptor is {} bytes, expected {}",
+                data.len(),
+                USB_DEVICE_DESCRIPTOR_SIZE
+            ));
+        }
+        let word = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        Ok(UsbDeviceDescriptor {
+            bLength: data[0],
+            bDescriptorType: data[1],
+            bcdUSB: word(2),
+            bDeviceClass: data[4],
+            bDeviceSubClass: data[5],
+            bDeviceProtocol: data[6],
+            bMaxPacketSize0: data[7],
+            idVendor: word(8),
+            idProduct: word(10),
+            bcdDevice: word(12),
+            iManufacturer: (data[14], None),
+            iProduct: (data[15], None),
+            iSerialNumber: (data[16], None),
+            bNumConfigurations: data[17],
+        })
+    }
+}
+
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Device {
-    pub container_id: String,
+    /// None if the device node could not be found
+    pub container_id: Option<Guid>,
     pub driver_key_name: String,
     pub port_number: u8,
+    pub speed: Option<UsbSpeed>,
+    pub connection: Option<ConnectionInfoV2>,
+    /// The device is SuperSpeed capable but connected at a lower speed
+    pub speed_downgrade: bool,
+    pub location: Option<DeviceLocation>,
+    /// Socket the device is plugged into, with the companion port of USB 3 connectors
+    pub connector: Option<PhysicalConnector>,
+    /// Properties of the device node, None if it could not be found
+    pub pnp: Option<PnpProperties>,
     pub descriptor: UsbDeviceDescriptor,
+    /// Active configuration, the first one if the device is not configured.
+    /// None if the device did not answer the request.
+    pub configuration: Option<UsbConfigurationDescriptor>,
+    /// Only requested from devices with bcdUSB >= 0x0201
+    pub bos: Option<UsbBosDescriptor>,
+    /// Functions of a composite device and the child device nodes bound to them
+    pub functions: Vec<DeviceFunction>,
+    /// The device has a serial number, but Windows derived the instance ID from the port
+    pub serial_ignored: bool,
+    /// HID report descriptors by interface number, only known from captures
+    pub hid_reports: Vec<(u8, Vec<u8>)>,
+}
+
+impl Device {
+    /// Device known only by its descriptors, e.g. from a report of another system.
+    /// The composite functions are derived from the configuration.
+    pub fn from_descriptors(
+        port_number: u8,
+        speed: Option<UsbSpeed>,
+        descriptor: UsbDeviceDescriptor,
+        configuration: Option<UsbConfigurationDescriptor>,
+    ) -> Device {
+        let functions = match &configuration {
+            Some(config) if composite::is_composite(&descriptor, config) => {
+                composite::device_functions(config)
+            }
+            _ => Vec::new(),
+        };
+        Device {
+            container_id: None,
+            driver_key_name: String::new(),
+            port_number,
+            speed,
+            connection: None,
+            speed_downgrade: false,
+            location: None,
+            connector: None,
+            pnp: None,
+            descriptor,
+            configuration,
+            bos: None,
+            functions,
+            serial_ignored: false,
+            hid_reports: Vec::new(),
+        }
+    }
 }
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Hub {
     pub hub_id: String,
     pub number_of_ports: u8,
     pub devices: Vec<Device>,
+    pub ports: Vec<PortConnectorProperties>,
     pub parent_hub: Option<String>,
+    /// Port of the parent hub this hub is connected to
+    pub parent_port: Option<u8>,
+    /// Only set for root hubs
+    pub host_controller: Option<HostController>,
     pub descriptor: Option<UsbDeviceDescriptor>,
 }
+
+#[allow(non_snake_case)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default)]
+pub struct UsbEndpointDescriptor {
+    pub bLength: u8,
+    pub bDescriptorType: u8,
+    pub bEndpointAddress: u8,
+    pub bmAttributes: u8,
+    pub wMaxPacketSize: u16,
+    pub bInterval: u8,
+}
+
+#[allow(non_snake_case)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default)]
+pub struct UsbInterfaceDescriptor {
+    pub bLength: u8,
+    pub bDescriptorType: u8,
+    pub bInterfaceNumber: u8,
+    pub bAlternateSetting: u8,
+    pub bNumEndpoints: u8,
+    pub bInterfaceClass: u8,
+    pub bInterfaceSubClass: u8,
+    pub bInterfaceProtocol: u8,
+    pub iInterface: (u8, Option<String>),
+    pub endpoints: Vec<UsbEndpointDescriptor>,
+}
+
+#[allow(non_snake_case)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default)]
+pub struct UsbInterfaceAssociationDescriptor {
+    pub bLength: u8,
+    pub bDescriptorType: u8,
+    pub bFirstInterface: u8,
+    pub bInterfaceCount: u8,
+    pub bFunctionClass: u8,
+    pub bFunctionSubClass: u8,
+    pub bFunctionProtocol: u8,
+    pub iFunction: (u8, Option<String>),
+}
+
+/// Configuration descriptor with all interface, endpoint and interface association
+/// descriptors that follow it. Class specific descriptors are skipped.
+#[allow(non_snake_case)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default)]
+pub struct UsbConfigurationDescriptor {
+    pub bLength: u8,
+    pub bDescriptorType: u8,
+    pub wTotalLength: u16,
+    pub bNumInterfaces: u8,
+    pub bConfigurationValue: u8,
+    pub iConfiguration: (u8, Option<String>),
+    pub bmAttributes: u8,
+    pub bMaxPower: u8,
+    pub interfaces: Vec<UsbInterfaceDescriptor>,
+    pub associations: Vec<UsbInterfaceAssociationDescriptor>,
+}
+
+pub const USB_CONFIGURATION_DESCRIPTOR_TYPE: u8 = 0x02;
+pub const USB_INTERFACE_DESCRIPTOR_TYPE: u8 = 0x04;
+pub const USB_ENDPOINT_DESCRIPTOR_TYPE: u8 = 0x05;
+pub const USB_INTERFACE_ASSOCIATION_DESCRIPTOR_TYPE: u8 = 0x0B;
+
+impl UsbConfigurationDescriptor {
+    /// Parses the full configuration descriptor (wTotalLength bytes).
+    /// String indices are kept, the strings itself have to be requested separately.
+    pub fn from_bytes(buf: &[u8]) -> Result<UsbConfigurationDescriptor, String> {
+        if buf.len() < 9 || buf[1] != USB_CONFIGURATION_DESCRIPTOR_TYPE {
+            return Err("Not a configuration descriptor".to_string());
+        }
+        let mut config = UsbConfigurationDescriptor {
+            bLength: buf[0],
+            bDescriptorType: buf[1],
+            wTotalLength: u16::from_le_bytes([buf[2], buf[3]]),
+            bNumInterfaces: buf[4],
+            bConfigurationValue: buf[5],
+            iConfiguration: (buf[6], None),
+            bmAttributes: buf[7],
+            bMaxPower: buf[8],
+            interfaces: Vec::new(),
+            associations: Vec::new(),
+        };
+
+        let end = (config.wTotalLength as usize).min(buf.len());
+        let mut offset = buf[0] as usize;
+        while offset + 2 <= end {
+            let length = buf[offset] as usize;
+            if length < 2 || offset + length > end {
+                return Err(format!(
+                    "Invalid descriptor length {} at offset {}",
+                    length, offset
+                ));
+            }
+            let desc = &buf[offset..offset + length];
+            match desc[1] {
+                USB_INTERFACE_DESCRIPTOR_TYPE if length >= 9 => {
+                    config.interfaces.push(UsbInterfaceDescriptor {
+                        bLength: desc[0],
+                        bDescriptorType: desc[1],
+                        bInterfaceNumber: desc[2],
+                        bAlternateSetting: desc[3],
+                        bNumEndpoints: desc[4],
+                        bInterfaceClass: desc[5],
+                        bInterfaceSubClass: desc[6],
+                        bInterfaceProtocol: desc[7],
+                        iInterface: (desc[8], None),
+                        endpoints: Vec::new(),
+                    });
+                }
+                USB_ENDPOINT_DESCRIPTOR_TYPE if length >= 7 => {
+                    if let Some(interface) = config.interfaces.last_mut() {
+                        interface.endpoints.push(UsbEndpointDescriptor {
+                            bLength: desc[0],
+                            bDescriptorType: desc[1],
+                            bEndpointAddress: desc[2],
+                            bmAttributes: desc[3],
+                            wMaxPacketSize: u16::from_le_bytes([desc[4], desc[5]]),
+                            bInterval: desc[6],
+                        });
+                    }
+                }
+                USB_INTERFACE_ASSOCIATION_DESCRIPTOR_TYPE if length >= 8 => {
+                    config.associations.push(UsbInterfaceAssociationDescriptor {
+                        bLength: desc[0],
+                        bDescriptorType: desc[1],
+                        bFirstInterface: desc[2],
+                        bInterfaceCount: desc[3],
+                        bFunctionClass: desc[4],
+                        bFunctionSubClass: desc[5],
+                        bFunctionProtocol: desc[6],
+                        iFunction: (desc[7], None),
+                    });
+                }
+                _ => {} // Class or vendor specific
+            }
+            offset += length;
+        }
+        Ok(config)
+    }
+}
+
+pub const USB_BOS_DESCRIPTOR_TYPE: u8 = 0x0F;
+pub const USB_DEVICE_CAPABILITY_DESCRIPTOR_TYPE: u8 = 0x10;
+pub const USB_DEVICE_CAPABILITY_CONTAINER_ID: u8 = 0x04;
+
+/// Device capability descriptor, the capability specific part is kept as raw bytes
+#[allow(non_snake_case)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct UsbDeviceCapabilityDescriptor {
+    pub bLength: u8,
+    pub bDescriptorType: u8,
+    pub bDevCapabilityType: u8,
+    pub data: Vec<u8>,
+}
+
+/// Binary device Object Store, available for devices with bcdUSB >= 0x0201
+#[allow(non_snake_case)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct UsbBosDescriptor {
+    pub bLength: u8,
+    pub bDescriptorType: u8,
+    pub wTotalLength: u16,
+    pub bNumDeviceCaps: u8,
+    pub capabilities: Vec<UsbDeviceCapabilityDescriptor>,
+}
+
+impl UsbBosDescriptor {
+    /// Parses the full BOS descriptor (wTotalLength bytes)
+    pub fn from_bytes(buf: &[u8]) -> Result<UsbBosDescriptor, String> {
+        if buf.len() < 5 || buf[1] != USB_BOS_DESCRIPTOR_TYPE {
+            return Err("Not a BOS descriptor".to_string());
+        }
+        let mut bos = UsbBosDescriptor {
+            bLength: buf[0],
+            bDescriptorType: buf[1],
+            wTotalLength: u16::from_le_bytes([buf[2], buf[3]]),
+            bNumDeviceCaps: buf[4],
+            capabilities: Vec::new(),
+        };
+
+        let end = (bos.wTotalLength as usize).min(buf.len());
+        let mut offset = buf[0] as usize;
+        while offset + 3 <= end {
+            let length = buf[offset] as usize;
+            if length < 3 || offset + length > end {
+                return Err(format!(
+                    "Invalid descriptor length {} at offset {}",
+                    length, offset
+                ));
+            }
+            let desc = &buf[offset..offset + length];
+            if desc[1] == USB_DEVICE_CAPABILITY_DESCRIPTOR_TYPE {
+                bos.capabilities.push(UsbDeviceCapabilityDescriptor {
+                    bLength: desc[0],
+                    bDescriptorType: desc[1],
+                    bDevCapabilityType: desc[2],
+                    data: desc[3..].to_vec(),
+                });
+            }
+            offset += length;
+        }
+        Ok(bos)
+    }
+
+    /// ContainerID of the Container ID capability
+    pub fn container_id(&self) -> Option<Guid> {
+        let capability = self
+            .capabilities
+            .iter()
+            .find(|c| c.bDevCapabilityType == USB_DEVICE_CAPABILITY_CONTAINER_ID)?;
+        // bReserved followed by the 16 byte ContainerID
+        let data: &[u8; 16] = capability.data.get(1..17)?.try_into().ok()?;
+        Some(Guid::from_bytes(data))
+    }
+}
@@ -0,0 +1,476 @@
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::connection::UsbSpeed;
+use crate::descriptor::{Device, Hub};
+use crate::guid::Guid;
+use crate::topology::normalize_hub_id;
+
+/// Heuristic identity of a physical device across two enumerations
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DeviceIdentity {
+    /// Unique serial number, the device keeps its identity on any port
+    Serial { vid: u16, pid: u16, serial: String },
+    /// Container ID reported by the device (BOS) or generated by Windows
+    Container {
+        vid: u16,
+        pid: u16,
+        container_id: Guid,
+    },
+    /// Devices without serial number can only be recognized by their port
+    Location {
+        vid: u16,
+        pid: u16,
+        location: String,
+    },
+}
+
+impl fmt::Display for DeviceIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeviceIdentity::Serial { vid, pid, serial } => {
+                write!(f, "{:04x}:{:04x} serial {}", vid, pid, serial)
+            }
+            DeviceIdentity::Container {
+                vid,
+                pid,
+                container_id,
+            } => write!(f, "{:04x}:{:04x} container {}", vid, pid, container_id),
+            DeviceIdentity::Location { vid, pid, location } => {
+                write!(f, "{:04x}:{:04x} at {}", vid, pid, location)
+            }
+        }
+    }
+}
+
+/// Bus/port path if the topology was resolved, the hub and port otherwise
+pub fn device_position(hub: &Hub, device: &Device) -> String {
+    match &device.location {
+        Some(location) => location.bus_path.clone(),
+        None => format!("{}:{}", normalize_hub_id(&hub.hub_id), device.port_number),
+    }
+}
+
+/// Serial numbers Windows ignored are not unique and the container ID of the
+/// computer is shared by all built-in devices, both fall back to the location
+pub fn device_identity(hub: &Hub, device: &Device) -> DeviceIdentity {
+    let vid = device.descriptor.idVendor;
+    let pid = device.descriptor.idProduct;
+    match &device.descriptor.iSerialNumber.1 {
+        Some(serial) if !serial.is_empty() && !device.serial_ignored => {
+            return DeviceIdentity::Serial {
+                vid,
+                pid,
+                serial: serial.clone(),
+            }
+        }
+        _ => {}
+    }
+    match device.container_id {
+        Some(id) if id != Guid::LOCAL_MACHINE && !id.is_nil() => DeviceIdentity::Container {
+            vid,
+            pid,
+            container_id: id,
+        },
+        _ => DeviceIdentity::Location {
+            vid,
+            pid,
+            location: device_position(hub, device),
+        },
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangedField {
+    Manufacturer,
+    Product,
+    Serial,
+    BcdDevice,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: ChangedField,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub enum DeviceEvent {
+    Attached {
+        identity: DeviceIdentity,
+        position: String,
+        device: Device,
+    },
+    Detached {
+        identity: DeviceIdentity,
+        position: String,
+        device: Device,
+    },
+    Moved {
+        identity: DeviceIdentity,
+        from: String,
+        to: String,
+        device: Device,
+    },
+    /// Re-enumerated at a different speed, e.g. a SuperSpeed device fell back to USB 2
+    SpeedChanged {
+        identity: DeviceIdentity,
+        position: String,
+        before: Option<UsbSpeed>,
+        after: Option<UsbSpeed>,
+    },
+    /// Strings or bcdDevice changed, e.g. after a firmware update
+    Changed {
+        identity: DeviceIdentity,
+        position: String,
+        changes: Vec<FieldChange>,
+    },
+}
+
+impl DeviceEvent {
+    pub fn identity(&self) -> &DeviceIdentity {
+        match self {
+            DeviceEvent::Attached { identity, .. }
+            | DeviceEvent::Detached { identity, .. }
+            | DeviceEvent::Moved { identity, .. }
+            | DeviceEvent::SpeedChanged { identity, .. }
+            | DeviceEvent::Changed { identity, .. } => identity,
+        }
+    }
+}
+
+struct Entry<'a> {
+    identity: DeviceIdentity,
+    position: String,
+    device: &'a Device,
+}
+
+fn entries(hubs: &[Hub]) -> Vec<Entry<'_>> {
+    hubs.iter()
+        .flat_map(|hub| {
+            hub.devices.iter().map(move |device| Entry {
+                identity: device_identity(hub, device),
+                position: device_position(hub, device),
+                device,
+            })
+        })
+        .collect()
+}
+
+fn string_change(
+    field: ChangedField,
+    before: &(u8, Option<String>),
+    after: &(u8, Option<String>),
+) -> Option<FieldChange> {
+    if before.1 == after.1 {
+        return None;
+    }
+    Some(FieldChange {
+        field,
+        before: before.1.clone(),
+        after: after.1.clone(),
+    })
+}
+
+fn field_changes(before: &Device, after: &Device) -> Vec<FieldChange> {
+    let (b, a) = (&before.descriptor, &after.descriptor);
+    let mut changes: Vec<FieldChange> = [
+        string_change(
+            ChangedField::Manufacturer,
+            &b.iManufacturer,
+            &a.iManufacturer,
+        ),
+        string_change(ChangedField::Product, &b.iProduct, &a.iProduct),
+        string_change(ChangedField::Serial, &b.iSerialNumber, &a.iSerialNumber),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if b.bcdDevice != a.bcdDevice {
+        changes.push(FieldChange {
+            field: ChangedField::BcdDevice,
+            before: Some(format!("{:04x}", b.bcdDevice)),
+            after: Some(format!("{:04x}", a.bcdDevice)),
+        });
+    }
+    changes
+}
+
+/// Compares two enumeration results. Detached devices are reported first,
+/// then the events of devices present in both, then attached devices,
+/// each in enumeration order.
+pub fn diff(before: &[Hub], after: &[Hub]) -> Vec<DeviceEvent> {
+    let before = entries(before);
+    let after = entries(after);
+    let mut matched_before = vec![false; before.len()];
+    let mut pairs: Vec<(usize, usize)> = Vec::new();
+    let mut attached: Vec<usize> = Vec::new();
+
+    for (a, entry) in after.iter().enumerate() {
+        // Prefer the same position if several devices share the identity
+        let candidates = || {
+            before
+                .iter()
+                .enumerate()
+                .filter(|(b, e)| !matched_before[*b] && e.identity == entry.identity)
+        };
+        let found = candidates()
+            .find(|(_, e)| e.position == entry.position)
+            .or_else(|| candidates().next())
+            .map(|(b, _)| b);
+        match found {
+            Some(b) => {
+                matched_before[b] = true;
+                pairs.push((b, a));
+            }
+            None => attached.push(a),
+        }
+    }
+
+    let mut events: Vec<DeviceEvent> = Vec::new();
+    for (b, entry) in before.iter().enumerate() {
+        if !matched_before[b] {
+            events.push(DeviceEvent::Detached {
+                identity: entry.identity.clone(),
+                position: entry.position.clone(),
+                device: entry.device.clone(),
+            });
+        }
+    }
+    for (b, a) in pairs {
+        let (old, new) = (&before[b], &after[a]);
+        if old.position != new.position {
+            events.push(DeviceEvent::Moved {
+                identity: new.identity.clone(),
+                from: old.position.clone(),
+                to: new.position.clone(),
+                device: new.device.clone(),
+            });
+        }
+        if old.device.speed != new.device.speed {
+            events.push(DeviceEvent::SpeedChanged {
+                identity: new.identity.clone(),
+                position: new.position.clone(),
+                before: old.device.speed,
+                after: new.device.speed,
+            });
+        }
+        let changes = field_changes(old.device, new.device);
+        if !changes.is_empty() {
+            events.push(DeviceEvent::Changed {
+                identity: new.identity.clone(),
+                position: new.position.clone(),
+                changes,
+            });
+        }
+    }
+    for a in attached {
+        let entry = &after[a];
+        events.push(DeviceEvent::Attached {
+            identity: entry.identity.clone(),
+            position: entry.position.clone(),
+            device: entry.device.clone(),
+        });
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::UsbDeviceDescriptor;
+    use crate::topology::DeviceLocation;
+
+    fn device(path: &str, vid: u16, pid: u16, serial: Option<&str>) -> Device {
+        let descriptor = UsbDeviceDescriptor {
+            idVendor: vid,
+            idProduct: pid,
+            bcdDevice: 0x0100,
+            iProduct: (2, Some("Device".to_string())),
+            iSerialNumber: (3, serial.map(String::from)),
+            ..Default::default()
+        };
+        let port_number = path.rsplit(['-', '.']).next().unwrap().parse().unwrap();
+        let mut device =
+            Device::from_descriptors(port_number, Some(UsbSpeed::High), descriptor, None);
+        device.location = Some(DeviceLocation {
+            bus_path: path.to_string(),
+            location_path: None,
+            location_info: String::new(),
+        });
+        device
+    }
+
+    fn snapshot(devices: Vec<Device>) -> Vec<Hub> {
+        vec![Hub {
+            hub_id: "root".to_string(),
+            number_of_ports: 4,
+            devices,
+            ports: Vec::new(),
+            parent_hub: None,
+            parent_port: None,
+            host_controller: None,
+            descriptor: None,
+        }]
+    }
+
+    fn summary(events: &[DeviceEvent]) -> Vec<String> {
+        events
+            .iter()
+            .map(|event| match event {
+                DeviceEvent::Attached {
+                    identity, position, ..
+                } => format!("attached {} ({})", identity, position),
+                DeviceEvent::Detached {
+                    identity, position, ..
+                } => format!("detached {} ({})", identity, position),
+                DeviceEvent::Moved {
+                    identity, from, to, ..
+                } => {
+                    format!("moved {} from {} to {}", identity, from, to)
+                }
+                DeviceEvent::SpeedChanged {
+                    identity,
+                    before,
+                    after,
+                    ..
+                } => format!("speed {} {:?} -> {:?}", identity, before, after),
+                DeviceEvent::Changed {
+                    identity, changes, ..
+                } => format!("changed {} {:?}", identity, changes),
+            })
+            .collect()
+    }
+
+    fn keyboard() -> Device {
+        device("1-2", 0x046d, 0xc31c, None)
+    }
+
+    fn drive(path: &str) -> Device {
+        device(path, 0x0781, 0x5583, Some("4C530001"))
+    }
+
+    #[test]
+    fn identical_snapshots_have_no_events() {
+        let before = snapshot(vec![keyboard(), drive("1-3")]);
+        assert!(diff(&before, &before.clone()).is_empty());
+    }
+
+    #[test]
+    fn reports_added_and_removed_devices() {
+        let before = snapshot(vec![keyboard(), drive("1-3")]);
+        let after = snapshot(vec![
+            drive("1-3"),
+            device("1-4", 0x0483, 0xdf11, Some("AB")),
+        ]);
+        assert_eq!(
+            summary(&diff(&before, &after)),
+            vec![
+                "detached 046d:c31c at 1-2 (1-2)",
+                "attached 0483:df11 serial AB (1-4)",
+            ]
+        );
+    }
+
+    #[test]
+    fn devices_with_serial_keep_their_identity_when_moved() {
+        let before = snapshot(vec![keyboard(), drive("1-3")]);
+        let after = snapshot(vec![keyboard(), drive("1-4.1")]);
+        assert_eq!(
+            summary(&diff(&before, &after)),
+            vec!["moved 0781:5583 serial 4C530001 from 1-3 to 1-4.1"]
+        );
+    }
+
+    #[test]
+    fn devices_without_serial_are_replugged_when_moved() {
+        let before = snapshot(vec![keyboard()]);
+        let after = snapshot(vec![device("1-4", 0x046d, 0xc31c, None)]);
+        assert_eq!(
+            summary(&diff(&before, &after)),
+            vec![
+                "detached 046d:c31c at 1-2 (1-2)",
+                "attached 046d:c31c at 1-4 (1-4)",
+            ]
+        );
+
+        // Unless Windows generated a container ID for them
+        let container: Guid = "{8b3c4f1e-0a57-5d2d-b3c1-6c2f1a0e9d11}".parse().unwrap();
+        let mut before = keyboard();
+        before.container_id = Some(container);
+        let mut after = device("1-4", 0x046d, 0xc31c, None);
+        after.container_id = Some(container);
+        assert_eq!(
+            summary(&diff(&snapshot(vec![before]), &snapshot(vec![after]))),
+            vec![format!(
+                "moved 046d:c31c container {} from 1-2 to 1-4",
+                container
+            )]
+        );
+    }
+
+    #[test]
+    fn ignored_serials_and_the_machine_container_fall_back_to_the_location() {
+        let mut device = drive("1-3");
+        device.serial_ignored = true;
+        device.container_id = Some("{00000000-0000-0000-ffff-ffffffffffff}".parse().unwrap());
+        let hubs = snapshot(vec![device.clone()]);
+        assert_eq!(
+            device_identity(&hubs[0], &device).to_string(),
+            "0781:5583 at 1-3"
+        );
+        device.location = None;
+        assert_eq!(device_position(&hubs[0], &device), "root:3");
+    }
+
+    #[test]
+    fn reports_changed_descriptors_and_speed() {
+        let before = snapshot(vec![drive("1-3")]);
+        let mut updated = drive("1-3");
+        updated.descriptor.bcdDevice = 0x0110;
+        updated.descriptor.iProduct.1 = Some("Device (DFU)".to_string());
+        updated.speed = Some(UsbSpeed::Super);
+        let events = diff(&before, &snapshot(vec![updated]));
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            summary(&events[..1]),
+            vec!["speed 0781:5583 serial 4C530001 Some(High) -> Some(Super)"]
+        );
+        match &events[1] {
+            DeviceEvent::Changed { changes, .. } => assert_eq!(
+                changes,
+                &vec![
+                    FieldChange {
+                        field: ChangedField::Product,
+                        before: Some("Device".to_string()),
+                        after: Some("Device (DFU)".to_string()),
+                    },
+                    FieldChange {
+                        field: ChangedField::BcdDevice,
+                        before: Some("0100".to_string()),
+                        after: Some("0110".to_string()),
+                    },
+                ]
+            ),
+            event => panic!("Unexpected {:?}", event),
+        }
+    }
+
+    #[test]
+    fn duplicate_identities_prefer_the_same_position() {
+        // Two adapters with the same serial, the second one is removed
+        let before = snapshot(vec![drive("1-2"), drive("1-3")]);
+        let after = snapshot(vec![drive("1-3")]);
+        assert_eq!(
+            summary(&diff(&before, &after)),
+            vec!["detached 0781:5583 serial 4C530001 (1-2)"]
+        );
+    }
+}
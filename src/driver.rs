@@ -1,111 +1,165 @@
+use std::collections::HashMap;
 use std::mem;
 
 use windows::Win32::Devices::DeviceAndDriverInstallation::HDEVINFO;
 use windows::{
-    core::GUID,
+    core::PCWSTR,
     Win32::{
         Devices::{
             DeviceAndDriverInstallation::{
-                SetupDiEnumDeviceInfo, SetupDiGetClassDevsW, SetupDiGetDevicePropertyW,
-                DIGCF_ALLCLASSES, DIGCF_PRESENT, SP_DEVINFO_DATA,
+                SetupDiDestroyDeviceInfoList, SetupDiEnumDeviceInfo, SetupDiGetClassDevsW,
+                SetupDiGetDevicePropertyW, DIGCF_ALLCLASSES, DIGCF_PRESENT, SP_DEVINFO_DATA,
             },
             Properties::{
-                DEVPKEY_Device_ContainerId, DEVPKEY_Device_Driver, DEVPROPTYPE, DEVPROP_TYPE_GUID,
+                DEVPKEY_Device_CompatibleIds, DEVPKEY_Device_ContainerId,
+                DEVPKEY_Device_DeviceDesc, DEVPKEY_Device_Driver, DEVPKEY_Device_DriverDate,
+                DEVPKEY_Device_DriverInfPath, DEVPKEY_Device_DriverProvider,
+                DEVPKEY_Device_DriverVersion, DEVPKEY_Device_FriendlyName,
+                DEVPKEY_Device_HardwareIds, DEVPKEY_Device_InstanceId, DEVPKEY_Device_LocationInfo,
+                DEVPKEY_Device_LocationPaths, DEVPKEY_Device_Parent, DEVPKEY_Device_ProblemCode,
+                DEVPKEY_Device_Service, DEVPROPKEY, DEVPROPTYPE,
             },
         },
-        Foundation::{GetLastError, BOOL, ERROR_NO_MORE_ITEMS, MAX_PATH},
+        Foundation::{GetLastError, BOOL, ERROR_NO_MORE_ITEMS},
     },
 };
 
+use crate::backend::Backend;
 use crate::helper::get_error;
+use crate::property::{
+    decode_property, filetime_to_date, DeviceProperties, PnpProperties, PropertyValue,
+};
 
-fn get_driver_id(
+fn get_property(
     device_info_set: HDEVINFO,
     dev_info_data: &SP_DEVINFO_DATA,
-) -> Result<String, String> {
-    let mut data_type: DEVPROPTYPE = DEVPROP_TYPE_GUID;
-    let mut data: Vec<u8> = vec![0u8; MAX_PATH as usize];
-    let buffer: Option<&mut [u8]> = Some(&mut data[..]);
-    let ptr: *mut u32 = 0 as *mut u32;
-    let reqsize: Option<*mut u32> = Some(ptr);
+    key: &DEVPROPKEY,
+) -> Result<PropertyValue, String> {
+    let mut data_type = DEVPROPTYPE::default();
+    let mut reqsize: u32 = 0;
 
-    let result = unsafe {
+    // Ask for the size first, lists and descriptions are often longer than MAX_PATH
+    unsafe {
         SetupDiGetDevicePropertyW(
             device_info_set,
             dev_info_data,
-            &DEVPKEY_Device_Driver,
+            key,
             &mut data_type,
-            buffer,
-            reqsize,
+            None,
+            Some(&mut reqsize),
             0,
         )
     };
-
-    if result == BOOL(0) {
-        //unsafe { SetupDiDestroyDeviceInfoList(device_info_set) };
+    if reqsize == 0 {
         return Err(get_error());
-    } else {
-        let mut vec_u16: Vec<u16> = Vec::new();
-        let mut i = 0;
-        while i < data.len() {
-            vec_u16.push((data[i + 1] as u16) << 8 | data[i] as u16);
-            i += 2; // Convert to u16 manually because it is utf16
-        }
-        let b = String::from_utf16_lossy(&vec_u16);
-        let b = b.trim_end_matches('\0');
-        Ok(b.to_string())
     }
-}
-
-fn get_container_id(
-    device_info_set: HDEVINFO,
-    dev_info_data: &SP_DEVINFO_DATA,
-) -> Result<String, String> {
-    let mut data_type: DEVPROPTYPE = DEVPROP_TYPE_GUID;
-    let mut data: Vec<u8> = vec![0u8; MAX_PATH as usize];
-    let buffer: Option<&mut [u8]> = Some(&mut data[..]);
-    let ptr: *mut u32 = 0 as *mut u32;
-    let reqsize: Option<*mut u32> = Some(ptr);
-
+    let mut data: Vec<u8> = vec![0u8; reqsize as usize];
     let result = unsafe {
         SetupDiGetDevicePropertyW(
             device_info_set,
             dev_info_data,
-            &DEVPKEY_Device_ContainerId,
+            key,
             &mut data_type,
-            buffer,
-            reqsize,
+            Some(&mut data[..]),
+            None,
             0,
         )
     };
 
     if result == BOOL(0) {
-        //unsafe { SetupDiDestroyDeviceInfoList(device_info_set) };
-        return Err(get_error());
+        Err(get_error())
     } else {
-        let data1 = u32::from_le_bytes(data[0..4].try_into().unwrap());
-        let data2 = u16::from_le_bytes(data[4..6].try_into().unwrap());
-        let data3 = u16::from_le_bytes(data[6..8].try_into().unwrap());
-        let mut data4: [u8; 8] = [0; 8];
-        data4.copy_from_slice(&data[8..16]);
-
-        let x = GUID::from_values(data1, data2, data3, data4);
-        let x = format!("{{{:?}}}", x).to_lowercase();
-        //println!("GUID {}", x);
-        return Ok(x);
+        decode_property(data_type.0, &data)
     }
 }
 
-pub(crate) fn get_all_ids() -> Result<Vec<(String, String)>, String> {
-    let devices = unsafe {
-        SetupDiGetClassDevsW(
-            None,
-            windows::w!("USB"),
-            None,
-            DIGCF_PRESENT | DIGCF_ALLCLASSES,
-        )
+fn get_string(
+    device_info_set: HDEVINFO,
+    dev_info_data: &SP_DEVINFO_DATA,
+    key: &DEVPROPKEY,
+) -> Option<String> {
+    match get_property(device_info_set, dev_info_data, key) {
+        Ok(value) => value.into_string(),
+        Err(_err) => None,
+    }
+}
+
+fn get_string_list(
+    device_info_set: HDEVINFO,
+    dev_info_data: &SP_DEVINFO_DATA,
+    key: &DEVPROPKEY,
+) -> Vec<String> {
+    match get_property(device_info_set, dev_info_data, key) {
+        Ok(value) => value.into_string_list().unwrap_or_default(),
+        Err(_err) => Vec::new(),
+    }
+}
+
+fn get_driver_id(
+    device_info_set: HDEVINFO,
+    dev_info_data: &SP_DEVINFO_DATA,
+) -> Result<String, String> {
+    match get_property(device_info_set, dev_info_data, &DEVPKEY_Device_Driver)?.into_string() {
+        Some(driver_key) => Ok(driver_key),
+        None => Err("Driver key is not a string".to_string()),
+    }
+}
+
+fn get_pnp_properties(device_info_set: HDEVINFO, dev_info_data: &SP_DEVINFO_DATA) -> PnpProperties {
+    let problem_code =
+        match get_property(device_info_set, dev_info_data, &DEVPKEY_Device_ProblemCode) {
+            Ok(value) => value.as_u32().filter(|code| *code != 0),
+            Err(_err) => None,
+        };
+    let driver_date = match get_property(device_info_set, dev_info_data, &DEVPKEY_Device_DriverDate)
+    {
+        Ok(value) => value.as_filetime().map(filetime_to_date),
+        Err(_err) => None,
     };
-    let mut results = Vec::new();
+    PnpProperties {
+        instance_id: get_string(device_info_set, dev_info_data, &DEVPKEY_Device_InstanceId),
+        friendly_name: get_string(device_info_set, dev_info_data, &DEVPKEY_Device_FriendlyName),
+        device_description: get_string(device_info_set, dev_info_data, &DEVPKEY_Device_DeviceDesc),
+        hardware_ids: get_string_list(device_info_set, dev_info_data, &DEVPKEY_Device_HardwareIds),
+        compatible_ids: get_string_list(
+            device_info_set,
+            dev_info_data,
+            &DEVPKEY_Device_CompatibleIds,
+        ),
+        service: get_string(device_info_set, dev_info_data, &DEVPKEY_Device_Service),
+        driver_provider: get_string(
+            device_info_set,
+            dev_info_data,
+            &DEVPKEY_Device_DriverProvider,
+        ),
+        driver_version: get_string(
+            device_info_set,
+            dev_info_data,
+            &DEVPKEY_Device_DriverVersion,
+        ),
+        driver_date,
+        inf_name: get_string(
+            device_info_set,
+            dev_info_data,
+            &DEVPKEY_Device_DriverInfPath,
+        ),
+        problem_code,
+        location_info: get_string(device_info_set, dev_info_data, &DEVPKEY_Device_LocationInfo),
+        location_paths: get_string_list(
+            device_info_set,
+            dev_info_data,
+            &DEVPKEY_Device_LocationPaths,
+        ),
+    }
+}
+
+/// Calls `f` for every present device of the enumerator until it returns false
+fn for_each_device<F>(enumerator: PCWSTR, mut f: F) -> Result<(), String>
+where
+    F: FnMut(HDEVINFO, &SP_DEVINFO_DATA) -> bool,
+{
+    let devices =
+        unsafe { SetupDiGetClassDevsW(None, enumerator, None, DIGCF_PRESENT | DIGCF_ALLCLASSES) };
     match devices {
         Ok(device_info_set) => {
             if device_info_set.is_invalid() {
@@ -119,35 +173,113 @@ pub(crate) fn get_all_ids() -> Result<Vec<(String, String)>, String> {
                 dev_info_data.cbSize = mem::size_of::<SP_DEVINFO_DATA>() as u32;
                 let result =
                     unsafe { SetupDiEnumDeviceInfo(device_info_set, index, &mut dev_info_data) };
+                index += 1;
                 if result == BOOL(0) {
                     let x = unsafe { GetLastError() };
                     if x == ERROR_NO_MORE_ITEMS {
                         break;
                     }
-                    //println!("Error {:?}", x);
                     continue;
                 }
-                match get_driver_id(device_info_set, &dev_info_data) {
-                    Ok(guid) => {
-                        match get_container_id(device_info_set, &dev_info_data) {
-                            Ok(cguid) => {
-                                results.push((guid, cguid));
-                            }
-                            Err(_err) => {
-                                //println!("{}", err)
-                            }
-                        };
-                    }
-                    Err(_err) => {
-                        //println!("{}", err)
-                    }
-                };
-                index += 1;
+                if !f(device_info_set, &dev_info_data) {
+                    break;
+                }
+            }
+            unsafe { SetupDiDestroyDeviceInfoList(device_info_set) };
+            Ok(())
+        }
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Looks up the hardware IDs and location paths of a PCI device (e.g. a host controller) by its driver key
+pub(crate) fn get_pci_device_ids(driver_key: &str) -> Result<(Vec<String>, Vec<String>), String> {
+    let mut result = Err(format!("No PCI device with driver key {}", driver_key));
+    for_each_device(
+        windows::w!("PCI"),
+        |device_info_set, dev_info_data| match get_driver_id(device_info_set, dev_info_data) {
+            Ok(key) if key.eq_ignore_ascii_case(driver_key) => {
+                let hardware_ids =
+                    get_string_list(device_info_set, dev_info_data, &DEVPKEY_Device_HardwareIds);
+                let location_paths = get_string_list(
+                    device_info_set,
+                    dev_info_data,
+                    &DEVPKEY_Device_LocationPaths,
+                );
+                result = Ok((hardware_ids, location_paths));
+                false
             }
+            _ => true,
+        },
+    )?;
+    result
+}
+
+/// Reads the properties of all present USB devnodes
+pub(crate) fn get_device_properties() -> Result<Vec<DeviceProperties>, String> {
+    let mut devices = Vec::new();
+    for_each_device(windows::w!("USB"), |device_info_set, dev_info_data| {
+        // Devnodes without a driver (e.g. failed installs) are kept, they can still be children of a composite device
+        devices.push(DeviceProperties {
+            driver_key: get_driver_id(device_info_set, dev_info_data).ok(),
+            parent: get_string(device_info_set, dev_info_data, &DEVPKEY_Device_Parent),
+            container_id: match get_property(
+                device_info_set,
+                dev_info_data,
+                &DEVPKEY_Device_ContainerId,
+            ) {
+                Ok(value) => value.as_guid(),
+                Err(_err) => None,
+            },
+            pnp: get_pnp_properties(device_info_set, dev_info_data),
+        });
+        true
+    })?;
+    Ok(devices)
+}
+
+/// All present USB devnodes, looked up by their driver key name or parent.
+/// Building it is a full SetupDi scan, so it is done once and shared by all hubs
+/// (`cargo bench --bench enumeration` compares it with a scan per device).
+#[derive(Debug, Default)]
+pub(crate) struct DeviceIndex {
+    devices: Vec<DeviceProperties>,
+    by_driver_key: HashMap<String, usize>,
+}
+
+impl DeviceIndex {
+    pub(crate) fn build(backend: &dyn Backend) -> Result<DeviceIndex, String> {
+        let mut index = DeviceIndex::default();
+        for properties in backend.device_properties()? {
+            index.insert(properties);
         }
-        Err(_err) => {
-            //println!("{:?}", err);
+        Ok(index)
+    }
+
+    pub(crate) fn insert(&mut self, properties: DeviceProperties) {
+        if let Some(driver_key) = &properties.driver_key {
+            self.by_driver_key
+                .insert(driver_key.to_lowercase(), self.devices.len());
         }
+        self.devices.push(properties);
+    }
+
+    pub(crate) fn get(&self, driver_key: &str) -> Option<&DeviceProperties> {
+        let index = self.by_driver_key.get(&driver_key.to_lowercase())?;
+        self.devices.get(*index)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &DeviceProperties> {
+        self.devices.iter()
+    }
+
+    pub(crate) fn children(&self, instance_id: &str) -> Vec<&DeviceProperties> {
+        self.devices
+            .iter()
+            .filter(|d| match &d.parent {
+                Some(parent) => parent.eq_ignore_ascii_case(instance_id),
+                None => false,
+            })
+            .collect()
     }
-    return Ok(results);
 }
@@ -0,0 +1,187 @@
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A GUID in the Windows layout, formatted as `{xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx}` in lower case
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Guid {
+    pub data1: u32,
+    pub data2: u16,
+    pub data3: u16,
+    pub data4: [u8; 8],
+}
+
+impl Guid {
+    /// Container of all devices built into the computer
+    pub const LOCAL_MACHINE: Guid = Guid {
+        data1: 0,
+        data2: 0,
+        data3: 0,
+        data4: [0xff; 8],
+    };
+
+    /// Decodes the in memory layout of a GUID struct, which is also used by the
+    /// Container ID capability of the BOS descriptor
+    pub fn from_bytes(data: &[u8; 16]) -> Guid {
+        let mut data4: [u8; 8] = [0; 8];
+        data4.copy_from_slice(&data[8..16]);
+        Guid {
+            data1: u32::from_le_bytes(data[0..4].try_into().unwrap()),
+            data2: u16::from_le_bytes(data[4..6].try_into().unwrap()),
+            data3: u16::from_le_bytes(data[6..8].try_into().unwrap()),
+            data4,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut data = [0u8; 16];
+        data[0..4].copy_from_slice(&self.data1.to_le_bytes());
+        data[4..6].copy_from_slice(&self.data2.to_le_bytes());
+        data[6..8].copy_from_slice(&self.data3.to_le_bytes());
+        data[8..16].copy_from_slice(&self.data4);
+        data
+    }
+
+    pub fn is_nil(&self) -> bool {
+        *self == Guid::default()
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{{{:08x}-{:04x}-{:04x}-{:02x}{:02x}-",
+            self.data1, self.data2, self.data3, self.data4[0], self.data4[1]
+        )?;
+        for byte in &self.data4[2..] {
+            write!(f, "{:02x}", byte)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl FromStr for Guid {
+    type Err = String;
+
+    /// Accepts the GUID with or without braces in any case
+    fn from_str(value: &str) -> Result<Guid, String> {
+        let trimmed = value.trim();
+        let trimmed = trimmed
+            .strip_prefix('{')
+            .and_then(|v| v.strip_suffix('}'))
+            .unwrap_or(trimmed);
+        let groups: Vec<&str> = trimmed.split('-').collect();
+        let lengths = [8, 4, 4, 4, 12];
+        if groups.len() != 5
+            || groups
+                .iter()
+                .zip(lengths.iter())
+                .any(|(g, l)| g.len() != *l || !g.chars().all(|c| c.is_ascii_hexdigit()))
+        {
+            return Err(format!("Invalid GUID {}", value));
+        }
+        let hex = |s: &str| u64::from_str_radix(s, 16).unwrap();
+        let tail = format!("{}{}", groups[3], groups[4]);
+        let mut data4: [u8; 8] = [0; 8];
+        for (i, byte) in data4.iter_mut().enumerate() {
+            *byte = hex(&tail[i * 2..i * 2 + 2]) as u8;
+        }
+        Ok(Guid {
+            data1: hex(groups[0]) as u32,
+            data2: hex(groups[1]) as u16,
+            data3: hex(groups[2]) as u16,
+            data4,
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Guid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Guid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Guid, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Guid::from_str(&value).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYBOARD: &str = "{4d36e96b-e325-11ce-bfc1-08002be10318}";
+
+    #[test]
+    fn local_machine_container() {
+        let parsed: Guid = "{00000000-0000-0000-ffff-ffffffffffff}".parse().unwrap();
+        assert_eq!(parsed, Guid::LOCAL_MACHINE);
+        assert_eq!(
+            Guid::LOCAL_MACHINE.to_string(),
+            "{00000000-0000-0000-ffff-ffffffffffff}"
+        );
+        assert!(!Guid::LOCAL_MACHINE.is_nil());
+        assert!(Guid::default().is_nil());
+    }
+
+    #[test]
+    fn parses_and_formats() {
+        let guid: Guid = KEYBOARD.parse().unwrap();
+        assert_eq!(
+            guid,
+            Guid {
+                data1: 0x4d36e96b,
+                data2: 0xe325,
+                data3: 0x11ce,
+                data4: [0xbf, 0xc1, 0x08, 0x00, 0x2b, 0xe1, 0x03, 0x18],
+            }
+        );
+        assert_eq!(guid.to_string(), KEYBOARD);
+        assert_eq!(Guid::from_bytes(&guid.to_bytes()), guid);
+    }
+
+    #[test]
+    fn parses_mixed_case_without_braces() {
+        let expected: Guid = KEYBOARD.parse().unwrap();
+        for value in [
+            "4D36E96B-E325-11CE-BFC1-08002BE10318",
+            "{4D36e96b-E325-11ce-bFC1-08002Be10318}",
+            " 4d36e96b-e325-11ce-bfc1-08002be10318\n",
+        ] {
+            assert_eq!(value.parse::<Guid>(), Ok(expected), "{}", value);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_guids() {
+        for value in [
+            "",
+            "{}",
+            "4d36e96b-e325-11ce-bfc1",
+            "4d36e96b-e325-11ce-bfc1-08002be1031",
+            "4d36e96be325-11ce-bfc1-08002be10318",
+            "{4d36e96b-e325-11ce-bfc1-08002be1031g}",
+            "{4d36e96b-e325-11ce-bfc1-08002be10318",
+            "4d36e96b-e325-11ce-bf-c108002be10318",
+        ] {
+            assert!(value.parse::<Guid>().is_err(), "{}", value);
+        }
+    }
+
+    #[cfg(all(feature = "serde", feature = "serde_binary_example"))]
+    #[test]
+    fn serde_round_trip() {
+        let guid: Guid = KEYBOARD.parse().unwrap();
+        let json = serde_json::to_string(&guid).unwrap();
+        assert_eq!(json, format!("\"{}\"", KEYBOARD));
+        assert_eq!(serde_json::from_str::<Guid>(&json).unwrap(), guid);
+        assert!(serde_json::from_str::<Guid>("\"not a guid\"").is_err());
+    }
+}
@@ -0,0 +1,351 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::composite::{is_composite, DeviceFunction};
+use crate::descriptor::{Device, UsbConfigurationDescriptor, UsbDeviceDescriptor};
+
+// https://learn.microsoft.com/en-us/windows-hardware/drivers/install/standard-usb-identifiers
+
+/// Hardware and compatible IDs Windows builds for a device node, most specific first
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExpectedIds {
+    pub hardware_ids: Vec<String>,
+    pub compatible_ids: Vec<String>,
+}
+
+fn class_ids(prefix: &str, class: u8, subclass: u8, protocol: u8) -> Vec<String> {
+    vec![
+        format!(
+            r"USB\{}_{:02X}&SubClass_{:02X}&Prot_{:02X}",
+            prefix, class, subclass, protocol
+        ),
+        format!(r"USB\{}_{:02X}&SubClass_{:02X}", prefix, class, subclass),
+        format!(r"USB\{}_{:02X}", prefix, class),
+    ]
+}
+
+/// IDs of the device node of a USB device. The configuration is needed to tell
+/// composite devices apart and to use the interface class of single interface devices.
+pub fn device_ids(
+    desc: &UsbDeviceDescriptor,
+    config: Option<&UsbConfigurationDescriptor>,
+) -> ExpectedIds {
+    let hardware_ids = vec![
+        format!(
+            r"USB\VID_{:04X}&PID_{:04X}&REV_{:04X}",
+            desc.idVendor, desc.idProduct, desc.bcdDevice
+        ),
+        format!(r"USB\VID_{:04X}&PID_{:04X}", desc.idVendor, desc.idProduct),
+    ];
+
+    let composite = match config {
+        Some(config) => is_composite(desc, config),
+        None => false,
+    };
+    let first_interface =
+        config.and_then(|c| c.interfaces.iter().find(|i| i.bAlternateSetting == 0));
+
+    let compatible_ids = if composite {
+        let mut ids = class_ids(
+            "DevClass",
+            desc.bDeviceClass,
+            desc.bDeviceSubClass,
+            desc.bDeviceProtocol,
+        );
+        ids.push(r"USB\COMPOSITE".to_string());
+        ids
+    } else if desc.bDeviceClass == 0x00 {
+        // The class is defined by the (only) interface
+        match first_interface {
+            Some(interface) => class_ids(
+                "Class",
+                interface.bInterfaceClass,
+                interface.bInterfaceSubClass,
+                interface.bInterfaceProtocol,
+            ),
+            None => Vec::new(),
+        }
+    } else {
+        class_ids(
+            "Class",
+            desc.bDeviceClass,
+            desc.bDeviceSubClass,
+            desc.bDeviceProtocol,
+        )
+    };
+
+    ExpectedIds {
+        hardware_ids,
+        compatible_ids,
+    }
+}
+
+/// IDs of a child device node the generic parent driver creates for a function
+pub fn function_ids(desc: &UsbDeviceDescriptor, function: &DeviceFunction) -> ExpectedIds {
+    ExpectedIds {
+        hardware_ids: vec![
+            format!(
+                r"USB\VID_{:04X}&PID_{:04X}&REV_{:04X}&MI_{:02X}",
+                desc.idVendor, desc.idProduct, desc.bcdDevice, function.first_interface
+            ),
+            format!(
+                r"USB\VID_{:04X}&PID_{:04X}&MI_{:02X}",
+                desc.idVendor, desc.idProduct, function.first_interface
+            ),
+        ],
+        compatible_ids: class_ids(
+            "Class",
+            function.function_class,
+            function.function_subclass,
+            function.function_protocol,
+        ),
+    }
+}
+
+/// Difference between computed and reported IDs, compared case insensitive
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdComparison {
+    /// Computed but not reported by PnP
+    pub missing: Vec<String>,
+    /// Reported by PnP but not computed, e.g. IDs added by a filter driver
+    pub unexpected: Vec<String>,
+}
+
+impl IdComparison {
+    pub fn matches(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+pub fn compare_ids(expected: &[String], actual: &[String]) -> IdComparison {
+    let contains = |list: &[String], id: &String| list.iter().any(|i| i.eq_ignore_ascii_case(id));
+    IdComparison {
+        missing: expected
+            .iter()
+            .filter(|id| !contains(actual, id))
+            .cloned()
+            .collect(),
+        unexpected: actual
+            .iter()
+            .filter(|id| !contains(expected, id))
+            .cloned()
+            .collect(),
+    }
+}
+
+/// Result of comparing the IDs of a device node
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct IdReport {
+    /// First interface of the function, None for the device itself
+    pub interface: Option<u8>,
+    pub expected: ExpectedIds,
+    pub hardware_ids: IdComparison,
+    pub compatible_ids: IdComparison,
+}
+
+fn report(
+    interface: Option<u8>,
+    expected: ExpectedIds,
+    hardware: &[String],
+    compatible: &[String],
+) -> IdReport {
+    IdReport {
+        interface,
+        hardware_ids: compare_ids(&expected.hardware_ids, hardware),
+        compatible_ids: compare_ids(&expected.compatible_ids, compatible),
+        expected,
+    }
+}
+
+/// Compares the IDs of the device and all its functions against what PnP reported.
+/// Device nodes without PnP properties are skipped.
+pub fn compare_device_ids(device: &Device) -> Vec<IdReport> {
+    let mut results = Vec::new();
+    if let Some(pnp) = &device.pnp {
+        let expected = device_ids(&device.descriptor, device.configuration.as_ref());
+        results.push(report(
+            None,
+            expected,
+            &pnp.hardware_ids,
+            &pnp.compatible_ids,
+        ));
+    }
+    for function in &device.functions {
+        if let Some(pnp) = &function.pnp {
+            let expected = function_ids(&device.descriptor, function);
+            results.push(report(
+                Some(function.first_interface),
+                expected,
+                &pnp.hardware_ids,
+                &pnp.compatible_ids,
+            ));
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::property::PnpProperties;
+
+    fn descriptor(vendor_id: u16, product_id: u16, bcd_device: u16) -> UsbDeviceDescriptor {
+        UsbDeviceDescriptor {
+            idVendor: vendor_id,
+            idProduct: product_id,
+            bcdDevice: bcd_device,
+            bNumConfigurations: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Flash drive with one mass storage interface
+    fn storage_configuration() -> UsbConfigurationDescriptor {
+        let raw = [
+            9, 2, 32, 0, 1, 1, 0, 0x80, 100, // Configuration
+            9, 4, 0, 0, 2, 0x08, 0x06, 0x50, 0, // Mass storage
+            7, 5, 0x81, 2, 0, 2, 0, // Endpoint 1 IN
+            7, 5, 0x02, 2, 0, 2, 0, // Endpoint 2 OUT
+        ];
+        UsbConfigurationDescriptor::from_bytes(&raw).unwrap()
+    }
+
+    /// Keyboard receiver with a boot keyboard and a mouse interface
+    fn receiver_configuration() -> UsbConfigurationDescriptor {
+        let raw = [
+            9, 2, 41, 0, 2, 1, 0, 0xa0, 49, // Configuration
+            9, 4, 0, 0, 1, 0x03, 0x01, 0x01, 0, // Keyboard
+            7, 5, 0x81, 3, 8, 0, 8, // Endpoint 1 IN
+            9, 4, 1, 0, 1, 0x03, 0x01, 0x02, 0, // Mouse
+            7, 5, 0x82, 3, 8, 0, 2, // Endpoint 2 IN
+        ];
+        UsbConfigurationDescriptor::from_bytes(&raw).unwrap()
+    }
+
+    fn strings(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn ids_of_single_interface_device() {
+        let ids = device_ids(
+            &descriptor(0x0781, 0x5583, 0x0100),
+            Some(&storage_configuration()),
+        );
+        assert_eq!(
+            ids.hardware_ids,
+            strings(&[r"USB\VID_0781&PID_5583&REV_0100", r"USB\VID_0781&PID_5583"])
+        );
+        assert_eq!(
+            ids.compatible_ids,
+            strings(&[
+                r"USB\Class_08&SubClass_06&Prot_50",
+                r"USB\Class_08&SubClass_06",
+                r"USB\Class_08",
+            ])
+        );
+
+        // Without the configuration the interface class is unknown
+        let ids = device_ids(&descriptor(0x0781, 0x5583, 0x0100), None);
+        assert!(ids.compatible_ids.is_empty());
+    }
+
+    #[test]
+    fn ids_of_composite_device() {
+        let ids = device_ids(
+            &descriptor(0x046d, 0xc52b, 0x1211),
+            Some(&receiver_configuration()),
+        );
+        assert_eq!(
+            ids.hardware_ids,
+            strings(&[r"USB\VID_046D&PID_C52B&REV_1211", r"USB\VID_046D&PID_C52B"])
+        );
+        assert_eq!(
+            ids.compatible_ids,
+            strings(&[
+                r"USB\DevClass_00&SubClass_00&Prot_00",
+                r"USB\DevClass_00&SubClass_00",
+                r"USB\DevClass_00",
+                r"USB\COMPOSITE",
+            ])
+        );
+    }
+
+    #[test]
+    fn ids_of_function() {
+        let functions = crate::composite::device_functions(&receiver_configuration());
+        let ids = function_ids(&descriptor(0x046d, 0xc52b, 0x1211), &functions[1]);
+        assert_eq!(
+            ids.hardware_ids,
+            strings(&[
+                r"USB\VID_046D&PID_C52B&REV_1211&MI_01",
+                r"USB\VID_046D&PID_C52B&MI_01",
+            ])
+        );
+        assert_eq!(
+            ids.compatible_ids,
+            strings(&[
+                r"USB\Class_03&SubClass_01&Prot_02",
+                r"USB\Class_03&SubClass_01",
+                r"USB\Class_03",
+            ])
+        );
+    }
+
+    #[test]
+    fn compares_ids_case_insensitive() {
+        let expected = strings(&[r"USB\VID_0781&PID_5583&REV_0100", r"USB\VID_0781&PID_5583"]);
+        let same = compare_ids(
+            &expected,
+            &strings(&[r"USB\VID_0781&PID_5583&REV_0100", r"usb\vid_0781&pid_5583"]),
+        );
+        assert!(same.matches());
+
+        let comparison = compare_ids(
+            &expected,
+            &strings(&[r"USB\VID_0781&PID_5583", r"USBSTOR\GenDisk"]),
+        );
+        assert!(!comparison.matches());
+        assert_eq!(
+            comparison.missing,
+            strings(&[r"USB\VID_0781&PID_5583&REV_0100"])
+        );
+        assert_eq!(comparison.unexpected, strings(&[r"USBSTOR\GenDisk"]));
+    }
+
+    #[test]
+    fn compares_device_and_functions() {
+        let desc = descriptor(0x046d, 0xc52b, 0x1211);
+        let mut device = Device::from_descriptors(1, None, desc, Some(receiver_configuration()));
+        device.pnp = Some(PnpProperties {
+            hardware_ids: strings(&[r"USB\VID_046D&PID_C52B&REV_1211", r"USB\VID_046D&PID_C52B"]),
+            compatible_ids: strings(&[
+                r"USB\DevClass_00&SubClass_00&Prot_00",
+                r"USB\DevClass_00&SubClass_00",
+                r"USB\DevClass_00",
+                r"USB\COMPOSITE",
+            ]),
+            ..Default::default()
+        });
+        // The keyboard node has no compatible IDs, the mouse node has no device node
+        device.functions[0].pnp = Some(PnpProperties {
+            hardware_ids: strings(&[
+                r"USB\VID_046D&PID_C52B&REV_1211&MI_00",
+                r"USB\VID_046D&PID_C52B&MI_00",
+            ]),
+            ..Default::default()
+        });
+
+        let reports = compare_device_ids(&device);
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].interface, None);
+        assert!(reports[0].hardware_ids.matches() && reports[0].compatible_ids.matches());
+        assert_eq!(reports[1].interface, Some(0));
+        assert!(reports[1].hardware_ids.matches());
+        assert_eq!(reports[1].compatible_ids.missing.len(), 3);
+        assert!(reports[1].compatible_ids.unexpected.is_empty());
+    }
+}
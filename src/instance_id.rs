@@ -0,0 +1,194 @@
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::descriptor::Device;
+
+// https://learn.microsoft.com/en-us/windows-hardware/drivers/install/device-instance-ids
+
+/// Parsed device instance ID, e.g. `USB\VID_046D&PID_C52B&MI_02\7&1A2B3C4D&0&0002`
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstanceId {
+    /// `USB`
+    pub enumerator: String,
+    /// `VID_046D&PID_C52B&MI_02`
+    pub device_id: String,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub mi: Option<u8>,
+    pub rev: Option<u16>,
+    /// Serial number or an ID generated by the parent, e.g. `7&1A2B3C4D&0&0002`
+    pub instance: String,
+}
+
+fn hex_value(part: &str, key: &str, len: usize) -> Option<u32> {
+    let value = part.strip_prefix(key)?;
+    if value.len() != len {
+        return None;
+    }
+    u32::from_str_radix(value, 16).ok()
+}
+
+impl InstanceId {
+    pub fn parse(instance_id: &str) -> Result<InstanceId, String> {
+        let mut parts = instance_id.splitn(3, '\\');
+        let enumerator = parts.next().unwrap_or_default();
+        let device_id = parts.next();
+        let instance = parts.next();
+        let (device_id, instance) = match (device_id, instance) {
+            (Some(device_id), Some(instance)) if !enumerator.is_empty() => (device_id, instance),
+            _ => return Err(format!("Invalid instance ID {}", instance_id)),
+        };
+
+        let mut result = InstanceId {
+            enumerator: enumerator.to_string(),
+            device_id: device_id.to_string(),
+            vid: None,
+            pid: None,
+            mi: None,
+            rev: None,
+            instance: instance.to_string(),
+        };
+        for part in device_id.to_uppercase().split('&') {
+            if let Some(vid) = hex_value(part, "VID_", 4) {
+                result.vid = Some(vid as u16);
+            } else if let Some(pid) = hex_value(part, "PID_", 4) {
+                result.pid = Some(pid as u16);
+            } else if let Some(mi) = hex_value(part, "MI_", 2) {
+                result.mi = Some(mi as u8);
+            } else if let Some(rev) = hex_value(part, "REV_", 4) {
+                result.rev = Some(rev as u16);
+            }
+        }
+        Ok(result)
+    }
+
+    /// True if the instance part was generated by the parent from the port location
+    /// (`<depth>&<hash>&<n>&<port>`) instead of the serial number of the device.
+    /// Such devices get a new instance, COM port number etc. when moved to another port.
+    pub fn is_location_derived(&self) -> bool {
+        let fields: Vec<&str> = self.instance.split('&').collect();
+        fields.len() >= 3
+            && fields
+                .iter()
+                .all(|f| !f.is_empty() && f.chars().all(|c| c.is_ascii_hexdigit()))
+    }
+}
+
+impl fmt::Display for InstanceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}\\{}\\{}",
+            self.enumerator, self.device_id, self.instance
+        )
+    }
+}
+
+/// True if the device reports a serial number but Windows did not accept it as unique,
+/// e.g. because another device with the same VID/PID/serial was seen before
+/// or the serial contains invalid characters.
+pub fn is_serial_ignored(device: &Device) -> bool {
+    let serial = match &device.descriptor.iSerialNumber.1 {
+        Some(serial) if !serial.is_empty() => serial,
+        _ => return false,
+    };
+    let instance_id = match device.pnp.as_ref().and_then(|p| p.instance_id.as_ref()) {
+        Some(id) => id,
+        None => return false,
+    };
+    match InstanceId::parse(instance_id) {
+        Ok(id) => !id.instance.eq_ignore_ascii_case(serial) && id.is_location_derived(),
+        Err(_err) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::UsbDeviceDescriptor;
+    use crate::property::PnpProperties;
+
+    fn device(serial: Option<&str>, instance_id: &str) -> Device {
+        let descriptor = UsbDeviceDescriptor {
+            idVendor: 0x0781,
+            idProduct: 0x5583,
+            iSerialNumber: (3, serial.map(str::to_string)),
+            ..Default::default()
+        };
+        let mut device = Device::from_descriptors(3, None, descriptor, None);
+        device.pnp = Some(PnpProperties {
+            instance_id: Some(instance_id.to_string()),
+            ..Default::default()
+        });
+        device
+    }
+
+    #[test]
+    fn parses_serial_number_instance() {
+        let id = InstanceId::parse(r"USB\VID_0781&PID_5583\4C530001").unwrap();
+        assert_eq!(id.enumerator, "USB");
+        assert_eq!(id.device_id, "VID_0781&PID_5583");
+        assert_eq!(
+            (id.vid, id.pid, id.mi, id.rev),
+            (Some(0x0781), Some(0x5583), None, None)
+        );
+        assert_eq!(id.instance, "4C530001");
+        assert!(!id.is_location_derived());
+        assert_eq!(id.to_string(), r"USB\VID_0781&PID_5583\4C530001");
+    }
+
+    #[test]
+    fn parses_location_derived_instance() {
+        let id = InstanceId::parse(r"USB\VID_0781&PID_5583\5&1a2b3c4d&0&3").unwrap();
+        assert_eq!(id.instance, "5&1a2b3c4d&0&3");
+        assert!(id.is_location_derived());
+    }
+
+    #[test]
+    fn parses_function_instance() {
+        let id = InstanceId::parse(r"USB\VID_046D&PID_C52B&MI_01\7&2B7C5E1&0&0001").unwrap();
+        assert_eq!(id.mi, Some(1));
+        assert_eq!((id.vid, id.pid), (Some(0x046d), Some(0xc52b)));
+        assert!(id.is_location_derived());
+
+        // Lower case and the revision of hardware IDs
+        let id = InstanceId::parse(r"usb\vid_046d&pid_c52b&rev_1211&mi_02\x").unwrap();
+        assert_eq!((id.rev, id.mi), (Some(0x1211), Some(2)));
+    }
+
+    #[test]
+    fn rejects_malformed_instance_ids() {
+        for id in [
+            "",
+            "USB",
+            r"USB\VID_0781&PID_5583",
+            r"\VID_0781&PID_5583\4C530001",
+        ] {
+            assert!(InstanceId::parse(id).is_err(), "{}", id);
+        }
+        // Fields of the wrong length are not values
+        let id = InstanceId::parse(r"USB\VID_781&PID_55830\1").unwrap();
+        assert_eq!((id.vid, id.pid), (None, None));
+    }
+
+    #[test]
+    fn detects_ignored_serial_numbers() {
+        // Serial reported but the instance was derived from the location
+        assert!(is_serial_ignored(&device(
+            Some("4C530001"),
+            r"USB\VID_0781&PID_5583\5&1a2b3c4d&0&3"
+        )));
+        assert!(!is_serial_ignored(&device(
+            Some("4C530001"),
+            r"USB\VID_0781&PID_5583\4C530001"
+        )));
+        assert!(!is_serial_ignored(&device(
+            None,
+            r"USB\VID_0781&PID_5583\5&1a2b3c4d&0&3"
+        )));
+        assert!(!is_serial_ignored(&device(Some("4C530001"), "garbage")));
+    }
+}
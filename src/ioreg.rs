@@ -0,0 +1,275 @@
+use crate::connection::UsbSpeed;
+use crate::descriptor::Hub;
+use crate::report::{to_hubs, Field, NodeKind, ReportNode, Section};
+use crate::xml::{self, Element};
+
+// Property list of `ioreg -p IOUSB -l -a`. The IOUSB plane only has the host controllers
+// and the devices, interfaces are not part of it.
+
+#[derive(Debug, Clone)]
+enum Value {
+    Dict(Vec<(String, Value)>),
+    Array(Vec<Value>),
+    String(String),
+    Integer(i64),
+    /// Booleans, data, dates and reals are not used
+    Other,
+}
+
+impl Value {
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Dict(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn integer(&self, key: &str) -> Option<i64> {
+        match self.get(key)? {
+            Value::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn string(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+fn parse_value(element: &Element) -> Result<Value, String> {
+    let value = match element.name.as_str() {
+        "dict" => {
+            let mut entries = Vec::new();
+            let mut children = element.children.iter();
+            while let Some(key) = children.next() {
+                if key.name != "key" {
+                    return Err(format!("Expected <key> in <dict>, found <{}>", key.name));
+                }
+                let value = children
+                    .next()
+                    .ok_or_else(|| format!("Missing value of {}", key.text))?;
+                entries.push((key.text.clone(), parse_value(value)?));
+            }
+            Value::Dict(entries)
+        }
+        "array" => Value::Array(
+            element
+                .children
+                .iter()
+                .map(parse_value)
+                .collect::<Result<_, _>>()?,
+        ),
+        "string" => Value::String(element.text.clone()),
+        "integer" => {
+            // Large unsigned values are printed as is, e.g. registry entry IDs
+            let text = element.text.as_str();
+            let value = text
+                .parse::<i64>()
+                .or_else(|_| text.parse::<u64>().map(|v| v as i64))
+                .map_err(|_| format!("Invalid integer {}", text))?;
+            Value::Integer(value)
+        }
+        _ => Value::Other,
+    };
+    Ok(value)
+}
+
+fn is_device(entry: &Value) -> bool {
+    match entry.string("IOObjectClass") {
+        Some(class) => class == "IOUSBHostDevice" || class == "IOUSBDevice",
+        None => false,
+    }
+}
+
+fn children(entry: &Value) -> &[Value] {
+    match entry.get("IORegistryEntryChildren") {
+        Some(Value::Array(children)) => children,
+        _ => &[],
+    }
+}
+
+/// `USBSpeed` of IOUSBHostDevice counts from no connection, `Device Speed` of the
+/// older IOUSBDevice starts at low speed
+fn speed(entry: &Value) -> Option<UsbSpeed> {
+    if let Some(speed) = entry.integer("USBSpeed") {
+        return match speed {
+            1 => Some(UsbSpeed::Full),
+            2 => Some(UsbSpeed::Low),
+            3 => Some(UsbSpeed::High),
+            4.. => Some(UsbSpeed::Super),
+            _ => None,
+        };
+    }
+    match entry.integer("Device Speed")? {
+        speed @ 0..=2 => UsbSpeed::from_raw(speed as u8),
+        3.. => Some(UsbSpeed::Super),
+        _ => None,
+    }
+}
+
+/// Name in the registry with the location, e.g. `USB Receiver@14200000`
+fn entry_name(entry: &Value) -> Option<String> {
+    let name = entry.string("IORegistryEntryName")?;
+    match entry.integer("locationID") {
+        Some(location) => Some(format!("{}@{:08x}", name, location as u32)),
+        None => Some(name.to_string()),
+    }
+}
+
+/// The location ID has the bus in the top byte followed by one nibble per hop
+fn port_from_location(location: u32, depth: usize) -> Option<u8> {
+    if depth == 0 || depth > 6 {
+        return None;
+    }
+    let nibble = (location >> (24 - 4 * depth)) & 0xF;
+    (nibble != 0).then_some(nibble as u8)
+}
+
+const DESCRIPTOR_FIELDS: [&str; 13] = [
+    "bcdUSB",
+    "bDeviceClass",
+    "bDeviceSubClass",
+    "bDeviceProtocol",
+    "bMaxPacketSize0",
+    "idVendor",
+    "idProduct",
+    "bcdDevice",
+    "iManufacturer",
+    "iProduct",
+    "iSerialNumber",
+    "bNumConfigurations",
+    "bLength",
+];
+
+fn read_device(entry: &Value, depth: usize) -> ReportNode {
+    let mut node = ReportNode::new(NodeKind::Device);
+    node.descriptors.begin(Section::Device);
+    for name in DESCRIPTOR_FIELDS {
+        if let Some(value) = entry.integer(name) {
+            let value = value.to_string();
+            let field = Field {
+                name,
+                value: &value,
+                string: None,
+            };
+            node.descriptors.apply(Section::Device, &field);
+        }
+    }
+    let descriptor = &mut node.descriptors.descriptor;
+    descriptor.bLength = 18;
+    descriptor.bDescriptorType = 1;
+    let string = |keys: [&str; 2]| keys.iter().find_map(|k| entry.string(k).map(String::from));
+    descriptor.iManufacturer.1 = string(["USB Vendor Name", "kUSBVendorString"]);
+    descriptor.iProduct.1 = string(["USB Product Name", "kUSBProductString"]);
+    descriptor.iSerialNumber.1 = string(["USB Serial Number", "kUSBSerialNumberString"]);
+
+    node.speed = speed(entry);
+    node.port_number = match entry.integer("PortNum") {
+        Some(port) => u8::try_from(port).ok(),
+        None => entry
+            .integer("locationID")
+            .and_then(|location| port_from_location(location as u32, depth)),
+    };
+
+    let devices: Vec<&Value> = children(entry).iter().filter(|c| is_device(c)).collect();
+    if node.descriptors.descriptor.bDeviceClass == 0x09 || !devices.is_empty() {
+        node.kind = NodeKind::Hub;
+        node.name = entry_name(entry);
+    }
+    for child in devices {
+        node.children.push(read_device(child, depth + 1));
+    }
+    node
+}
+
+/// Host controllers become root hubs with the devices below them
+fn find_roots(entry: &Value, roots: &mut Vec<ReportNode>) {
+    let devices: Vec<&Value> = children(entry).iter().filter(|c| is_device(c)).collect();
+    if !devices.is_empty() {
+        let mut root = ReportNode::new(NodeKind::RootHub);
+        root.name = entry_name(entry);
+        for device in devices {
+            root.children.push(read_device(device, 1));
+        }
+        roots.push(root);
+    }
+    for child in children(entry).iter().filter(|c| !is_device(c)) {
+        find_roots(child, roots);
+    }
+}
+
+/// Parses the output of `ioreg -p IOUSB -l -a`
+pub fn import(text: &str) -> Result<Vec<Hub>, String> {
+    let document = xml::parse(text)?;
+    if document.name != "plist" {
+        return Err(format!("Expected <plist>, found <{}>", document.name));
+    }
+    let mut roots = Vec::new();
+    for element in &document.children {
+        match parse_value(element)? {
+            Value::Array(entries) => {
+                for entry in &entries {
+                    find_roots(entry, &mut roots);
+                }
+            }
+            entry => find_roots(&entry, &mut roots),
+        }
+    }
+    if roots.is_empty() {
+        return Err("No USB devices found in the ioreg output".to_string());
+    }
+    Ok(to_hubs(roots))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(properties: &[(&str, i64)]) -> Value {
+        Value::Dict(
+            properties
+                .iter()
+                .map(|(key, value)| (key.to_string(), Value::Integer(*value)))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn port_of_location_id() {
+        // Bus 0x14, ports 2.1.3.4.5.6
+        let location = 0x14213456;
+        assert_eq!(port_from_location(location, 1), Some(2));
+        assert_eq!(port_from_location(location, 4), Some(4));
+        assert_eq!(port_from_location(location, 6), Some(6));
+        assert_eq!(port_from_location(location, 0), None);
+        assert_eq!(port_from_location(location, 7), None);
+        // Deeper than the device
+        assert_eq!(port_from_location(0x14200000, 2), None);
+    }
+
+    #[test]
+    fn speed_of_both_families() {
+        let host = |value: i64| speed(&entry(&[("USBSpeed", value)]));
+        assert_eq!(host(0), None);
+        assert_eq!(host(1), Some(UsbSpeed::Full));
+        assert_eq!(host(2), Some(UsbSpeed::Low));
+        assert_eq!(host(3), Some(UsbSpeed::High));
+        assert_eq!(host(4), Some(UsbSpeed::Super));
+        assert_eq!(host(5), Some(UsbSpeed::Super));
+
+        let legacy = |value: i64| speed(&entry(&[("Device Speed", value)]));
+        assert_eq!(legacy(0), Some(UsbSpeed::Low));
+        assert_eq!(legacy(1), Some(UsbSpeed::Full));
+        assert_eq!(legacy(2), Some(UsbSpeed::High));
+        assert_eq!(legacy(3), Some(UsbSpeed::Super));
+        assert_eq!(legacy(-1), None);
+
+        // USBSpeed wins if both are present
+        let both = entry(&[("Device Speed", 2), ("USBSpeed", 1)]);
+        assert_eq!(speed(&both), Some(UsbSpeed::Full));
+        assert_eq!(speed(&entry(&[])), None);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::descriptor::{UsbDeviceDescriptor, USB_DEVICE_DESCRIPTOR_SIZE};
+
+// https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/usbioctl/
+//
+// usbioctl.h wraps all structures in pshpack1.h, so there is no padding. They only use
+// fixed size fields (no pointers, SIZE_T or HANDLE), so the offsets are the same
+// on every architecture. The buffers are built and decoded from these offsets instead of
+// size_of() arithmetic on the Win32 structs.
+
+/// Byte offsets and sizes of the IOCTL structures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoctlLayout {
+    /// USB_ROOT_HUB_NAME and USB_HCD_DRIVERKEY_NAME: ULONG ActualLength, WCHAR Name[1]
+    pub name_offset: usize,
+    pub name_size: usize,
+    /// USB_NODE_CONNECTION_NAME and USB_NODE_CONNECTION_DRIVERKEY_NAME:
+    /// ULONG ConnectionIndex, ULONG ActualLength, WCHAR Name[1]
+    pub connection_name_offset: usize,
+    pub connection_name_size: usize,
+    /// USB_DESCRIPTOR_REQUEST: ULONG ConnectionIndex, 8 byte setup packet, UCHAR Data[1]
+    pub descriptor_setup_offset: usize,
+    pub descriptor_data_offset: usize,
+    pub descriptor_request_size: usize,
+    /// USB_NODE_INFORMATION: USB_HUB_NODE NodeType, USB_HUB_INFORMATION (71 byte hub descriptor and BOOLEAN)
+    pub number_of_ports_offset: usize,
+    pub node_information_size: usize,
+    /// USB_NODE_CONNECTION_INFORMATION_EX with one 11 byte USB_PIPE_INFO
+    pub device_descriptor_offset: usize,
+    pub current_configuration_offset: usize,
+    pub speed_offset: usize,
+    pub device_is_hub_offset: usize,
+    pub connection_status_offset: usize,
+    pub connection_information_size: usize,
+}
+
+/// Layout used for the requests, the same on x86, x64 and ARM64
+pub const LAYOUT: IoctlLayout = IoctlLayout {
+    name_offset: 4,
+    name_size: 6,
+    connection_name_offset: 8,
+    connection_name_size: 10,
+    descriptor_setup_offset: 4,
+    descriptor_data_offset: 12,
+    descriptor_request_size: 13,
+    number_of_ports_offset: 6,
+    node_information_size: 76,
+    device_descriptor_offset: 4,
+    current_configuration_offset: 22,
+    speed_offset: 23,
+    device_is_hub_offset: 24,
+    connection_status_offset: 31,
+    connection_information_size: 46,
+};
+
+// USB_CONNECTION_STATUS
+pub const NO_DEVICE_CONNECTED: u32 = 0;
+pub const DEVICE_CONNECTED: u32 = 1;
+
+fn too_short(name: &str, buf: &[u8], expected: usize) -> Result<(), String> {
+    if buf.len() < expected {
+        return Err(format!(
+            "{} is {} bytes, expected at least {}",
+            name,
+            buf.len(),
+            expected
+        ));
+    }
+    Ok(())
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+/// Decodes UTF-16LE up to the first NUL or the end of the data
+pub fn decode_utf16z(data: &[u8]) -> String {
+    let chars: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|c| *c != 0)
+        .collect();
+    String::from_utf16_lossy(&chars)
+}
+
+/// Output of IOCTL_USB_GET_ROOT_HUB_NAME and IOCTL_GET_HCD_DRIVERKEY_NAME
+pub fn name(buf: &[u8]) -> Result<String, String> {
+    too_short("Name", buf, LAYOUT.name_offset)?;
+    Ok(decode_utf16z(&buf[LAYOUT.name_offset..]))
+}
+
+/// Size of the complete name structure, valid in the first `name_size` bytes
+pub fn name_length(buf: &[u8]) -> Result<usize, String> {
+    too_short("Name", buf, 4)?;
+    Ok(u32_at(buf, 0) as usize)
+}
+
+/// Input of IOCTL_USB_GET_NODE_CONNECTION_NAME and IOCTL_USB_GET_NODE_CONNECTION_DRIVERKEY_NAME
+pub fn connection_name_request(port_number: u8) -> Vec<u8> {
+    let mut buf = vec![0u8; LAYOUT.connection_name_size];
+    buf[0..4].copy_from_slice(&(port_number as u32).to_le_bytes());
+    buf
+}
+
+/// Output of IOCTL_USB_GET_NODE_CONNECTION_NAME and IOCTL_USB_GET_NODE_CONNECTION_DRIVERKEY_NAME
+pub fn connection_name(buf: &[u8]) -> Result<String, String> {
+    too_short("Connection name", buf, LAYOUT.connection_name_offset)?;
+    Ok(decode_utf16z(&buf[LAYOUT.connection_name_offset..]))
+}
+
+/// Size of the complete connection name structure, valid in the first `connection_name_size` bytes
+pub fn connection_name_length(buf: &[u8]) -> Result<usize, String> {
+    too_short("Connection name", buf, 8)?;
+    Ok(u32_at(buf, 4) as usize)
+}
+
+/// USB_DESCRIPTOR_REQUEST header for a GET_DESCRIPTOR request, the hub driver fills in
+/// bmRequest and bRequest. The data follows at `descriptor_data_offset`.
+pub fn descriptor_request(
+    port_number: u8,
+    descriptor_type: u8,
+    descriptor_index: u8,
+    lang_id: u16,
+    length: u16,
+) -> Vec<u8> {
+    let mut buf = vec![0u8; LAYOUT.descriptor_data_offset];
+    let setup = LAYOUT.descriptor_setup_offset;
+    let value = ((descriptor_type as u16) << 8) | descriptor_index as u16;
+    buf[0..4].copy_from_slice(&(port_number as u32).to_le_bytes());
+    buf[setup + 2..setup + 4].copy_from_slice(&value.to_le_bytes());
+    buf[setup + 4..setup + 6].copy_from_slice(&lang_id.to_le_bytes());
+    buf[setup + 6..setup + 8].copy_from_slice(&length.to_le_bytes());
+    buf
+}
+
+/// Descriptor bytes of an IOCTL_USB_GET_DESCRIPTOR_FROM_NODE_CONNECTION output
+pub fn descriptor_data(buf: &[u8]) -> Result<&[u8], String> {
+    too_short("Descriptor request", buf, LAYOUT.descriptor_data_offset)?;
+    Ok(&buf[LAYOUT.descriptor_data_offset..])
+}
+
+/// Request size for the complete descriptor from bLength
+pub fn descriptor_length(buf: &[u8]) -> Result<usize, String> {
+    let data = descriptor_data(buf)?;
+    too_short("Descriptor", data, 1)?;
+    Ok(LAYOUT.descriptor_data_offset + data[0] as usize)
+}
+
+/// Request size for a configuration or BOS descriptor including all following
+/// descriptors, from wTotalLength
+pub fn total_descriptor_length(buf: &[u8]) -> Result<usize, String> {
+    let data = descriptor_data(buf)?;
+    too_short("Descriptor", data, 4)?;
+    Ok(LAYOUT.descriptor_data_offset + u16::from_le_bytes([data[2], data[3]]) as usize)
+}
+
+/// String descriptor: bLength, bDescriptorType, UTF-16LE string without terminator
+pub fn string_descriptor(buf: &[u8]) -> Result<String, String> {
+    let data = descriptor_data(buf)?;
+    too_short("String descriptor", data, 2)?;
+    let end = (data[0] as usize).clamp(2, data.len());
+    Ok(decode_utf16z(&data[2..end]))
+}
+
+/// String descriptor 0 lists the supported LANGIDs
+pub fn language_ids(buf: &[u8]) -> Result<Vec<u16>, String> {
+    let data = descriptor_data(buf)?;
+    too_short("Language ID descriptor", data, 2)?;
+    let end = (data[0] as usize).clamp(2, data.len());
+    Ok(data[2..end]
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect())
+}
+
+/// Input of IOCTL_USB_GET_NODE_INFORMATION, NodeType is UsbHub (0)
+pub fn node_information_request() -> Vec<u8> {
+    vec![0u8; LAYOUT.node_information_size]
+}
+
+pub fn number_of_ports(buf: &[u8]) -> Result<u8, String> {
+    too_short("Node information", buf, LAYOUT.number_of_ports_offset + 1)?;
+    Ok(buf[LAYOUT.number_of_ports_offset])
+}
+
+/// Input of IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX
+pub fn connection_information_request(port_number: u8) -> Vec<u8> {
+    let mut buf = vec![0u8; LAYOUT.connection_information_size];
+    buf[0..4].copy_from_slice(&(port_number as u32).to_le_bytes());
+    buf
+}
+
+/// Decoded USB_NODE_CONNECTION_INFORMATION_EX, the pipes are not used
+#[derive(Debug, Clone)]
+pub struct NodeConnectionInformation {
+    pub connection_status: u32,
+    pub descriptor: UsbDeviceDescriptor,
+    /// bConfigurationValue of the active configuration, 0 if not configured
+    pub current_configuration_value: u8,
+    /// USB_DEVICE_SPEED
+    pub speed: u8,
+    pub device_is_hub: bool,
+}
+
+pub fn connection_information(buf: &[u8]) -> Result<NodeConnectionInformation, String> {
+    too_short(
+        "Connection information",
+        buf,
+        LAYOUT.connection_status_offset + 4,
+    )?;
+    let offset = LAYOUT.device_descriptor_offset;
+    Ok(NodeConnectionInformation {
+        connection_status: u32_at(buf, LAYOUT.connection_status_offset),
+        descriptor: UsbDeviceDescriptor::from_bytes(
+            &buf[offset..offset + USB_DEVICE_DESCRIPTOR_SIZE],
+        )?,
+        current_configuration_value: buf[LAYOUT.current_configuration_offset],
+        speed: buf[LAYOUT.speed_offset],
+        device_is_hub: buf[LAYOUT.device_is_hub_offset] != 0,
+    })
+}
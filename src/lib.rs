@@ -1,122 +1,152 @@
+use backend::{sized_ioctl, Backend, Win32Backend};
+use composite::DeviceFunction;
+use connection::ConnectionInfoV2;
+use connection::PortConnectorProperties;
+use connection::UsbSpeed;
+use connection::CONNECTION_INFORMATION_EX_V2_SIZE;
+use connection::PORT_CONNECTOR_PROPERTIES_NAME_OFFSET;
+use controller::ControllerInfo;
+use controller::HostController;
+use controller::PciIdentity;
+use controller::CONTROLLER_INFO_0_SIZE;
+use controller::IOCTL_USB_USER_REQUEST;
 use descriptor::Device;
 use descriptor::Hub;
+use descriptor::UsbBosDescriptor;
+use descriptor::UsbConfigurationDescriptor;
 use descriptor::UsbDeviceDescriptor;
-use driver::get_all_ids;
-use helper::get_error;
-use helper::get_mut_ptr;
-use std::ffi::c_void;
-use windows::core::Error;
-use windows::core::HSTRING;
-use windows::core::PCWSTR;
-use windows::Win32::Devices::Usb::DeviceConnected;
-use windows::Win32::Devices::Usb::NoDeviceConnected;
+use driver::DeviceIndex;
+use layout::LAYOUT;
+use query::DeviceQuery;
+use std::sync::Mutex;
+use watch::{WatchOptions, Watcher};
+use windows::Win32::Devices::Usb::IOCTL_GET_HCD_DRIVERKEY_NAME;
 use windows::Win32::Devices::Usb::IOCTL_USB_GET_DESCRIPTOR_FROM_NODE_CONNECTION;
 use windows::Win32::Devices::Usb::IOCTL_USB_GET_NODE_CONNECTION_DRIVERKEY_NAME;
-use windows::Win32::Devices::Usb::IOCTL_USB_GET_NODE_CONNECTION_INFORMATION;
+use windows::Win32::Devices::Usb::IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX;
+use windows::Win32::Devices::Usb::IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX_V2;
 use windows::Win32::Devices::Usb::IOCTL_USB_GET_NODE_CONNECTION_NAME;
 use windows::Win32::Devices::Usb::IOCTL_USB_GET_NODE_INFORMATION;
+use windows::Win32::Devices::Usb::IOCTL_USB_GET_PORT_CONNECTOR_PROPERTIES;
 use windows::Win32::Devices::Usb::IOCTL_USB_GET_ROOT_HUB_NAME;
 use windows::Win32::Devices::Usb::MAX_USB_STRING_LENGTH;
-use windows::Win32::Devices::Usb::USB_DESCRIPTOR_REQUEST;
-use windows::Win32::Devices::Usb::USB_NODE_CONNECTION_DRIVERKEY_NAME;
-use windows::Win32::Devices::Usb::USB_NODE_CONNECTION_INFORMATION;
-use windows::Win32::Devices::Usb::USB_NODE_CONNECTION_NAME;
-use windows::Win32::Devices::Usb::USB_NODE_INFORMATION;
-use windows::Win32::Devices::Usb::USB_ROOT_HUB_NAME;
+use windows::Win32::Devices::Usb::USB_BOS_DESCRIPTOR_TYPE;
+use windows::Win32::Devices::Usb::USB_CONFIGURATION_DESCRIPTOR_TYPE;
 use windows::Win32::Devices::Usb::USB_STRING_DESCRIPTOR_TYPE;
-use windows::Win32::Foundation::BOOL;
 use windows::Win32::Foundation::HANDLE;
-use windows::Win32::Foundation::MAX_PATH;
-use windows::Win32::Storage::FileSystem::CreateFileW;
-use windows::Win32::Storage::FileSystem::FILE_GENERIC_WRITE;
-use windows::Win32::Storage::FileSystem::FILE_SHARE_WRITE;
-use windows::Win32::Storage::FileSystem::OPEN_EXISTING;
-use windows::Win32::Storage::FileSystem::SECURITY_ANONYMOUS;
-use windows::Win32::System::IO::DeviceIoControl;
 
+pub mod backend;
+pub mod composite;
+pub mod connection;
+pub mod container;
+pub mod controller;
 pub mod descriptor;
+pub mod diff;
 pub(crate) mod driver;
+pub mod guid;
 pub(crate) mod helper;
+pub mod hwid;
+pub mod instance_id;
+pub mod ioreg;
+pub mod layout;
+pub mod lsusb;
+pub mod mock;
+pub mod pcap;
+mod pool;
+pub mod property;
+pub mod query;
+pub mod record;
+pub mod replay;
+pub(crate) mod report;
+pub mod selector;
+pub mod topology;
+pub mod usbfs;
+pub mod usbview;
+pub mod watch;
+pub(crate) mod xml;
 
 // https://learn.microsoft.com/en-us/samples/microsoft/windows-driver-samples/usbview-sample-application/
 
-fn get_root_hub_name(handle: HANDLE) -> Result<String, String> {
-    let retbytes = Some(0 as *mut u32);
-    let mut outbuf: [u16; MAX_PATH as usize] = [0; MAX_PATH as usize];
-    let outbuf_ptr = get_mut_ptr(&mut outbuf);
-
-    let result = unsafe {
-        DeviceIoControl(
-            handle,
-            IOCTL_USB_GET_ROOT_HUB_NAME,
-            None,
-            0,
-            Some(outbuf_ptr),
-            (outbuf.len() * std::mem::size_of::<u16>()) as u32,
-            retbytes,
-            None,
-        )
-    };
-    return if result == BOOL(1) {
-        let start = (std::mem::size_of::<USB_ROOT_HUB_NAME>() - 2) / 2; // RootHubName so minus 2, divide by 2 for u16
-        let b = String::from_utf16_lossy(&outbuf[start..]);
-        let b = b.trim_end_matches('\0');
-        Ok(b.to_string())
-    } else {
-        Err(get_error())
-    };
+fn get_root_hub_name(backend: &dyn Backend, handle: HANDLE) -> Result<String, String> {
+    let outbuf = sized_ioctl(
+        backend,
+        handle,
+        IOCTL_USB_GET_ROOT_HUB_NAME,
+        LAYOUT.name_size,
+        |_| Vec::new(),
+        layout::name_length,
+    )?;
+    layout::name(&outbuf)
 }
 
-fn get_number_of_ports(handle: HANDLE) -> Result<u8, String> {
-    unsafe {
-        let retbytes = Some(0 as *mut u32);
-        let mut inbuf = USB_NODE_INFORMATION::default();
-        inbuf.NodeType = windows::Win32::Devices::Usb::UsbHub;
-        let inbuf_ptr = get_mut_ptr(&mut inbuf);
+fn get_hcd_driverkey_name(backend: &dyn Backend, handle: HANDLE) -> Result<String, String> {
+    let outbuf = sized_ioctl(
+        backend,
+        handle,
+        IOCTL_GET_HCD_DRIVERKEY_NAME,
+        LAYOUT.name_size,
+        |_| Vec::new(),
+        layout::name_length,
+    )?;
+    layout::name(&outbuf)
+}
 
-        let mut outbuf = USB_NODE_INFORMATION::default();
-        let outbuf_ptr = get_mut_ptr(&mut outbuf);
+fn get_controller_info(backend: &dyn Backend, handle: HANDLE) -> Result<ControllerInfo, String> {
+    let request = ControllerInfo::request();
+    let mut outbuf = [0u8; CONTROLLER_INFO_0_SIZE];
+    let returned = backend.ioctl(handle, IOCTL_USB_USER_REQUEST, &request, &mut outbuf)?;
+    ControllerInfo::from_bytes(&outbuf[..returned])
+}
 
-        let result = DeviceIoControl(
-            handle,
-            IOCTL_USB_GET_NODE_INFORMATION,
-            Some(inbuf_ptr),
-            (std::mem::size_of::<USB_NODE_INFORMATION>()) as u32,
-            Some(outbuf_ptr),
-            (std::mem::size_of::<USB_NODE_INFORMATION>()) as u32,
-            retbytes,
-            None,
-        );
-        return if result == BOOL(1) {
-            let number_of_hub_ports = outbuf.u.HubInformation.HubDescriptor.bNumberOfPorts;
-            Ok(number_of_hub_ports)
-        } else {
-            Err(get_error())
-        };
+fn get_host_controller(
+    backend: &dyn Backend,
+    handle: HANDLE,
+    index: u8,
+    root_hub: &str,
+) -> HostController {
+    let driver_key_name = get_hcd_driverkey_name(backend, handle).unwrap_or_default();
+    let info = get_controller_info(backend, handle).ok();
+    let (mut pci, location_path) = match backend.pci_device_ids(&driver_key_name) {
+        Ok((ids, paths)) => (
+            PciIdentity::from_hardware_ids(&ids),
+            // The ACPI path is listed as well, the PCIROOT one is what Windows uses for USB devices
+            paths.into_iter().find(|p| p.starts_with("PCIROOT")),
+        ),
+        Err(_err) => (None, None),
     };
+    if pci.is_none() {
+        // Hardware IDs are not available, e.g. for non PCI controllers, use what the USB stack knows
+        pci = info.map(|info| PciIdentity {
+            vendor_id: info.pci_vendor_id as u16,
+            device_id: info.pci_device_id as u16,
+            revision: info.pci_revision as u8,
+            ..Default::default()
+        });
+    }
+    HostController {
+        index,
+        driver_key_name,
+        flavor: HostController::detect_flavor(&pci, &info, root_hub),
+        pci,
+        number_of_root_ports: info.map(|info| info.number_of_root_ports),
+        location_path,
+    }
 }
 
-fn open_device(hub: &mut String) -> Result<HANDLE, Error> {
-    hub.insert_str(0, r"\\.\");
-    let hub: PCWSTR = PCWSTR(HSTRING::from(hub.clone()).as_ptr());
-    let x = unsafe {
-        CreateFileW(
-            hub,
-            FILE_GENERIC_WRITE.0,
-            FILE_SHARE_WRITE,
-            None,
-            OPEN_EXISTING,
-            SECURITY_ANONYMOUS,
-            None,
-        )
-    };
-    return match x {
-        Ok(handle) => Ok(handle),
-        Err(err) => Err(err),
-    };
+fn get_number_of_ports(backend: &dyn Backend, handle: HANDLE) -> Result<u8, String> {
+    let request = layout::node_information_request();
+    let mut outbuf = vec![0u8; LAYOUT.node_information_size];
+    let returned = backend.ioctl(
+        handle,
+        IOCTL_USB_GET_NODE_INFORMATION,
+        &request,
+        &mut outbuf,
+    )?;
+    layout::number_of_ports(&outbuf[..returned])
 }
 
 fn get_descriptor(
+    backend: &dyn Backend,
     handle: HANDLE,
     port_number: u8,
     descriptor_id: u8,
@@ -127,67 +157,68 @@ fn get_descriptor(
     }
     // https://learn.microsoft.com/en-us/windows-hardware/drivers/usbcon/usb-string-descriptors
 
-    let retbytes = Some(0 as *mut u32);
-    let mut inbuf = USB_DESCRIPTOR_REQUEST::default();
-    inbuf.ConnectionIndex = port_number as u32;
-    inbuf.SetupPacket.wValue = ((USB_STRING_DESCRIPTOR_TYPE << 8) | descriptor_id as u32) as u16;
-    inbuf.SetupPacket.wIndex = lang_id;
-    inbuf.SetupPacket.wLength = MAX_USB_STRING_LENGTH as u16;
-    let inbuf_ptr = get_mut_ptr(&mut inbuf);
-
-    let mut outbuf: [u16; (MAX_USB_STRING_LENGTH) as usize] = [0; (MAX_USB_STRING_LENGTH) as usize];
-    let outbuf_ptr = get_mut_ptr(&mut outbuf);
-
-    let result = unsafe {
-        DeviceIoControl(
-            handle,
-            IOCTL_USB_GET_DESCRIPTOR_FROM_NODE_CONNECTION,
-            Some(inbuf_ptr),
-            (std::mem::size_of::<USB_DESCRIPTOR_REQUEST>()) as u32,
-            Some(outbuf_ptr),
-            (outbuf.len() * std::mem::size_of::<u16>()) as u32,
-            retbytes,
-            None,
-        )
+    // Without a language ask for string descriptor 0, the list of supported languages
+    let (index, language) = if lang_id == 0 {
+        (0, 0)
+    } else {
+        (descriptor_id, lang_id)
     };
+    // bLength is one byte, so the first request always gets the complete string
+    let header = LAYOUT.descriptor_data_offset;
+    let outbuf = sized_ioctl(
+        backend,
+        handle,
+        IOCTL_USB_GET_DESCRIPTOR_FROM_NODE_CONNECTION,
+        header + MAX_USB_STRING_LENGTH as usize,
+        |size| {
+            let mut request = layout::descriptor_request(
+                port_number,
+                USB_STRING_DESCRIPTOR_TYPE as u8,
+                index,
+                language,
+                (size - header) as u16,
+            );
+            request.resize(size, 0);
+            request
+        },
+        layout::descriptor_length,
+    )
+    .ok()?;
 
-    return if result == BOOL(1) {
-        if lang_id == 0 {
-            let first_lang = outbuf[0];
-            Some(("".to_string(), first_lang))
-        } else {
-            let start = (std::mem::size_of::<USB_DESCRIPTOR_REQUEST>() as u32 - 1)
-                / std::mem::size_of::<u16>() as u32; // Struct size is 13 bytes with data starting at byte 12. buffer is u16 so divide length by 2
-            let b = String::from_utf16_lossy(&outbuf[(start + 1) as usize..]); // +1 for alignment
-            let b = b.trim_end_matches('\0');
-            Some((b.to_string(), 0))
-        }
+    if lang_id == 0 {
+        let languages = layout::language_ids(&outbuf).ok()?;
+        languages
+            .first()
+            .map(|first_lang| ("".to_string(), *first_lang))
     } else {
-        None
-    };
+        match layout::string_descriptor(&outbuf) {
+            Ok(value) => Some((value, 0)),
+            Err(_err) => None,
+        }
+    }
 }
 
 fn get_string_fallback(
+    backend: &dyn Backend,
     handle: HANDLE,
     port_number: u8,
     string_id: u8,
     lang_id: u16,
 ) -> Option<String> {
-    let tmp = get_descriptor(handle, port_number, string_id, lang_id);
-    let result: Option<String>;
-    if tmp == None {
+    let tmp = get_descriptor(backend, handle, port_number, string_id, lang_id);
+    if tmp.is_none() {
         // If there is not localized descriptor try the first one from the list of supported languages
-        let tmp = get_descriptor(handle, port_number, string_id, 0); // Get first language from descriptor
-        result = match tmp {
+        let tmp = get_descriptor(backend, handle, port_number, string_id, 0); // Get first language from descriptor
+        match tmp {
             Some(code) => {
                 let tmp: Option<(String, u16)> =
-                    get_descriptor(handle, port_number, string_id, code.1); // Request again //TODO Check with virtualbox
+                    get_descriptor(backend, handle, port_number, string_id, code.1); // Request again //TODO Check with virtualbox
                 match tmp {
                     Some(code) => Some(code.0),
                     None => {
                         //println!("US FALLBACK MODE");
                         let tmp: Option<(String, u16)> =
-                            get_descriptor(handle, port_number, string_id, 0x0409); // Fallback to us-en
+                            get_descriptor(backend, handle, port_number, string_id, 0x0409); // Fallback to us-en
                         match tmp {
                             Some(val) => Some(val.0),
                             None => None,
@@ -196,269 +227,867 @@ fn get_string_fallback(
                 }
             }
             None => None,
-        };
+        }
     } else {
-        result = match tmp {
+        match tmp {
             Some(val) => Some(val.0),
             None => None,
         }
     }
-    return result;
 }
 
-fn get_secondary_hub_name(handle: HANDLE, index: u32) -> Result<String, String> {
-    let retbytes = Some(0 as *mut u32);
-    let mut inbuf = USB_NODE_CONNECTION_NAME::default();
-    inbuf.ConnectionIndex = index;
-    let input_ptr = get_mut_ptr(&mut inbuf);
+/// Requests any descriptor and returns the bytes following the USB_DESCRIPTOR_REQUEST header
+fn get_raw_descriptor(
+    backend: &dyn Backend,
+    handle: HANDLE,
+    port_number: u8,
+    descriptor_type: u32,
+    descriptor_index: u8,
+    initial_length: u16,
+    required_size: impl Fn(&[u8]) -> Result<usize, String>,
+) -> Result<Vec<u8>, String> {
+    let header = LAYOUT.descriptor_data_offset;
+    let outbuf = sized_ioctl(
+        backend,
+        handle,
+        IOCTL_USB_GET_DESCRIPTOR_FROM_NODE_CONNECTION,
+        header + initial_length as usize,
+        |size| {
+            let mut request = layout::descriptor_request(
+                port_number,
+                descriptor_type as u8,
+                descriptor_index,
+                0,
+                (size - header) as u16,
+            );
+            request.resize(size, 0);
+            request
+        },
+        required_size,
+    )?;
+    Ok(layout::descriptor_data(&outbuf)?.to_vec())
+}
 
-    let mut outbuf: [u16; MAX_PATH as usize] = [0; MAX_PATH as usize];
-    let outbuf_ptr = get_mut_ptr(&mut outbuf);
+/// Configurations are requested by index, the active one is found by its
+/// bConfigurationValue. Unconfigured devices (value 0) get the first one.
+fn get_configuration_descriptor(
+    backend: &dyn Backend,
+    handle: HANDLE,
+    port_number: u8,
+    configuration_value: u8,
+    number_of_configurations: u8,
+    lang_id: u16,
+) -> Result<UsbConfigurationDescriptor, String> {
+    let mut first = None;
+    for index in 0..number_of_configurations.max(1) {
+        // Read the header first to get wTotalLength
+        let data = get_raw_descriptor(
+            backend,
+            handle,
+            port_number,
+            USB_CONFIGURATION_DESCRIPTOR_TYPE,
+            index,
+            9,
+            layout::total_descriptor_length,
+        )?;
+        let config = UsbConfigurationDescriptor::from_bytes(&data)?;
+        if configuration_value == 0 || config.bConfigurationValue == configuration_value {
+            first = Some(config);
+            break;
+        }
+        first.get_or_insert(config);
+    }
+    let mut config = first.ok_or("Device has no configuration")?;
 
-    let result = unsafe {
-        DeviceIoControl(
+    config.iConfiguration.1 = get_string_fallback(
+        backend,
+        handle,
+        port_number,
+        config.iConfiguration.0,
+        lang_id,
+    );
+    for interface in config.interfaces.iter_mut() {
+        interface.iInterface.1 = get_string_fallback(
+            backend,
             handle,
-            IOCTL_USB_GET_NODE_CONNECTION_NAME,
-            Some(input_ptr),
-            (std::mem::size_of::<USB_NODE_CONNECTION_NAME>()) as u32,
-            Some(outbuf_ptr),
-            (outbuf.len() * std::mem::size_of::<u16>()) as u32,
-            retbytes,
-            None,
-        )
-    };
-    return if result == BOOL(1) {
-        let start = (std::mem::size_of::<USB_NODE_CONNECTION_NAME>() as u32 - 2)
-            / std::mem::size_of::<u16>() as u32; // Struct size is 10 bytes with data starting at byte 8. buffer is u16 so divide length by 2
-        let b = String::from_utf16_lossy(&outbuf[start as usize..]);
-        let b = b.trim_end_matches('\0');
-        Ok(b.to_string())
-    } else {
-        Err(get_error())
-    };
+            port_number,
+            interface.iInterface.0,
+            lang_id,
+        );
+    }
+    for association in config.associations.iter_mut() {
+        association.iFunction.1 = get_string_fallback(
+            backend,
+            handle,
+            port_number,
+            association.iFunction.0,
+            lang_id,
+        );
+    }
+    Ok(config)
 }
 
-fn get_port_information(
+fn get_bos_descriptor(
+    backend: &dyn Backend,
     handle: HANDLE,
     port_number: u8,
-    hubs: &mut Vec<Hub>,
-    parent_hub: String,
+) -> Result<UsbBosDescriptor, String> {
+    // Read the header first to get wTotalLength
+    let data = get_raw_descriptor(
+        backend,
+        handle,
+        port_number,
+        USB_BOS_DESCRIPTOR_TYPE,
+        0,
+        5,
+        layout::total_descriptor_length,
+    )?;
+    UsbBosDescriptor::from_bytes(&data)
+}
+
+/// Maps the functions of a composite device to the child device nodes Windows created for them
+fn get_device_functions(
+    desc: &UsbDeviceDescriptor,
+    config: &UsbConfigurationDescriptor,
+    instance_id: &Option<String>,
+    index: &DeviceIndex,
+) -> Vec<DeviceFunction> {
+    if !composite::is_composite(desc, config) {
+        return Vec::new();
+    }
+    let mut functions = composite::device_functions(config);
+    if let Some(instance_id) = instance_id {
+        let children = index
+            .children(instance_id)
+            .into_iter()
+            .map(|child| (child.driver_key.clone(), child.pnp.clone()))
+            .collect();
+        composite::assign_children(&mut functions, children);
+    }
+    functions
+}
+
+fn get_secondary_hub_name(
+    backend: &dyn Backend,
+    handle: HANDLE,
+    index: u32,
+) -> Result<String, String> {
+    let outbuf = sized_ioctl(
+        backend,
+        handle,
+        IOCTL_USB_GET_NODE_CONNECTION_NAME,
+        LAYOUT.connection_name_size,
+        |_| layout::connection_name_request(index as u8),
+        layout::connection_name_length,
+    )?;
+    layout::connection_name(&outbuf)
+}
+
+/// Progress of `enumerate`
+pub enum Discovered<'a> {
+    Device { hub_id: &'a str, device: &'a Device },
+    Hub(&'a Hub),
+}
+
+/// Hubs read by an enumeration and why the others could not be read
+#[derive(Debug, Clone, Default)]
+pub struct Enumeration {
+    pub hubs: Vec<Hub>,
+    /// Errors of the device index and of the host controllers and hubs, or of the
+    /// devices an import skipped
+    pub errors: Vec<String>,
+}
+
+/// Without the device index the devices have no driver or Plug and Play properties,
+/// but the descriptors can still be read
+fn build_device_index(backend: &dyn Backend, errors: &mut Vec<String>) -> DeviceIndex {
+    DeviceIndex::build(backend).unwrap_or_else(|err| {
+        errors.push(err);
+        DeviceIndex::default()
+    })
+}
+
+/// Connection of a hub port, string descriptors are not requested yet
+struct PortConnection {
+    descriptor: UsbDeviceDescriptor,
+    speed: Option<UsbSpeed>,
+    is_hub: bool,
+    /// bConfigurationValue of the active configuration, 0 if not configured
+    configuration_value: u8,
+}
+
+fn get_port_connection(
+    backend: &dyn Backend,
+    handle: HANDLE,
+    port_number: u8,
+) -> Result<PortConnection, String> {
+    let request = layout::connection_information_request(port_number);
+    let mut outbuf = vec![0u8; LAYOUT.connection_information_size];
+    let returned = backend.ioctl(
+        handle,
+        IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX,
+        &request,
+        &mut outbuf,
+    )?;
+    let info = layout::connection_information(&outbuf[..returned])?;
+    match info.connection_status {
+        layout::DEVICE_CONNECTED => Ok(PortConnection {
+            descriptor: info.descriptor,
+            speed: UsbSpeed::from_raw(info.speed),
+            is_hub: info.device_is_hub,
+            configuration_value: info.current_configuration_value,
+        }),
+        layout::NO_DEVICE_CONNECTED => Err(format!("Port {} is not connected", port_number)),
+        _ => Err(format!("Port {} is in transition state", port_number)),
+    }
+}
+
+fn get_device_strings(
+    backend: &dyn Backend,
+    handle: HANDLE,
+    port_number: u8,
+    desc: &mut UsbDeviceDescriptor,
     lang_id: u16,
-) -> Result<UsbDeviceDescriptor, String> {
-    let retbytes = Some(0 as *mut u32);
-    let mut inbuf = USB_NODE_CONNECTION_INFORMATION::default();
-    inbuf.ConnectionIndex = port_number as u32;
-    let inbuf_ptr = get_mut_ptr(&mut inbuf);
+) {
+    desc.iManufacturer.1 =
+        get_string_fallback(backend, handle, port_number, desc.iManufacturer.0, lang_id);
+    desc.iProduct.1 = get_string_fallback(backend, handle, port_number, desc.iProduct.0, lang_id);
+    desc.iSerialNumber.1 =
+        get_string_fallback(backend, handle, port_number, desc.iSerialNumber.0, lang_id);
+}
 
-    let mut outbuf = USB_NODE_CONNECTION_INFORMATION::default(); // This is working because USB_NODE_CONNECTION_INFORMATION has no stupid arrarys
-    let outbuf_ptr = get_mut_ptr(&mut outbuf);
+/// Reads everything about a connected device which is not a hub
+fn get_device(
+    backend: &dyn Backend,
+    handle: HANDLE,
+    port_number: u8,
+    connection: PortConnection,
+    lang_id: u16,
+    index: &DeviceIndex,
+) -> Result<Device, String> {
+    let mut desc = connection.descriptor;
+    get_device_strings(backend, handle, port_number, &mut desc, lang_id);
 
-    let result = unsafe {
-        DeviceIoControl(
+    // Not supported before Windows 8, so a failure is not fatal
+    let connection_v2 = get_connection_info_v2(backend, handle, port_number).ok();
+    let mut device = Device {
+        port_number,
+        container_id: None,
+        driver_key_name: "".to_string(),
+        speed: connection.speed,
+        speed_downgrade: match connection_v2 {
+            Some(info) => info.is_speed_downgraded(),
+            None => false,
+        },
+        connection: connection_v2,
+        location: None,
+        connector: None,
+        pnp: None,
+        configuration: get_configuration_descriptor(
+            backend,
             handle,
-            IOCTL_USB_GET_NODE_CONNECTION_INFORMATION,
-            Some(inbuf_ptr),
-            (std::mem::size_of::<USB_NODE_CONNECTION_INFORMATION>()) as u32,
-            Some(outbuf_ptr),
-            (std::mem::size_of::<USB_NODE_CONNECTION_INFORMATION>()) as u32,
-            retbytes,
-            None,
+            port_number,
+            connection.configuration_value,
+            desc.bNumConfigurations,
+            lang_id,
         )
-    };
-    if result == BOOL(1) {
-        let connected = outbuf.ConnectionStatus;
-        if connected == DeviceConnected {
-            let desc = UsbDeviceDescriptor {
-                bLength: outbuf.DeviceDescriptor.bLength,
-                bDescriptorType: outbuf.DeviceDescriptor.bDescriptorType,
-                bcdUSB: outbuf.DeviceDescriptor.bcdUSB,
-                bDeviceClass: outbuf.DeviceDescriptor.bDeviceClass,
-                bDeviceSubClass: outbuf.DeviceDescriptor.bDeviceSubClass,
-                bDeviceProtocol: outbuf.DeviceDescriptor.bDeviceProtocol,
-                bMaxPacketSize0: outbuf.DeviceDescriptor.bMaxPacketSize0,
-                idVendor: outbuf.DeviceDescriptor.idVendor,
-                idProduct: outbuf.DeviceDescriptor.idProduct,
-                bcdDevice: outbuf.DeviceDescriptor.bcdDevice,
-                iManufacturer: (
-                    outbuf.DeviceDescriptor.iManufacturer,
-                    get_string_fallback(
-                        handle,
-                        port_number,
-                        outbuf.DeviceDescriptor.iManufacturer,
-                        lang_id,
-                    ),
-                ),
-                iProduct: (
-                    outbuf.DeviceDescriptor.iProduct,
-                    get_string_fallback(
-                        handle,
-                        port_number,
-                        outbuf.DeviceDescriptor.iProduct,
-                        lang_id,
-                    ),
-                ),
-                iSerialNumber: (
-                    outbuf.DeviceDescriptor.iSerialNumber,
-                    get_string_fallback(
-                        handle,
-                        port_number,
-                        outbuf.DeviceDescriptor.iSerialNumber,
-                        lang_id,
-                    ),
-                ),
-                bNumConfigurations: outbuf.DeviceDescriptor.bNumConfigurations,
-            };
-
-            if outbuf.DeviceIsHub.as_bool() {
-                match get_secondary_hub_name(handle, outbuf.ConnectionIndex) {
-                    Ok(hub_id) => {
-                        match get_hub_devices(hub_id.clone(), hubs, lang_id) {
-                            Ok(mut hub) => {
-                                hub.parent_hub = Some(parent_hub);
-                                hub.descriptor = Some(desc);
-                                hubs.push(hub);
-                                return Err(format!("Device is a hub which is added to the hub list instead of returning it"));
-                            }
-                            Err(err) => {
-                                return Err(format!("Could not extract hub devices: {}", err));
-                            }
-                        };
-                    }
-                    Err(err) => {
-                        return Err(format!("Could not extract hub name: {}", err));
-                    }
-                };
-            } else {
-                return Ok(desc);
-            }
-        } else if connected == NoDeviceConnected {
-            return Err(format!("Port {} is not connected", port_number));
+        .ok(),
+        bos: if desc.bcdUSB >= 0x0201 {
+            get_bos_descriptor(backend, handle, port_number).ok()
         } else {
-            return Err(format!("Port {} is in transition state", port_number));
+            None
+        },
+        functions: Vec::new(),
+        serial_ignored: false,
+        hid_reports: Vec::new(),
+        descriptor: desc,
+    };
+    let driverkey = get_driverkey_name(backend, handle, port_number)?;
+    if let Some(properties) = index.get(&driverkey) {
+        device.container_id = properties.container_id;
+        device.pnp = Some(properties.pnp.clone());
+        if let Some(config) = &device.configuration {
+            device.functions = get_device_functions(
+                &device.descriptor,
+                config,
+                &properties.pnp.instance_id,
+                index,
+            );
         }
-    } else {
-        return Err(get_error());
     }
+    device.serial_ignored = instance_id::is_serial_ignored(&device);
+    device.driver_key_name = driverkey;
+    Ok(device)
 }
 
-fn get_driverkey_name(handle: HANDLE, port_number: u8) -> Result<String, String> {
-    let retbytes: Option<*mut u32> = Some(0 as *mut u32);
-
-    let mut inbuf = USB_NODE_CONNECTION_DRIVERKEY_NAME::default();
-    inbuf.ConnectionIndex = port_number as u32;
-    let inbuf_ptr: *mut c_void = get_mut_ptr(&mut inbuf);
+fn get_connection_info_v2(
+    backend: &dyn Backend,
+    handle: HANDLE,
+    port_number: u8,
+) -> Result<ConnectionInfoV2, String> {
+    let request = ConnectionInfoV2::request(port_number);
+    let mut outbuf = [0u8; CONNECTION_INFORMATION_EX_V2_SIZE];
+    let returned = backend.ioctl(
+        handle,
+        IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX_V2,
+        &request,
+        &mut outbuf,
+    )?;
+    ConnectionInfoV2::from_bytes(&outbuf[..returned])
+}
 
-    let mut outbuf: [u16; (MAX_PATH) as usize] = [0; (MAX_PATH) as usize];
-    let outbuf_ptr = get_mut_ptr(&mut outbuf);
+fn get_port_connector_properties(
+    backend: &dyn Backend,
+    handle: HANDLE,
+    port_number: u8,
+) -> Result<PortConnectorProperties, String> {
+    let outbuf = sized_ioctl(
+        backend,
+        handle,
+        IOCTL_USB_GET_PORT_CONNECTOR_PROPERTIES,
+        PORT_CONNECTOR_PROPERTIES_NAME_OFFSET + 2,
+        |_| PortConnectorProperties::request(port_number).to_vec(),
+        PortConnectorProperties::required_size,
+    )?;
+    PortConnectorProperties::from_bytes(&outbuf)
+}
 
-    let result = unsafe {
-        DeviceIoControl(
-            handle,
-            IOCTL_USB_GET_NODE_CONNECTION_DRIVERKEY_NAME,
-            Some(inbuf_ptr),
-            (std::mem::size_of::<USB_NODE_CONNECTION_DRIVERKEY_NAME>()) as u32,
-            Some(outbuf_ptr),
-            (outbuf.len() * std::mem::size_of::<u16>()) as u32,
-            retbytes,
-            None,
-        )
-    };
+fn get_driverkey_name(
+    backend: &dyn Backend,
+    handle: HANDLE,
+    port_number: u8,
+) -> Result<String, String> {
+    let outbuf = sized_ioctl(
+        backend,
+        handle,
+        IOCTL_USB_GET_NODE_CONNECTION_DRIVERKEY_NAME,
+        LAYOUT.connection_name_size,
+        |_| layout::connection_name_request(port_number),
+        layout::connection_name_length,
+    )?;
+    layout::connection_name(&outbuf)
+}
 
-    return if result == BOOL(1) {
-        let start = (std::mem::size_of::<USB_NODE_CONNECTION_DRIVERKEY_NAME>() - 1) / 2; // DriverKeyName has 4 bytes, divide by 2 for u16
-        let b = String::from_utf16_lossy(&outbuf[start..]);
-        let b = b.trim_end_matches('\0');
-        Ok(b.to_string())
-    } else {
-        Err(get_error())
-    };
+/// Hub found on a port, enumerated after the ports of its parent
+struct ChildHub {
+    hub_id: String,
+    port_number: u8,
+    descriptor: UsbDeviceDescriptor,
 }
 
-fn get_hub_devices(hub: String, hub_list: &mut Vec<Hub>, lang_id: u16) -> Result<Hub, String> {
+/// Reads the ports and devices of one hub, downstream hubs are only returned
+fn read_hub(
+    backend: &dyn Backend,
+    hub: String,
+    lang_id: u16,
+    index: &DeviceIndex,
+    on_device: &mut dyn FnMut(&str, &Device),
+) -> Result<(Hub, Vec<ChildHub>), String> {
     let mut hub_results = Vec::new();
-    return match open_device(&mut hub.clone()) {
-        Ok(hub_handle) => match get_number_of_ports(hub_handle) {
-            Ok(number_of_ports) => {
-                for port_number in 1..number_of_ports {
-                    match get_port_information(
-                        hub_handle,
-                        port_number,
-                        hub_list,
-                        hub.clone(),
-                        lang_id,
-                    ) {
-                        Ok(desc) => {
-                            let mut device = Device {
-                                port_number,
-                                container_id: "".to_string(),
-                                driver_key_name: "".to_string(),
-                                descriptor: desc,
-                            };
-                            match get_driverkey_name(hub_handle, port_number) {
-                                Ok(driverkey) => {
-                                    device.driver_key_name = driverkey.clone();
-                                    match get_all_ids() {
-                                        Ok(result) => {
-                                            for element in result {
-                                                if element.0 == driverkey {
-                                                    device.container_id = element.1;
-                                                    hub_results.push(device);
-                                                    break;
-                                                }
-                                            }
-                                        }
-                                        Err(err) => {
-                                            return Err(err);
-                                        }
-                                    };
-                                }
-                                Err(err) => {
-                                    return Err(err);
-                                }
-                            };
+    let mut ports = Vec::new();
+    let mut children = Vec::new();
+    let hub_handle = backend.open(&hub)?;
+    let result = match get_number_of_ports(backend, hub_handle) {
+        Ok(number_of_ports) => {
+            for port_number in 1..=number_of_ports {
+                // Not supported before Windows 8
+                if let Ok(connector) =
+                    get_port_connector_properties(backend, hub_handle, port_number)
+                {
+                    ports.push(connector);
+                }
+                let connection = match get_port_connection(backend, hub_handle, port_number) {
+                    Ok(connection) => connection,
+                    Err(_err) => {
+                        // NOTE: Ignore not connected/transition errors
+                        continue;
+                    }
+                };
+                if connection.is_hub {
+                    let mut desc = connection.descriptor;
+                    get_device_strings(backend, hub_handle, port_number, &mut desc, lang_id);
+                    match get_secondary_hub_name(backend, hub_handle, port_number as u32) {
+                        Ok(hub_id) => children.push(ChildHub {
+                            hub_id,
+                            port_number,
+                            descriptor: desc,
+                        }),
+                        Err(_err) => {
+                            //println!("Could not extract hub name: {}", err);
+                        }
+                    };
+                } else {
+                    match get_device(backend, hub_handle, port_number, connection, lang_id, index) {
+                        Ok(device) => {
+                            on_device(&hub, &device);
+                            hub_results.push(device);
                         }
                         Err(_err) => {
-                            // NOTE: Ignore not connected/transition/ishub errors
-                            //println!("Error: {}", err);
+                            // NOTE: The device was reset or removed while reading it,
+                            // skip it instead of dropping the whole hub
                         }
                     }
                 }
-                Ok(Hub {
+            }
+            Ok((
+                Hub {
                     hub_id: hub,
                     number_of_ports,
                     devices: hub_results,
+                    ports,
                     parent_hub: None,
+                    parent_port: None,
+                    host_controller: None,
                     descriptor: None,
-                })
-            }
-            Err(err) => Err(err),
-        },
-        Err(err) => Err(err.to_string()),
+                },
+                children,
+            ))
+        }
+        Err(err) => Err(err),
     };
+    backend.close(hub_handle);
+    result
 }
 
-pub fn get_all_hubs_with_devices(lang_id: u16) -> Vec<Hub> {
+fn get_hub_devices(
+    backend: &dyn Backend,
+    hub: String,
+    hub_list: &mut Vec<Hub>,
+    lang_id: u16,
+    index: &DeviceIndex,
+    on_discovered: &mut dyn FnMut(Discovered),
+) -> Result<Hub, String> {
+    let (result, children) = read_hub(
+        backend,
+        hub.clone(),
+        lang_id,
+        index,
+        &mut |hub_id, device| on_discovered(Discovered::Device { hub_id, device }),
+    )?;
+    for child_hub in children {
+        match get_hub_devices(
+            backend,
+            child_hub.hub_id,
+            hub_list,
+            lang_id,
+            index,
+            on_discovered,
+        ) {
+            Ok(mut child) => {
+                child.parent_hub = Some(hub.clone());
+                child.parent_port = Some(child_hub.port_number);
+                child.descriptor = Some(child_hub.descriptor);
+                on_discovered(Discovered::Hub(&child));
+                hub_list.push(child);
+            }
+            Err(_err) => {
+                //println!("Could not extract hub devices: {}", err);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Enumerates all hubs and devices like `get_all_hubs_with_devices`, but reports
+/// every device and hub as soon as it was read. Hubs are reported after their devices.
+/// The topology derived `location` is only filled in the returned hubs.
+pub fn enumerate(lang_id: u16, on_discovered: impl FnMut(Discovered)) -> Enumeration {
+    enumerate_with(&Win32Backend, lang_id, on_discovered)
+}
+
+/// Same as `enumerate`, but with the given backend, e.g. a `Recorder`
+pub fn enumerate_with(
+    backend: &dyn Backend,
+    lang_id: u16,
+    mut on_discovered: impl FnMut(Discovered),
+) -> Enumeration {
     let mut results: Vec<Hub> = Vec::new();
+    let mut errors: Vec<String> = Vec::new();
+    let index = build_device_index(backend, &mut errors);
     for root_hub_number in 0..0xff {
-        let root_hub = format!("\\\\.\\HCD{}", root_hub_number);
-        match open_device(&mut root_hub.clone()) {
-            Ok(handle) => match get_root_hub_name(handle) {
-                Ok(hub) => match get_hub_devices(hub, &mut results, lang_id) {
-                    Ok(hub) => {
-                        results.push(hub);
-                    }
+        let root_hub = format!("HCD{}", root_hub_number);
+        let handle = match backend.open(&root_hub) {
+            Ok(handle) => handle,
+            Err(_err) => continue, // Hub does not exist
+        };
+        match get_root_hub_name(backend, handle) {
+            Ok(hub) => match get_hub_devices(
+                backend,
+                hub.clone(),
+                &mut results,
+                lang_id,
+                &index,
+                &mut on_discovered,
+            ) {
+                Ok(mut root_hub) => {
+                    root_hub.host_controller =
+                        Some(get_host_controller(backend, handle, root_hub_number, &hub));
+                    on_discovered(Discovered::Hub(&root_hub));
+                    results.push(root_hub);
+                }
+                Err(err) => errors.push(err),
+            },
+            Err(err) => errors.push(err),
+        }
+        backend.close(handle);
+    }
+    topology::assign_locations(&mut results);
+    topology::assign_connectors(&mut results);
+    Enumeration {
+        hubs: results,
+        errors,
+    }
+}
+
+/// The hubs which could be read, `enumerate` also returns the errors
+pub fn get_all_hubs_with_devices(lang_id: u16) -> Vec<Hub> {
+    enumerate(lang_id, |_| {}).hubs
+}
+
+pub fn get_all_hubs_with_devices_with(backend: &dyn Backend, lang_id: u16) -> Vec<Hub> {
+    enumerate_with(backend, lang_id, |_| {}).hubs
+}
+
+enum EnumerationJob {
+    /// Probe `\\.\HCD<n>`
+    Controller(u8),
+    Hub(Box<HubJob>),
+}
+
+struct HubJob {
+    hub_id: String,
+    controller: u8,
+    /// Ports from the root hub to this hub
+    ports: Vec<u8>,
+    parent: Option<(String, ChildHub)>,
+    host_controller: Option<HostController>,
+}
+
+/// Same result as `get_all_hubs_with_devices`, but host controllers and hubs are
+/// enumerated concurrently on up to `threads` threads
+pub fn get_all_hubs_with_devices_parallel(lang_id: u16, threads: usize) -> Vec<Hub> {
+    enumerate_parallel_with(&Win32Backend, lang_id, threads).hubs
+}
+
+/// Same result as `enumerate_with`, but host controllers and hubs are enumerated
+/// concurrently on up to `threads` threads
+pub fn enumerate_parallel_with(backend: &dyn Backend, lang_id: u16, threads: usize) -> Enumeration {
+    let errors = Mutex::new(Vec::new());
+    let index = build_device_index(backend, &mut errors.lock().unwrap());
+    let jobs = (0..0xff).map(EnumerationJob::Controller).collect();
+    let mut results = pool::run(threads, jobs, |job, add_job| match job {
+        EnumerationJob::Controller(root_hub_number) => {
+            let root_hub = format!("HCD{}", root_hub_number);
+            let handle = match backend.open(&root_hub) {
+                Ok(handle) => handle,
+                Err(_err) => return None, // Hub does not exist
+            };
+            match get_root_hub_name(backend, handle) {
+                Ok(hub) => add_job(EnumerationJob::Hub(Box::new(HubJob {
+                    hub_id: hub.clone(),
+                    controller: root_hub_number,
+                    ports: Vec::new(),
+                    parent: None,
+                    host_controller: Some(get_host_controller(
+                        backend,
+                        handle,
+                        root_hub_number,
+                        &hub,
+                    )),
+                }))),
+                Err(err) => errors.lock().unwrap().push(err),
+            }
+            backend.close(handle);
+            None
+        }
+        EnumerationJob::Hub(job) => {
+            let HubJob {
+                hub_id,
+                controller,
+                ports,
+                parent,
+                host_controller,
+            } = *job;
+            let (mut hub, children) =
+                match read_hub(backend, hub_id.clone(), lang_id, &index, &mut |_, _| {}) {
+                    Ok(result) => result,
                     Err(err) => {
-                        println!("{}", err);
+                        if host_controller.is_some() {
+                            errors.lock().unwrap().push(err);
+                        }
+                        return None;
                     }
-                },
-                Err(err) => {
-                    println!("Error: {}", err);
+                };
+            for child in children {
+                let mut child_ports = ports.clone();
+                child_ports.push(child.port_number);
+                add_job(EnumerationJob::Hub(Box::new(HubJob {
+                    hub_id: child.hub_id.clone(),
+                    controller,
+                    ports: child_ports,
+                    parent: Some((hub_id.clone(), child)),
+                    host_controller: None,
+                })));
+            }
+            if let Some((parent_hub, child)) = parent {
+                hub.parent_hub = Some(parent_hub);
+                hub.parent_port = Some(child.port_number);
+                hub.descriptor = Some(child.descriptor);
+            }
+            hub.host_controller = host_controller;
+            Some(((controller, ports), hub))
+        }
+    });
+    results.sort_by(|a, b| topology::sequential_order(&a.0, &b.0));
+    let mut results: Vec<Hub> = results.into_iter().map(|(_, hub)| hub).collect();
+    topology::assign_locations(&mut results);
+    topology::assign_connectors(&mut results);
+    Enumeration {
+        hubs: results,
+        errors: errors.into_inner().unwrap(),
+    }
+}
+
+/// Watches for attached, detached and changed devices by enumerating repeatedly,
+/// e.g. `for event in watch(lang_id, WatchOptions::default()) { ... }`
+pub fn watch(lang_id: u16, options: WatchOptions) -> Watcher<impl FnMut() -> Vec<Hub> + Send> {
+    watch_with(Win32Backend, lang_id, options)
+}
+
+pub fn watch_with<B: Backend + Send + 'static>(
+    backend: B,
+    lang_id: u16,
+    options: WatchOptions,
+) -> Watcher<impl FnMut() -> Vec<Hub> + Send> {
+    Watcher::new(
+        move || get_all_hubs_with_devices_with(&backend, lang_id),
+        options,
+    )
+}
+
+struct Search<'a> {
+    backend: &'a dyn Backend,
+    query: &'a DeviceQuery,
+    /// Driver keys of the device nodes matching an instance or container ID query
+    driver_keys: Option<Vec<String>>,
+    lang_id: u16,
+    index: &'a DeviceIndex,
+    first_only: bool,
+    results: Vec<Device>,
+}
+
+impl Search<'_> {
+    fn done(&self) -> bool {
+        self.first_only && !self.results.is_empty()
+    }
+
+    /// Checks everything that does not need the full device, strings are only requested if VID/PID matched
+    fn is_candidate(
+        &self,
+        handle: HANDLE,
+        port_number: u8,
+        desc: &UsbDeviceDescriptor,
+        bus: u8,
+        ports: &[u8],
+        controller_path: Option<&str>,
+    ) -> bool {
+        if !self.query.matches_location(bus, ports, controller_path)
+            || !self.query.matches_ids(desc.idVendor, desc.idProduct)
+        {
+            return false;
+        }
+        if let Some(driver_keys) = &self.driver_keys {
+            match get_driverkey_name(self.backend, handle, port_number) {
+                Ok(key) if driver_keys.iter().any(|k| k.eq_ignore_ascii_case(&key)) => {}
+                _ => return false,
+            }
+        }
+        if let DeviceQuery::VidPid {
+            serial: Some(_), ..
+        } = self.query
+        {
+            let serial = get_string_fallback(
+                self.backend,
+                handle,
+                port_number,
+                desc.iSerialNumber.0,
+                self.lang_id,
+            );
+            return self.query.matches_serial(serial.as_deref());
+        }
+        true
+    }
+}
+
+fn search_hub(
+    hub: &str,
+    bus: u8,
+    ports: &mut Vec<u8>,
+    controller_path: Option<&str>,
+    search: &mut Search,
+) -> Result<(), String> {
+    let backend = search.backend;
+    let handle = backend.open(hub)?;
+    let number_of_ports = match get_number_of_ports(backend, handle) {
+        Ok(number_of_ports) => number_of_ports,
+        Err(err) => {
+            backend.close(handle);
+            return Err(err);
+        }
+    };
+    for port_number in 1..=number_of_ports {
+        if search.done() {
+            break;
+        }
+        let connection = match get_port_connection(backend, handle, port_number) {
+            Ok(connection) => connection,
+            Err(_err) => continue,
+        };
+        ports.push(port_number);
+        if connection.is_hub {
+            if search.query.may_be_behind(bus, ports, controller_path) {
+                if let Ok(child) = get_secondary_hub_name(backend, handle, port_number as u32) {
+                    // A broken downstream hub must not stop the search
+                    let _ = search_hub(&child, bus, ports, controller_path, search);
                 }
-            },
-            Err(_err) => {
-                continue; // Hub does not exist
+            }
+        } else if search.is_candidate(
+            handle,
+            port_number,
+            &connection.descriptor,
+            bus,
+            ports,
+            controller_path,
+        ) {
+            if let Ok(device) = get_device(
+                backend,
+                handle,
+                port_number,
+                connection,
+                search.lang_id,
+                search.index,
+            ) {
+                search.results.push(device);
             }
         }
+        ports.pop();
     }
-    results
+    backend.close(handle);
+    Ok(())
+}
+
+fn find(
+    backend: &dyn Backend,
+    query: &DeviceQuery,
+    lang_id: u16,
+    first_only: bool,
+) -> Result<Vec<Device>, String> {
+    let index = DeviceIndex::build(backend)?;
+    let driver_keys: Option<Vec<String>> = match query {
+        DeviceQuery::InstanceId(instance_id) => Some(
+            index
+                .iter()
+                .filter(|d| match &d.pnp.instance_id {
+                    Some(id) => id.eq_ignore_ascii_case(instance_id),
+                    None => false,
+                })
+                .filter_map(|d| d.driver_key.clone())
+                .collect(),
+        ),
+        DeviceQuery::ContainerId(container_id) => Some(
+            index
+                .iter()
+                .filter(|d| d.container_id == Some(*container_id))
+                .filter_map(|d| d.driver_key.clone())
+                .collect(),
+        ),
+        _ => None,
+    };
+    if let Some(keys) = &driver_keys {
+        if keys.is_empty() {
+            return Ok(Vec::new()); // Not present, no need to walk the bus
+        }
+    }
+
+    let mut search = Search {
+        backend,
+        query,
+        driver_keys,
+        lang_id,
+        index: &index,
+        first_only,
+        results: Vec::new(),
+    };
+    let mut errors = Vec::new();
+    for root_hub_number in 0..0xff {
+        if search.done() {
+            break;
+        }
+        let root_hub = format!("HCD{}", root_hub_number);
+        let handle = match backend.open(&root_hub) {
+            Ok(handle) => handle,
+            Err(_err) => continue, // Hub does not exist
+        };
+        let hub = get_root_hub_name(backend, handle);
+        let controller_path = match &hub {
+            Ok(hub) if query.needs_controller_path() => {
+                get_host_controller(backend, handle, root_hub_number, hub).location_path
+            }
+            _ => None,
+        };
+        backend.close(handle);
+        let hub = match hub {
+            Ok(hub) => hub,
+            Err(err) => {
+                errors.push(err);
+                continue;
+            }
+        };
+        if let Err(err) = search_hub(
+            &hub,
+            topology::bus_number(root_hub_number),
+            &mut Vec::new(),
+            controller_path.as_deref(),
+            &mut search,
+        ) {
+            errors.push(err);
+        }
+    }
+    // The device may be behind a root hub which could not be read, so not finding
+    // it is only an answer if all of them were searched
+    match errors.into_iter().next() {
+        Some(err) if search.results.is_empty() => Err(err),
+        _ => Ok(search.results),
+    }
+}
+
+/// Looks up the first device matching the query. Only the hubs which can lead to the device
+/// are walked and strings are only requested from matching devices.
+/// The topology derived `location` is not filled.
+pub fn find_device(query: &DeviceQuery, lang_id: u16) -> Result<Option<Device>, String> {
+    find_device_with(&Win32Backend, query, lang_id)
+}
+
+/// Same as `find_device`, but with the given backend
+pub fn find_device_with(
+    backend: &dyn Backend,
+    query: &DeviceQuery,
+    lang_id: u16,
+) -> Result<Option<Device>, String> {
+    Ok(find(backend, query, lang_id, true)?.into_iter().next())
+}
+
+/// Same as `find_device`, but returns all matching devices,
+/// e.g. all device nodes of a container
+pub fn find_devices(query: &DeviceQuery, lang_id: u16) -> Result<Vec<Device>, String> {
+    find_devices_with(&Win32Backend, query, lang_id)
+}
+
+/// Same as `find_devices`, but with the given backend
+pub fn find_devices_with(
+    backend: &dyn Backend,
+    query: &DeviceQuery,
+    lang_id: u16,
+) -> Result<Vec<Device>, String> {
+    find(backend, query, lang_id, false)
 }
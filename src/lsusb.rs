@@ -0,0 +1,355 @@
+use crate::connection::UsbSpeed;
+use crate::descriptor::{Device, Hub, UsbConfigurationDescriptor, UsbDeviceDescriptor};
+use crate::report::{Descriptors, Field, Section};
+use crate::topology::{
+    bus_port_path, hub_numbers, location_information, sequential_order, DeviceLocation,
+};
+
+// Text output of usbutils' `lsusb -v` and `lsusb -t`, both the old (`Bus 02.Port 1`) and
+// the zero padded format of usbutils 017 (`Bus 002.Port 001`) are accepted.
+
+/// One device of `lsusb -v`
+#[derive(Debug, Clone)]
+pub struct LsusbDevice {
+    pub bus: u8,
+    pub address: u8,
+    pub descriptor: UsbDeviceDescriptor,
+    /// All configurations, lsusb does not tell which one is active
+    pub configurations: Vec<UsbConfigurationDescriptor>,
+    /// nNbrPorts of the hub descriptor
+    pub number_of_ports: Option<u8>,
+}
+
+/// One device of `lsusb -t`, devices with several interfaces are only reported once
+#[derive(Debug, Clone)]
+pub struct LsusbTreeNode {
+    pub bus: u8,
+    /// Port of every hop from the root hub, empty for the root hub itself
+    pub ports: Vec<u8>,
+    pub address: u8,
+    pub speed: Option<UsbSpeed>,
+    /// From the port count of the driver, e.g. `Driver=hub/4p`
+    pub number_of_ports: Option<u8>,
+}
+
+fn section(header: &str) -> Section {
+    match header {
+        "Device Descriptor:" => Section::Device,
+        "Configuration Descriptor:" => Section::Configuration,
+        "Interface Descriptor:" => Section::Interface,
+        "Endpoint Descriptor:" => Section::Endpoint,
+        "Interface Association:" => Section::Association,
+        _ if header.ends_with("Hub Descriptor:") => Section::Hub,
+        _ => Section::Other,
+    }
+}
+
+/// A `name value description` line, e.g. `iManufacturer 3 Linux Foundation`.
+/// The description is the string of string index fields, it is missing if lsusb
+/// could not open the device.
+fn parse_field(line: &str) -> Field<'_> {
+    let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim_start();
+    let (value, description) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let description = description.trim();
+    Field {
+        name,
+        value,
+        string: (!description.is_empty()).then_some(description),
+    }
+}
+
+/// `Bus 001 Device 002: ID 8087:0024 Intel Corp. Integrated Rate Matching Hub`
+fn parse_bus_line(line: &str) -> Option<(u8, u8)> {
+    let rest = line.strip_prefix("Bus ")?;
+    let (bus, rest) = rest.split_once(" Device ")?;
+    let (address, _) = rest.split_once(':')?;
+    Some((bus.trim().parse().ok()?, address.trim().parse().ok()?))
+}
+
+/// Parses the output of `lsusb -v`. Class specific descriptors are skipped.
+pub fn parse_verbose(text: &str) -> Result<Vec<LsusbDevice>, String> {
+    let mut devices: Vec<(u8, u8, Descriptors)> = Vec::new();
+    // Open sections with their indentation
+    let mut sections: Vec<(usize, Section)> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if trimmed.starts_with("Bus ") && trimmed.contains(": ID ") {
+            let (bus, address) = parse_bus_line(trimmed)
+                .ok_or_else(|| format!("Invalid device line {}: {}", number + 1, trimmed))?;
+            devices.push((bus, address, Descriptors::default()));
+            sections.clear();
+            continue;
+        }
+        let descriptors = match devices.last_mut() {
+            Some((_, _, descriptors)) => descriptors,
+            None => continue, // Warnings before the first device
+        };
+
+        let indent = line.len() - line.trim_start().len();
+        while matches!(sections.last(), Some((open, _)) if *open >= indent) {
+            sections.pop();
+        }
+        if trimmed.ends_with(':') {
+            // Ignore descriptors nested in unknown sections, e.g. of a device qualifier
+            let kind = match sections.last() {
+                Some((_, Section::Other)) => Section::Other,
+                _ => section(trimmed),
+            };
+            descriptors.begin(kind);
+            sections.push((indent, kind));
+        } else if let Some((_, kind)) = sections.last() {
+            descriptors.apply(*kind, &parse_field(trimmed));
+        }
+    }
+    if devices.is_empty() {
+        return Err("No devices found in the lsusb output".to_string());
+    }
+    Ok(devices
+        .into_iter()
+        .map(|(bus, address, descriptors)| LsusbDevice {
+            bus,
+            address,
+            descriptor: descriptors.descriptor,
+            configurations: descriptors.configurations,
+            number_of_ports: descriptors.number_of_ports,
+        })
+        .collect())
+}
+
+/// `480M`, SuperSpeedPlus with two lanes is `20000M/x2`
+fn parse_speed(speed: &str) -> Option<UsbSpeed> {
+    let speed = speed.split_once('/').map_or(speed, |(speed, _lanes)| speed);
+    match speed.strip_suffix('M')? {
+        "1.5" => Some(UsbSpeed::Low),
+        "12" => Some(UsbSpeed::Full),
+        "480" => Some(UsbSpeed::High),
+        mbits => match mbits.parse::<u32>() {
+            Ok(mbits) if mbits >= 5000 => Some(UsbSpeed::Super),
+            _ => None,
+        },
+    }
+}
+
+/// `Dev 2, If 0, Class=Hub, Driver=hub/4p, 480M`
+fn parse_tree_attributes(node: &mut LsusbTreeNode, attributes: &str) -> Option<()> {
+    for attribute in attributes.split(", ") {
+        let attribute = attribute.trim();
+        if let Some(address) = attribute.strip_prefix("Dev ") {
+            node.address = address.trim().parse().ok()?;
+        } else if let Some(driver) = attribute.strip_prefix("Driver=") {
+            node.number_of_ports = driver
+                .split_once('/')
+                .and_then(|(_, ports)| ports.strip_suffix('p'))
+                .and_then(|ports| ports.parse().ok());
+        } else if attribute.ends_with('M') || attribute.contains("M/x") {
+            node.speed = parse_speed(attribute);
+        }
+    }
+    Some(())
+}
+
+/// Parses the output of `lsusb -t`
+pub fn parse_tree(text: &str) -> Result<Vec<LsusbTreeNode>, String> {
+    let mut nodes: Vec<LsusbTreeNode> = Vec::new();
+    let mut bus = None;
+    let mut path: Vec<u8> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let invalid = || format!("Invalid tree line {}: {}", number + 1, line.trim());
+        let (node, attributes) = if let Some(root) = line.trim().strip_prefix("/:") {
+            // `/:  Bus 02.Port 1: Dev 1, Class=root_hub, Driver=xhci_hcd/6p, 5000M`
+            let (head, attributes) = root.split_once(": ").ok_or_else(invalid)?;
+            let number = head
+                .trim()
+                .strip_prefix("Bus ")
+                .and_then(|head| head.split('.').next())
+                .and_then(|number| number.parse().ok())
+                .ok_or_else(invalid)?;
+            bus = Some(number);
+            path.clear();
+            let node = LsusbTreeNode {
+                bus: number,
+                ports: Vec::new(),
+                address: 0,
+                speed: None,
+                number_of_ports: None,
+            };
+            (node, attributes)
+        } else if let Some(position) = line.find("|__ ") {
+            // `    |__ Port 1: Dev 2, If 0, Class=Mass Storage, Driver=usb-storage, 5000M`
+            let bus = bus.ok_or_else(invalid)?;
+            let depth = position / 4;
+            let (head, attributes) = line[position + 4..].split_once(": ").ok_or_else(invalid)?;
+            let port = head
+                .strip_prefix("Port ")
+                .and_then(|port| port.trim().parse().ok())
+                .ok_or_else(invalid)?;
+            if depth == 0 || depth > path.len() + 1 {
+                return Err(invalid());
+            }
+            path.truncate(depth - 1);
+            path.push(port);
+            let node = LsusbTreeNode {
+                bus,
+                ports: path.clone(),
+                address: 0,
+                speed: None,
+                number_of_ports: None,
+            };
+            (node, attributes)
+        } else {
+            continue; // `ID 1d6b:0003 ...` lines of `lsusb -tv`
+        };
+
+        let mut node = node;
+        parse_tree_attributes(&mut node, attributes).ok_or_else(invalid)?;
+        // Every interface has its own line
+        if !nodes
+            .iter()
+            .any(|n| n.bus == node.bus && n.ports == node.ports)
+        {
+            nodes.push(node);
+        }
+    }
+    if nodes.is_empty() {
+        return Err("No buses found in the lsusb tree".to_string());
+    }
+    Ok(nodes)
+}
+
+/// Linux names root hubs `usb1` and other hubs by their bus/port path, e.g. `1-4.2`
+fn hub_id(bus: u8, ports: &[u8]) -> String {
+    if ports.is_empty() {
+        format!("usb{}", bus)
+    } else {
+        bus_port_path(bus, ports)
+    }
+}
+
+fn empty_hub(hub_id: String, number_of_ports: u8) -> Hub {
+    Hub {
+        hub_id,
+        number_of_ports,
+        devices: Vec::new(),
+        ports: Vec::new(),
+        parent_hub: None,
+        parent_port: None,
+        host_controller: None,
+        descriptor: None,
+    }
+}
+
+fn to_device(source: &LsusbDevice, port_number: u8, speed: Option<UsbSpeed>) -> Device {
+    Device::from_descriptors(
+        port_number,
+        speed,
+        source.descriptor.clone(),
+        source.configurations.first().cloned(),
+    )
+}
+
+/// Builds the hubs from `lsusb -v` and optionally `lsusb -t` output.
+/// Without the tree the topology is unknown: every bus becomes a root hub and all
+/// other devices, hubs included, are listed on it with port 0.
+pub fn import(verbose: &str, tree: Option<&str>) -> Result<Vec<Hub>, String> {
+    let devices = parse_verbose(verbose)?;
+    match tree {
+        Some(tree) => build_hubs(&devices, &parse_tree(tree)?),
+        None => Ok(import_flat(&devices)),
+    }
+}
+
+/// Places the devices on the hubs of the tree, every device of the tree must be listed.
+/// The hubs are in the order of the Windows enumeration, child hubs before their parent.
+pub(crate) fn build_hubs(
+    devices: &[LsusbDevice],
+    tree: &[LsusbTreeNode],
+) -> Result<Vec<Hub>, String> {
+    let find = |bus: u8, address: u8| {
+        devices
+            .iter()
+            .find(|d| d.bus == bus && d.address == address)
+    };
+    // Bus and ports of every hub for the order
+    let mut hubs: Vec<((u8, Vec<u8>), Hub)> = Vec::new();
+    // Hub, device index and ports of every device for the location
+    let mut placed: Vec<(String, usize, u8, Vec<u8>)> = Vec::new();
+    for node in tree {
+        let source = find(node.bus, node.address);
+        let is_hub = node.ports.is_empty()
+            || node.number_of_ports.is_some()
+            || source.is_some_and(|s| s.descriptor.bDeviceClass == 0x09);
+        let parent = node
+            .ports
+            .split_last()
+            .map(|(port, upstream)| (hub_id(node.bus, upstream), *port));
+        if is_hub {
+            let number_of_ports = node
+                .number_of_ports
+                .or(source.and_then(|s| s.number_of_ports))
+                .unwrap_or(0);
+            let mut hub = empty_hub(hub_id(node.bus, &node.ports), number_of_ports);
+            hub.descriptor = source.map(|s| s.descriptor.clone());
+            if let Some((parent_hub, parent_port)) = parent {
+                hub.parent_hub = Some(parent_hub);
+                hub.parent_port = Some(parent_port);
+            }
+            hubs.push(((node.bus, node.ports.clone()), hub));
+            continue;
+        }
+
+        let source = source.ok_or_else(|| {
+            format!(
+                "Device {} is missing in the verbose output",
+                bus_port_path(node.bus, &node.ports)
+            )
+        })?;
+        let (parent_hub, port_number) = parent.expect("Only root hubs have no parent");
+        let (_, hub) = hubs
+            .iter_mut()
+            .find(|(_, h)| h.hub_id == parent_hub)
+            .ok_or_else(|| format!("Hub {} is missing in the tree", parent_hub))?;
+        let device_index = hub.devices.len();
+        hub.devices.push(to_device(source, port_number, node.speed));
+        placed.push((parent_hub, device_index, node.bus, node.ports.clone()));
+    }
+
+    hubs.sort_by(|a, b| sequential_order(&a.0, &b.0));
+    let mut hubs: Vec<Hub> = hubs.into_iter().map(|(_, hub)| hub).collect();
+    let numbers = hub_numbers(&hubs);
+    for (hub_id, device_index, bus, ports) in placed {
+        let hub_index = hubs.iter().position(|h| h.hub_id == hub_id).unwrap();
+        let device = &mut hubs[hub_index].devices[device_index];
+        device.location = Some(DeviceLocation {
+            bus_path: bus_port_path(bus, &ports),
+            location_path: None,
+            location_info: location_information(device.port_number, numbers[hub_index]),
+        });
+    }
+    Ok(hubs)
+}
+
+pub(crate) fn import_flat(devices: &[LsusbDevice]) -> Vec<Hub> {
+    let mut hubs: Vec<Hub> = Vec::new();
+    for device in devices {
+        let hub_id = hub_id(device.bus, &[]);
+        let index = match hubs.iter().position(|h| h.hub_id == hub_id) {
+            Some(index) => index,
+            None => {
+                hubs.push(empty_hub(hub_id, 0));
+                hubs.len() - 1
+            }
+        };
+        // The root hub is always the first device on the bus
+        if device.address == 1 {
+            hubs[index].number_of_ports = device.number_of_ports.unwrap_or(0);
+            hubs[index].descriptor = Some(device.descriptor.clone());
+        } else {
+            hubs[index].devices.push(to_device(device, 0, None));
+        }
+    }
+    hubs
+}
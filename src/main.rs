@@ -1,10 +1,86 @@
-use lswinusb::get_all_hubs_with_devices;
+use lswinusb::backend::Win32Backend;
+use lswinusb::record::{Recorder, Recording};
+use lswinusb::replay::ReplayBackend;
+use lswinusb::selector::Selector;
+use lswinusb::{enumerate, enumerate_with, Enumeration};
 use windows::Win32::Globalization::GetSystemDefaultLangID;
 
 fn main() {
+    // `lswinusb [--record <file> | --replay <file>] [selector]`, e.g. `lswinusb vid=0x0483 pid=0xdf11`
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut record_path: Option<String> = None;
+    let mut replay_path: Option<String> = None;
+    if let Some(option) = args.first().cloned() {
+        if option == "--record" || option == "--replay" {
+            if args.len() < 2 {
+                eprintln!("{} needs a file name", option);
+                std::process::exit(2);
+            }
+            let path = args.remove(1);
+            args.remove(0);
+            if option == "--record" {
+                record_path = Some(path);
+            } else {
+                replay_path = Some(path);
+            }
+        }
+    }
+    let selector = match Selector::parse(&args.join(" ")) {
+        Ok(selector) => selector,
+        Err(err) => {
+            eprintln!("Invalid selector: {}", err);
+            std::process::exit(2);
+        }
+    };
+
     let lang_id;
     unsafe { lang_id = GetSystemDefaultLangID() } // Windows uses localized descriptors...
-    let res = serde_json::to_string_pretty(&get_all_hubs_with_devices(lang_id))
-        .expect("This must be a struct");
+    let enumeration: Enumeration = match (&record_path, &replay_path) {
+        (_, Some(path)) => {
+            let backend = std::fs::read_to_string(path)
+                .map_err(|err| err.to_string())
+                .and_then(|data| {
+                    serde_json::from_str::<Recording>(&data).map_err(|err| err.to_string())
+                })
+                .and_then(ReplayBackend::new);
+            let backend = match backend {
+                Ok(backend) => backend,
+                Err(err) => {
+                    eprintln!("Could not read {}: {}", path, err);
+                    std::process::exit(1);
+                }
+            };
+            let lang_id = backend.lang_id().unwrap_or(lang_id);
+            let enumeration = enumerate_with(&backend, lang_id, |_| {});
+            for request in backend.unmatched() {
+                eprintln!("Not recorded: {}", request);
+            }
+            enumeration
+        }
+        (Some(path), None) => {
+            let recorder = Recorder::new(Win32Backend);
+            let enumeration = enumerate_with(&recorder, lang_id, |_| {});
+            let mut recording = recorder.into_recording();
+            recording.lang_id = Some(lang_id);
+            let recording =
+                serde_json::to_string_pretty(&recording).expect("This must be a struct");
+            if let Err(err) = std::fs::write(path, recording) {
+                eprintln!("Could not write {}: {}", path, err);
+                std::process::exit(1);
+            }
+            enumeration
+        }
+        (None, None) => enumerate(lang_id, |_| {}),
+    };
+    for err in &enumeration.errors {
+        eprintln!("Error: {}", err);
+    }
+    let hubs = enumeration.hubs;
+    let res = if selector.terms.is_empty() {
+        serde_json::to_string_pretty(&hubs)
+    } else {
+        serde_json::to_string_pretty(&selector.select(&hubs))
+    }
+    .expect("This must be a struct");
     println!("{}", res);
 }
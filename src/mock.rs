@@ -0,0 +1,620 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use windows::Win32::Devices::Usb::{
+    IOCTL_GET_HCD_DRIVERKEY_NAME, IOCTL_USB_GET_DESCRIPTOR_FROM_NODE_CONNECTION,
+    IOCTL_USB_GET_NODE_CONNECTION_DRIVERKEY_NAME, IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX,
+    IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX_V2, IOCTL_USB_GET_NODE_CONNECTION_NAME,
+    IOCTL_USB_GET_NODE_INFORMATION, IOCTL_USB_GET_PORT_CONNECTOR_PROPERTIES,
+    IOCTL_USB_GET_ROOT_HUB_NAME,
+};
+use windows::Win32::Foundation::HANDLE;
+
+use crate::backend::Backend;
+use crate::connection::{PortConnectorProperties, UsbSpeed, PORT_CONNECTOR_PROPERTIES_NAME_OFFSET};
+use crate::descriptor::{UsbDeviceDescriptor, USB_DEVICE_DESCRIPTOR_SIZE};
+use crate::layout::{DEVICE_CONNECTED, LAYOUT, NO_DEVICE_CONNECTED};
+use crate::property::{DeviceProperties, PnpProperties};
+
+// Simulated USB stack for tests and benchmarks. The IOCTLs are answered with the same
+// packed buffers as the hub driver, including the short answers to undersized requests,
+// so the enumeration runs unchanged on any platform.
+
+/// A device which is not a hub
+#[derive(Debug, Clone)]
+pub struct MockDevice {
+    /// The strings in the descriptor are answered as string descriptors
+    pub descriptor: UsbDeviceDescriptor,
+    pub speed: UsbSpeed,
+    /// Complete configuration descriptors with interfaces and endpoints, in index order
+    pub configurations: Vec<Vec<u8>>,
+    /// bConfigurationValue of the active configuration, 0 if not configured
+    pub active_configuration: u8,
+    /// Further string descriptors, e.g. of the configuration
+    pub strings: Vec<(u8, String)>,
+    pub driver_key: String,
+    /// Device node of the device, None if Windows did not create one
+    pub properties: Option<DeviceProperties>,
+}
+
+impl MockDevice {
+    /// Full speed device with one vendor specific interface and a device node
+    pub fn new(vendor_id: u16, product_id: u16, driver_key: &str) -> MockDevice {
+        let descriptor = UsbDeviceDescriptor {
+            bLength: USB_DEVICE_DESCRIPTOR_SIZE as u8,
+            bDescriptorType: 1,
+            bcdUSB: 0x0200,
+            bMaxPacketSize0: 64,
+            idVendor: vendor_id,
+            idProduct: product_id,
+            bcdDevice: 0x0100,
+            bNumConfigurations: 1,
+            ..Default::default()
+        };
+        let properties = DeviceProperties {
+            driver_key: Some(driver_key.to_string()),
+            parent: None,
+            container_id: None,
+            pnp: PnpProperties {
+                instance_id: Some(format!(
+                    r"USB\VID_{:04X}&PID_{:04X}\5&{}",
+                    vendor_id,
+                    product_id,
+                    driver_key.rsplit('\\').next().unwrap_or(driver_key)
+                )),
+                service: Some("WinUSB".to_string()),
+                ..Default::default()
+            },
+        };
+        MockDevice {
+            descriptor,
+            speed: UsbSpeed::Full,
+            configurations: vec![vendor_configuration()],
+            active_configuration: 1,
+            strings: Vec::new(),
+            driver_key: driver_key.to_string(),
+            properties: Some(properties),
+        }
+    }
+
+    /// Adds manufacturer, product and serial number strings. Windows derives the
+    /// instance ID from the serial number.
+    pub fn with_strings(mut self, manufacturer: &str, product: &str, serial: Option<&str>) -> Self {
+        self.descriptor.iManufacturer = (1, Some(manufacturer.to_string()));
+        self.descriptor.iProduct = (2, Some(product.to_string()));
+        if let Some(serial) = serial {
+            self.descriptor.iSerialNumber = (3, Some(serial.to_string()));
+            if let Some(properties) = &mut self.properties {
+                properties.pnp.instance_id = Some(format!(
+                    r"USB\VID_{:04X}&PID_{:04X}\{}",
+                    self.descriptor.idVendor, self.descriptor.idProduct, serial
+                ));
+            }
+        }
+        self
+    }
+
+    /// SuperSpeed device, the BOS descriptor is not answered
+    pub fn with_speed(mut self, speed: UsbSpeed) -> Self {
+        self.speed = speed;
+        if speed == UsbSpeed::Super {
+            self.descriptor.bcdUSB = 0x0320;
+            self.descriptor.bMaxPacketSize0 = 9;
+        }
+        self
+    }
+
+    fn string(&self, index: u8) -> Option<&str> {
+        let descriptor = &self.descriptor;
+        [
+            &descriptor.iManufacturer,
+            &descriptor.iProduct,
+            &descriptor.iSerialNumber,
+        ]
+        .into_iter()
+        .find(|(i, _)| *i == index)
+        .and_then(|(_, s)| s.as_deref())
+        .or_else(|| {
+            self.strings
+                .iter()
+                .find(|(i, _)| *i == index)
+                .map(|(_, s)| s.as_str())
+        })
+    }
+}
+
+/// Configuration with one vendor specific interface and a bulk IN and OUT endpoint
+fn vendor_configuration() -> Vec<u8> {
+    vec![
+        9, 2, 32, 0, 1, 1, 0, 0x80, 50, // Configuration
+        9, 4, 0, 0, 2, 0xff, 0, 0, 0, // Interface
+        7, 5, 0x81, 2, 64, 0, 0, // Endpoint 1 IN
+        7, 5, 0x01, 2, 64, 0, 0, // Endpoint 1 OUT
+    ]
+}
+
+fn device_descriptor_bytes(descriptor: &UsbDeviceDescriptor) -> [u8; USB_DEVICE_DESCRIPTOR_SIZE] {
+    let mut buf = [0u8; USB_DEVICE_DESCRIPTOR_SIZE];
+    buf[0] = descriptor.bLength;
+    buf[1] = descriptor.bDescriptorType;
+    buf[2..4].copy_from_slice(&descriptor.bcdUSB.to_le_bytes());
+    buf[4] = descriptor.bDeviceClass;
+    buf[5] = descriptor.bDeviceSubClass;
+    buf[6] = descriptor.bDeviceProtocol;
+    buf[7] = descriptor.bMaxPacketSize0;
+    buf[8..10].copy_from_slice(&descriptor.idVendor.to_le_bytes());
+    buf[10..12].copy_from_slice(&descriptor.idProduct.to_le_bytes());
+    buf[12..14].copy_from_slice(&descriptor.bcdDevice.to_le_bytes());
+    buf[14] = descriptor.iManufacturer.0;
+    buf[15] = descriptor.iProduct.0;
+    buf[16] = descriptor.iSerialNumber.0;
+    buf[17] = descriptor.bNumConfigurations;
+    buf
+}
+
+#[derive(Debug, Clone)]
+pub enum MockPort {
+    Device(Box<MockDevice>),
+    Hub(MockHub),
+}
+
+/// A root or downstream hub
+#[derive(Debug, Clone)]
+pub struct MockHub {
+    /// Symbolic link name returned for the hub, without the `\\.\` prefix
+    pub name: String,
+    pub descriptor: UsbDeviceDescriptor,
+    /// Index 0 is port 1, None is an empty port
+    pub ports: Vec<Option<MockPort>>,
+    /// Answers of IOCTL_USB_GET_PORT_CONNECTOR_PROPERTIES, ports without are not supported
+    pub connectors: Vec<PortConnectorProperties>,
+}
+
+impl MockHub {
+    pub fn new(name: &str, number_of_ports: u8) -> MockHub {
+        let descriptor = UsbDeviceDescriptor {
+            bLength: USB_DEVICE_DESCRIPTOR_SIZE as u8,
+            bDescriptorType: 1,
+            bcdUSB: 0x0200,
+            bDeviceClass: 0x09,
+            bDeviceProtocol: 1,
+            bMaxPacketSize0: 64,
+            idVendor: 0x05e3,
+            idProduct: 0x0608,
+            bNumConfigurations: 1,
+            ..Default::default()
+        };
+        MockHub {
+            name: name.to_string(),
+            descriptor,
+            ports: vec![None; number_of_ports as usize],
+            connectors: Vec::new(),
+        }
+    }
+
+    /// Plugs a device or hub into a port, starting at 1
+    pub fn attach(&mut self, port_number: u8, port: MockPort) {
+        self.ports[port_number as usize - 1] = Some(port);
+    }
+
+    fn port(&self, port_number: u32) -> Option<&MockPort> {
+        let index = (port_number as usize).checked_sub(1)?;
+        self.ports.get(index)?.as_ref()
+    }
+}
+
+/// A host controller (`\\.\HCD<n>`) with its root hub
+#[derive(Debug, Clone)]
+pub struct MockController {
+    pub driver_key: String,
+    /// Hardware IDs and location paths of the PCI device
+    pub hardware_ids: Vec<String>,
+    pub location_paths: Vec<String>,
+    pub root_hub: MockHub,
+}
+
+impl MockController {
+    pub fn new(driver_key: &str, root_hub: MockHub) -> MockController {
+        MockController {
+            driver_key: driver_key.to_string(),
+            hardware_ids: Vec::new(),
+            location_paths: Vec::new(),
+            root_hub,
+        }
+    }
+}
+
+/// Backend answering from simulated controllers, see the module comment
+pub struct MockBackend {
+    pub controllers: Vec<MockController>,
+    /// Device nodes besides the ones of the mock devices, e.g. other device classes
+    pub other_devices: Vec<DeviceProperties>,
+    /// Time every IOCTL takes, the hub driver talks to the device for most of them
+    pub ioctl_latency: Duration,
+    /// Time a SetupDi scan takes per device node
+    pub scan_latency: Duration,
+    ioctls: AtomicUsize,
+    scans: AtomicUsize,
+}
+
+/// Handles of root hubs and downstream hubs are the index of the hub plus this
+const HUB_HANDLE_BASE: isize = 0x1000;
+
+enum Node<'a> {
+    Controller(&'a MockController),
+    Hub(&'a MockHub),
+}
+
+impl MockBackend {
+    /// Controllers are opened as `HCD0`, `HCD1`, ... in the given order
+    pub fn new(controllers: Vec<MockController>) -> MockBackend {
+        MockBackend {
+            controllers,
+            other_devices: Vec::new(),
+            ioctl_latency: Duration::ZERO,
+            scan_latency: Duration::ZERO,
+            ioctls: AtomicUsize::new(0),
+            scans: AtomicUsize::new(0),
+        }
+    }
+
+    /// A machine with `controllers` root hubs of 4 ports. Port 1 has a chain of `depth`
+    /// hubs, every hub of the chain has `devices_per_hub` devices on ports 2 and up.
+    pub fn generate(controllers: usize, depth: usize, devices_per_hub: u8) -> MockBackend {
+        let mut next_key = 0;
+        let mut hub_with_devices = |name: String| {
+            let mut hub = MockHub::new(&name, devices_per_hub + 1);
+            for port in 2..=devices_per_hub + 1 {
+                let key = format!(r"{{36fc9e60-c465-11cf-8056-444553540000}}\{:04}", next_key);
+                let serial = format!("SN{:04}", next_key);
+                next_key += 1;
+                let device = MockDevice::new(0x1209, 0x0001 + port as u16, &key).with_strings(
+                    "Mock",
+                    "Device",
+                    Some(&serial),
+                );
+                hub.attach(port, MockPort::Device(Box::new(device)));
+            }
+            hub
+        };
+        let controllers = (0..controllers)
+            .map(|c| {
+                let mut hubs: Vec<MockHub> = (0..depth)
+                    .map(|d| hub_with_devices(format!("USB#VID_05E3&PID_0608#{}&{}#hub", c, d)))
+                    .collect();
+                let mut root = hub_with_devices(format!("USB#ROOT_HUB30#{}#root", c));
+                root.ports.resize(root.ports.len().max(4), None);
+                let mut child = None;
+                while let Some(mut hub) = hubs.pop() {
+                    if let Some(child) = child.take() {
+                        hub.attach(1, MockPort::Hub(child));
+                    }
+                    child = Some(hub);
+                }
+                if let Some(child) = child {
+                    root.attach(1, MockPort::Hub(child));
+                }
+                let mut controller = MockController::new(
+                    &format!(r"{{36fc9e60-c465-11cf-8056-444553540000}}\{:04}", 9000 + c),
+                    root,
+                );
+                controller.location_paths = vec![format!("PCIROOT(0)#PCI({:02X}00)", 0x14 + c)];
+                controller
+            })
+            .collect();
+        MockBackend::new(controllers)
+    }
+
+    /// Number of IOCTLs answered so far
+    pub fn ioctl_count(&self) -> usize {
+        self.ioctls.load(Ordering::Relaxed)
+    }
+
+    /// Number of SetupDi scans so far
+    pub fn scan_count(&self) -> usize {
+        self.scans.load(Ordering::Relaxed)
+    }
+
+    /// Hubs in depth first order, the handle is the index plus HUB_HANDLE_BASE
+    fn hubs(&self) -> Vec<&MockHub> {
+        fn visit<'a>(hub: &'a MockHub, hubs: &mut Vec<&'a MockHub>) {
+            hubs.push(hub);
+            for port in hub.ports.iter().flatten() {
+                if let MockPort::Hub(child) = port {
+                    visit(child, hubs);
+                }
+            }
+        }
+        let mut hubs = Vec::new();
+        for controller in &self.controllers {
+            visit(&controller.root_hub, &mut hubs);
+        }
+        hubs
+    }
+
+    fn devices(&self) -> Vec<&MockDevice> {
+        self.hubs()
+            .into_iter()
+            .flat_map(|hub| hub.ports.iter().flatten())
+            .filter_map(|port| match port {
+                MockPort::Device(device) => Some(device.as_ref()),
+                MockPort::Hub(_) => None,
+            })
+            .collect()
+    }
+
+    fn node(&self, handle: HANDLE) -> Result<Node<'_>, String> {
+        if handle.0 >= HUB_HANDLE_BASE {
+            let hubs = self.hubs();
+            return hubs
+                .get((handle.0 - HUB_HANDLE_BASE) as usize)
+                .map(|hub| Node::Hub(hub))
+                .ok_or_else(|| format!("Invalid handle {}", handle.0));
+        }
+        self.controllers
+            .get(handle.0 as usize)
+            .map(Node::Controller)
+            .ok_or_else(|| format!("Invalid handle {}", handle.0))
+    }
+}
+
+/// Copies as much of the answer as fits, like the hub driver does for the
+/// fixed size part of variable length structures
+fn answer(data: &[u8], output: &mut [u8]) -> Result<usize, String> {
+    let length = data.len().min(output.len());
+    output[..length].copy_from_slice(&data[..length]);
+    Ok(length)
+}
+
+fn utf16z(text: &str) -> Vec<u8> {
+    text.encode_utf16()
+        .chain([0])
+        .flat_map(|c| c.to_le_bytes())
+        .collect()
+}
+
+/// USB_ROOT_HUB_NAME and USB_HCD_DRIVERKEY_NAME
+fn name(text: &str) -> Vec<u8> {
+    let name = utf16z(text);
+    let mut buf = ((LAYOUT.name_offset + name.len()) as u32)
+        .to_le_bytes()
+        .to_vec();
+    buf.extend(name);
+    buf
+}
+
+/// USB_NODE_CONNECTION_NAME and USB_NODE_CONNECTION_DRIVERKEY_NAME
+fn connection_name(port_number: u32, text: &str) -> Vec<u8> {
+    let name = utf16z(text);
+    let mut buf = port_number.to_le_bytes().to_vec();
+    buf.extend(((LAYOUT.connection_name_offset + name.len()) as u32).to_le_bytes());
+    buf.extend(name);
+    buf
+}
+
+fn port_number(input: &[u8]) -> Result<u32, String> {
+    match input.get(0..4) {
+        Some(bytes) => Ok(u32::from_le_bytes(bytes.try_into().unwrap())),
+        None => Err("The parameter is incorrect.".to_string()),
+    }
+}
+
+fn not_connected(port_number: u32) -> String {
+    format!("Nothing connected to port {}", port_number)
+}
+
+/// USB_NODE_CONNECTION_INFORMATION_EX
+fn connection_information(port_number: u32, port: Option<&MockPort>) -> Vec<u8> {
+    let mut buf = vec![0u8; LAYOUT.connection_information_size];
+    buf[0..4].copy_from_slice(&port_number.to_le_bytes());
+    let status = match port {
+        Some(port) => {
+            let (descriptor, speed, is_hub) = match port {
+                MockPort::Device(device) => (&device.descriptor, device.speed, false),
+                MockPort::Hub(hub) => (&hub.descriptor, UsbSpeed::High, true),
+            };
+            let offset = LAYOUT.device_descriptor_offset;
+            buf[offset..offset + USB_DEVICE_DESCRIPTOR_SIZE]
+                .copy_from_slice(&device_descriptor_bytes(descriptor));
+            buf[LAYOUT.current_configuration_offset] = match port {
+                MockPort::Device(device) => device.active_configuration,
+                MockPort::Hub(_) => 1,
+            };
+            buf[LAYOUT.speed_offset] = speed as u8;
+            buf[LAYOUT.device_is_hub_offset] = is_hub as u8;
+            DEVICE_CONNECTED
+        }
+        None => NO_DEVICE_CONNECTED,
+    };
+    let offset = LAYOUT.connection_status_offset;
+    buf[offset..offset + 4].copy_from_slice(&status.to_le_bytes());
+    buf
+}
+
+/// USB_NODE_CONNECTION_INFORMATION_EX_V2, SuperSpeed devices run at SuperSpeed
+fn connection_information_v2(port_number: u32, speed: UsbSpeed) -> Vec<u8> {
+    let (protocols, flags): (u32, u32) = match speed {
+        UsbSpeed::Super => (0b111, 0b11),
+        _ => (0b011, 0),
+    };
+    let mut buf = port_number.to_le_bytes().to_vec();
+    buf.extend(16u32.to_le_bytes());
+    buf.extend(protocols.to_le_bytes());
+    buf.extend(flags.to_le_bytes());
+    buf
+}
+
+/// USB_PORT_CONNECTOR_PROPERTIES
+fn connector_properties(properties: &PortConnectorProperties) -> Vec<u8> {
+    let name = match &properties.companion_hub {
+        Some(hub) => utf16z(hub),
+        None => vec![0, 0],
+    };
+    let flags = properties.user_connectable as u32
+        | (properties.debug_capable as u32) << 1
+        | (properties.has_multiple_companions as u32) << 2
+        | (properties.type_c as u32) << 3;
+    let mut buf = (properties.port_number as u32).to_le_bytes().to_vec();
+    buf.extend(((PORT_CONNECTOR_PROPERTIES_NAME_OFFSET + name.len()) as u32).to_le_bytes());
+    buf.extend(flags.to_le_bytes());
+    buf.extend(properties.companion_index.to_le_bytes());
+    buf.extend(properties.companion_port_number.to_le_bytes());
+    buf.extend(name);
+    buf
+}
+
+/// Answers GET_DESCRIPTOR with the request header followed by at most wLength bytes
+fn descriptor(input: &[u8], port: Option<&MockPort>) -> Result<Vec<u8>, String> {
+    let header = LAYOUT.descriptor_data_offset;
+    if input.len() < header {
+        return Err("The parameter is incorrect.".to_string());
+    }
+    let setup = LAYOUT.descriptor_setup_offset;
+    let descriptor_index = input[setup + 2];
+    let descriptor_type = input[setup + 3];
+    let length = u16::from_le_bytes([input[setup + 6], input[setup + 7]]) as usize;
+    let port = port.ok_or_else(|| not_connected(port_number(input).unwrap_or(0)))?;
+    let data = match (port, descriptor_type) {
+        (MockPort::Device(device), 1) => device_descriptor_bytes(&device.descriptor).to_vec(),
+        (MockPort::Hub(hub), 1) => device_descriptor_bytes(&hub.descriptor).to_vec(),
+        (MockPort::Device(device), 2) => device
+            .configurations
+            .get(descriptor_index as usize)
+            .cloned()
+            .ok_or("A device attached to the system is not functioning.")?,
+        (_, 3) if descriptor_index == 0 => vec![4, 3, 0x09, 0x04],
+        (MockPort::Device(device), 3) => {
+            let string = device
+                .string(descriptor_index)
+                .ok_or("A device attached to the system is not functioning.")?;
+            let mut data = vec![0, 3];
+            data.extend(string.encode_utf16().flat_map(|c| c.to_le_bytes()));
+            data[0] = data.len() as u8;
+            data
+        }
+        (MockPort::Hub(hub), 3) => {
+            let mut data = vec![0, 3];
+            data.extend(
+                hub.name
+                    .encode_utf16()
+                    .take(20)
+                    .flat_map(|c| c.to_le_bytes()),
+            );
+            data[0] = data.len() as u8;
+            data
+        }
+        _ => return Err("A device attached to the system is not functioning.".to_string()),
+    };
+    let mut buf = input[..header].to_vec();
+    buf.extend(&data[..data.len().min(length)]);
+    Ok(buf)
+}
+
+impl Backend for MockBackend {
+    fn open(&self, name: &str) -> Result<HANDLE, String> {
+        if let Some(index) = name
+            .strip_prefix("HCD")
+            .and_then(|n| n.parse::<usize>().ok())
+        {
+            if index < self.controllers.len() {
+                return Ok(HANDLE(index as isize));
+            }
+        } else if let Some(index) = self
+            .hubs()
+            .iter()
+            .position(|hub| hub.name.eq_ignore_ascii_case(name))
+        {
+            return Ok(HANDLE(HUB_HANDLE_BASE + index as isize));
+        }
+        Err("The system cannot find the file specified.".to_string())
+    }
+
+    fn close(&self, _handle: HANDLE) {}
+
+    fn ioctl(
+        &self,
+        handle: HANDLE,
+        code: u32,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<usize, String> {
+        self.ioctls.fetch_add(1, Ordering::Relaxed);
+        if !self.ioctl_latency.is_zero() {
+            thread::sleep(self.ioctl_latency);
+        }
+        let hub = match self.node(handle)? {
+            Node::Controller(controller) => {
+                return match code {
+                    IOCTL_USB_GET_ROOT_HUB_NAME => answer(&name(&controller.root_hub.name), output),
+                    IOCTL_GET_HCD_DRIVERKEY_NAME => answer(&name(&controller.driver_key), output),
+                    _ => Err("The request is not supported.".to_string()),
+                };
+            }
+            Node::Hub(hub) => hub,
+        };
+        if code == IOCTL_USB_GET_NODE_INFORMATION {
+            let mut buf = vec![0u8; LAYOUT.node_information_size];
+            buf[LAYOUT.number_of_ports_offset] = hub.ports.len() as u8;
+            return answer(&buf, output);
+        }
+        let port_number = port_number(input)?;
+        let port = hub.port(port_number);
+        let data = match code {
+            IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX => {
+                connection_information(port_number, port)
+            }
+            IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX_V2 => match port {
+                Some(MockPort::Device(device)) => {
+                    connection_information_v2(port_number, device.speed)
+                }
+                Some(MockPort::Hub(_)) => connection_information_v2(port_number, UsbSpeed::High),
+                None => return Err(not_connected(port_number)),
+            },
+            IOCTL_USB_GET_PORT_CONNECTOR_PROPERTIES => {
+                match hub
+                    .connectors
+                    .iter()
+                    .find(|c| c.port_number as u32 == port_number)
+                {
+                    Some(properties) => connector_properties(properties),
+                    None => return Err("The request is not supported.".to_string()),
+                }
+            }
+            IOCTL_USB_GET_NODE_CONNECTION_NAME => match port {
+                Some(MockPort::Hub(child)) => connection_name(port_number, &child.name),
+                _ => connection_name(port_number, ""),
+            },
+            IOCTL_USB_GET_NODE_CONNECTION_DRIVERKEY_NAME => match port {
+                Some(MockPort::Device(device)) => connection_name(port_number, &device.driver_key),
+                Some(MockPort::Hub(_)) => connection_name(port_number, ""),
+                None => return Err(not_connected(port_number)),
+            },
+            IOCTL_USB_GET_DESCRIPTOR_FROM_NODE_CONNECTION => descriptor(input, port)?,
+            _ => return Err("The request is not supported.".to_string()),
+        };
+        answer(&data, output)
+    }
+
+    fn device_properties(&self) -> Result<Vec<DeviceProperties>, String> {
+        self.scans.fetch_add(1, Ordering::Relaxed);
+        let mut devices: Vec<DeviceProperties> = self
+            .devices()
+            .into_iter()
+            .filter_map(|device| device.properties.clone())
+            .collect();
+        devices.extend(self.other_devices.iter().cloned());
+        if !self.scan_latency.is_zero() {
+            thread::sleep(self.scan_latency * devices.len() as u32);
+        }
+        Ok(devices)
+    }
+
+    fn pci_device_ids(&self, driver_key: &str) -> Result<(Vec<String>, Vec<String>), String> {
+        self.controllers
+            .iter()
+            .find(|c| c.driver_key.eq_ignore_ascii_case(driver_key))
+            .map(|c| (c.hardware_ids.clone(), c.location_paths.clone()))
+            .ok_or_else(|| format!("No PCI device with driver key {}", driver_key))
+    }
+}
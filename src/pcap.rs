@@ -0,0 +1,438 @@
+use std::collections::HashMap;
+
+use crate::descriptor::{
+    Device, Hub, UsbBosDescriptor, UsbConfigurationDescriptor, UsbDeviceDescriptor,
+    USB_BOS_DESCRIPTOR_TYPE, USB_CONFIGURATION_DESCRIPTOR_TYPE, USB_DEVICE_DESCRIPTOR_SIZE,
+};
+
+// https://www.tcpdump.org/linktypes.html
+// https://www.kernel.org/doc/html/latest/usb/usbmon.html
+// https://desowin.org/usbpcap/captureformat.html
+
+const LINKTYPE_USB_LINUX: u32 = 189;
+const LINKTYPE_USB_LINUX_MMAPPED: u32 = 220;
+const LINKTYPE_USBPCAP: u32 = 249;
+
+const PCAPNG_SECTION_HEADER: u32 = 0x0A0D0D0A;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+
+const USB_REQUEST_SET_ADDRESS: u8 = 0x05;
+const USB_REQUEST_GET_DESCRIPTOR: u8 = 0x06;
+const USB_DEVICE_DESCRIPTOR_TYPE: u8 = 0x01;
+const USB_STRING_DESCRIPTOR_TYPE: u8 = 0x03;
+const HID_REPORT_DESCRIPTOR_TYPE: u8 = 0x22;
+
+const LINUX_FOUNDATION_VID: u16 = 0x1d6b;
+
+/// String descriptor read by the host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedString {
+    pub index: u8,
+    pub lang_id: u16,
+    pub value: String,
+}
+
+/// Descriptors read from one device, the longest answer of every descriptor is kept
+#[derive(Debug, Clone, Default)]
+pub struct CapturedDevice {
+    pub bus: u16,
+    pub address: u8,
+    pub descriptor: Option<UsbDeviceDescriptor>,
+    /// Raw configuration descriptor with all following descriptors
+    pub configuration: Option<Vec<u8>>,
+    pub bos: Option<Vec<u8>>,
+    pub strings: Vec<CapturedString>,
+    /// Languages of string descriptor 0
+    pub languages: Vec<u16>,
+    /// Raw HID report descriptors by interface number
+    pub hid_reports: Vec<(u8, Vec<u8>)>,
+}
+
+/// Control transfer of a capture, independent of the link type
+struct ControlTransfer {
+    bus: u16,
+    address: u8,
+    setup: [u8; 8],
+    data: Vec<u8>,
+}
+
+fn u16_at(data: &[u8], offset: usize, big_endian: bool) -> u16 {
+    let bytes = [data[offset], data[offset + 1]];
+    if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    }
+}
+
+fn u32_at(data: &[u8], offset: usize, big_endian: bool) -> u32 {
+    let bytes = data[offset..offset + 4].try_into().unwrap();
+    if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    }
+}
+
+/// Splits a pcap or pcapng file into (link type, packet) pairs
+fn read_packets(data: &[u8]) -> Result<Vec<(u32, &[u8])>, String> {
+    if data.len() < 24 {
+        return Err("File is too short for a capture".to_string());
+    }
+    let magic = u32_at(data, 0, false);
+    if magic == PCAPNG_SECTION_HEADER {
+        return read_pcapng(data);
+    }
+    let big_endian = match magic {
+        0xa1b2c3d4 | 0xa1b23c4d => false,
+        0xd4c3b2a1 | 0x4d3cb2a1 => true,
+        _ => return Err(format!("Unknown capture format {:#010x}", magic)),
+    };
+    let link_type = u32_at(data, 20, big_endian);
+    let mut packets = Vec::new();
+    let mut offset = 24;
+    while offset + 16 <= data.len() {
+        let length = u32_at(data, offset + 8, big_endian) as usize;
+        let start = offset + 16;
+        if start + length > data.len() {
+            return Err(format!("Truncated packet at offset {}", offset));
+        }
+        packets.push((link_type, &data[start..start + length]));
+        offset = start + length;
+    }
+    Ok(packets)
+}
+
+fn read_pcapng(data: &[u8]) -> Result<Vec<(u32, &[u8])>, String> {
+    let mut packets = Vec::new();
+    // Link type and snap length of the interfaces of the current section
+    let mut interfaces: Vec<(u32, usize)> = Vec::new();
+    let mut big_endian = false;
+    let mut offset = 0;
+    while offset + 12 <= data.len() {
+        let block_type = u32_at(data, offset, false);
+        if block_type == PCAPNG_SECTION_HEADER {
+            big_endian = match u32_at(data, offset + 8, false) {
+                0x1A2B3C4D => false,
+                0x4D3C2B1A => true,
+                magic => return Err(format!("Invalid byte order magic {:#010x}", magic)),
+            };
+            interfaces.clear();
+        }
+        let length = u32_at(data, offset + 4, big_endian) as usize;
+        if length < 12 || offset + length > data.len() {
+            return Err(format!(
+                "Invalid block length {} at offset {}",
+                length, offset
+            ));
+        }
+        let block = &data[offset..offset + length - 4];
+        match u32_at(data, offset, big_endian) {
+            PCAPNG_INTERFACE_DESCRIPTION if block.len() >= 16 => {
+                let link_type = u16_at(block, 8, big_endian) as u32;
+                let snap_length = u32_at(block, 12, big_endian) as usize;
+                interfaces.push((link_type, snap_length));
+            }
+            PCAPNG_ENHANCED_PACKET if block.len() >= 28 => {
+                let interface = u32_at(block, 8, big_endian) as usize;
+                let captured = u32_at(block, 20, big_endian) as usize;
+                let (link_type, _) = *interfaces
+                    .get(interface)
+                    .ok_or_else(|| format!("Unknown interface {}", interface))?;
+                let packet = block
+                    .get(28..28 + captured)
+                    .ok_or_else(|| format!("Truncated packet at offset {}", offset))?;
+                packets.push((link_type, packet));
+            }
+            PCAPNG_SIMPLE_PACKET if block.len() >= 12 => {
+                let original = u32_at(block, 8, big_endian) as usize;
+                let (link_type, snap_length) = *interfaces
+                    .first()
+                    .ok_or_else(|| "Packet without an interface".to_string())?;
+                let mut captured = original.min(block.len() - 12);
+                if snap_length > 0 {
+                    captured = captured.min(snap_length);
+                }
+                packets.push((link_type, &block[12..12 + captured]));
+            }
+            _ => {} // Statistics, name resolution, ...
+        }
+        offset += length;
+    }
+    Ok(packets)
+}
+
+/// Pairs submissions with completions of control transfers. The setup packet is only
+/// in the submission, the data of IN transfers only in the completion.
+#[derive(Default)]
+struct TransferTracker {
+    /// Setup packets by URB or IRP ID
+    pending: HashMap<u64, (u16, u8, [u8; 8])>,
+    transfers: Vec<ControlTransfer>,
+}
+
+impl TransferTracker {
+    fn submit(&mut self, id: u64, bus: u16, address: u8, setup: &[u8]) {
+        if let Ok(setup) = setup.try_into() {
+            self.pending.insert(id, (bus, address, setup));
+        }
+    }
+
+    fn complete(&mut self, id: u64, success: bool, data: &[u8]) {
+        if let Some((bus, address, setup)) = self.pending.remove(&id) {
+            if success {
+                self.transfers.push(ControlTransfer {
+                    bus,
+                    address,
+                    setup,
+                    data: data.to_vec(),
+                });
+            }
+        }
+    }
+
+    /// Linux usbmon, the header is 48 bytes or 64 bytes for the mmapped variant
+    fn usbmon(&mut self, packet: &[u8], header_length: usize) {
+        if packet.len() < header_length || packet[9] != 2 {
+            return; // Only control transfers
+        }
+        let id = u64::from_le_bytes(packet[0..8].try_into().unwrap());
+        let address = packet[11];
+        let bus = u16::from_le_bytes([packet[12], packet[13]]);
+        let status = i32::from_le_bytes(packet[28..32].try_into().unwrap());
+        let data = &packet[header_length..];
+        match packet[8] {
+            b'S' if packet[14] == 0 => self.submit(id, bus, address, &packet[40..48]),
+            b'C' => self.complete(id, status == 0, data),
+            b'E' => self.complete(id, false, data),
+            _ => {}
+        }
+    }
+
+    /// Windows USBPcap, control transfers have a stage after the 27 byte header
+    fn usbpcap(&mut self, packet: &[u8]) {
+        if packet.len() < 28 || packet[22] != 2 {
+            return; // Only control transfers
+        }
+        let header_length = u16::from_le_bytes([packet[0], packet[1]]) as usize;
+        if packet.len() < header_length || header_length < 28 {
+            return;
+        }
+        let id = u64::from_le_bytes(packet[2..10].try_into().unwrap());
+        let status = u32::from_le_bytes(packet[10..14].try_into().unwrap());
+        let bus = u16::from_le_bytes([packet[17], packet[18]]);
+        let address = u16::from_le_bytes([packet[19], packet[20]]) as u8;
+        let data = &packet[header_length..];
+        match packet[27] {
+            0 => self.submit(id, bus, address, data.get(..8).unwrap_or(&[])),
+            // The data stage of IN transfers is followed by a complete stage without data
+            1 if packet[16] & 1 != 0 => self.complete(id, status == 0, data),
+            3 => self.complete(id, status == 0, data),
+            _ => {}
+        }
+    }
+}
+
+fn find_device(devices: &mut Vec<CapturedDevice>, bus: u16, address: u8) -> &mut CapturedDevice {
+    let index = match devices
+        .iter()
+        .position(|d| d.bus == bus && d.address == address)
+    {
+        Some(index) => index,
+        None => {
+            devices.push(CapturedDevice {
+                bus,
+                address,
+                ..Default::default()
+            });
+            devices.len() - 1
+        }
+    };
+    &mut devices[index]
+}
+
+fn keep_longest(target: &mut Option<Vec<u8>>, data: &[u8]) {
+    if target.as_ref().is_none_or(|old| data.len() > old.len()) {
+        *target = Some(data.to_vec());
+    }
+}
+
+fn apply_transfer(devices: &mut Vec<CapturedDevice>, transfer: &ControlTransfer) {
+    let request_type = transfer.setup[0];
+    let request = transfer.setup[1];
+    let value = u16::from_le_bytes([transfer.setup[2], transfer.setup[3]]);
+    let index = u16::from_le_bytes([transfer.setup[4], transfer.setup[5]]);
+    let data = &transfer.data;
+
+    if request_type == 0x00 && request == USB_REQUEST_SET_ADDRESS {
+        // Everything read at the default address belongs to the device with the new address
+        if let Some(position) = devices
+            .iter()
+            .position(|d| d.bus == transfer.bus && d.address == 0)
+        {
+            let mut device = devices.remove(position);
+            device.address = value as u8;
+            devices.retain(|d| !(d.bus == transfer.bus && d.address == value as u8));
+            devices.push(device);
+        }
+        return;
+    }
+    if request != USB_REQUEST_GET_DESCRIPTOR || data.len() < 2 {
+        return;
+    }
+    let descriptor_type = (value >> 8) as u8;
+    let descriptor_index = value as u8;
+    let device = find_device(devices, transfer.bus, transfer.address);
+    match (request_type, descriptor_type) {
+        (0x80, USB_DEVICE_DESCRIPTOR_TYPE) if data.len() >= USB_DEVICE_DESCRIPTOR_SIZE => {
+            device.descriptor = UsbDeviceDescriptor::from_bytes(data).ok();
+        }
+        (0x80, USB_CONFIGURATION_DESCRIPTOR_TYPE) => keep_longest(&mut device.configuration, data),
+        (0x80, USB_BOS_DESCRIPTOR_TYPE) => keep_longest(&mut device.bos, data),
+        (0x80, USB_STRING_DESCRIPTOR_TYPE) => {
+            let end = (data[0] as usize).clamp(2, data.len());
+            let chars: Vec<u16> = data[2..end]
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect();
+            if descriptor_index == 0 {
+                device.languages = chars;
+                return;
+            }
+            let value = String::from_utf16_lossy(&chars);
+            match device
+                .strings
+                .iter_mut()
+                .find(|s| s.index == descriptor_index && s.lang_id == index)
+            {
+                Some(string) if value.len() > string.value.len() => string.value = value,
+                Some(_) => {}
+                None => device.strings.push(CapturedString {
+                    index: descriptor_index,
+                    lang_id: index,
+                    value,
+                }),
+            }
+        }
+        (0x81, HID_REPORT_DESCRIPTOR_TYPE) => {
+            let interface = index as u8;
+            match device.hid_reports.iter_mut().find(|(i, _)| *i == interface) {
+                Some((_, report)) if data.len() > report.len() => *report = data.to_vec(),
+                Some(_) => {}
+                None => device.hid_reports.push((interface, data.to_vec())),
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Reads all descriptors requested in a pcap or pcapng capture of usbmon or USBPcap
+pub fn read_capture(data: &[u8]) -> Result<Vec<CapturedDevice>, String> {
+    let mut tracker = TransferTracker::default();
+    let mut link_types = Vec::new();
+    for (link_type, packet) in read_packets(data)? {
+        match link_type {
+            LINKTYPE_USB_LINUX => tracker.usbmon(packet, 48),
+            LINKTYPE_USB_LINUX_MMAPPED => tracker.usbmon(packet, 64),
+            LINKTYPE_USBPCAP => tracker.usbpcap(packet),
+            _ => {
+                if !link_types.contains(&link_type) {
+                    link_types.push(link_type);
+                }
+            }
+        }
+    }
+    if tracker.transfers.is_empty() && !link_types.is_empty() {
+        return Err(format!("Unsupported link types {:?}", link_types));
+    }
+
+    let mut devices = Vec::new();
+    for transfer in &tracker.transfers {
+        apply_transfer(&mut devices, transfer);
+    }
+    devices.retain(|d| d.descriptor.is_some());
+    devices.sort_by_key(|d| (d.bus, d.address));
+    Ok(devices)
+}
+
+impl CapturedDevice {
+    /// String of an index, preferring the language of the first request
+    pub fn string(&self, index: u8) -> Option<String> {
+        if index == 0 {
+            return None;
+        }
+        let mut strings = self.strings.iter().filter(|s| s.index == index);
+        let preferred = self.languages.first();
+        strings
+            .clone()
+            .find(|s| Some(&s.lang_id) == preferred)
+            .or_else(|| strings.next())
+            .map(|s| s.value.clone())
+    }
+
+    /// Device with the captured strings and HID report descriptors filled in,
+    /// None if no device descriptor was read
+    pub fn to_device(&self) -> Option<Device> {
+        let mut descriptor = self.descriptor.clone()?;
+        descriptor.iManufacturer.1 = self.string(descriptor.iManufacturer.0);
+        descriptor.iProduct.1 = self.string(descriptor.iProduct.0);
+        descriptor.iSerialNumber.1 = self.string(descriptor.iSerialNumber.0);
+
+        let configuration = self.configuration.as_ref().and_then(|raw| {
+            let mut config = UsbConfigurationDescriptor::from_bytes(raw).ok()?;
+            config.iConfiguration.1 = self.string(config.iConfiguration.0);
+            for interface in config.interfaces.iter_mut() {
+                interface.iInterface.1 = self.string(interface.iInterface.0);
+            }
+            for association in config.associations.iter_mut() {
+                association.iFunction.1 = self.string(association.iFunction.0);
+            }
+            Some(config)
+        });
+        let mut device = Device::from_descriptors(0, None, descriptor, configuration);
+        device.bos = self
+            .bos
+            .as_ref()
+            .and_then(|raw| UsbBosDescriptor::from_bytes(raw).ok());
+        device.hid_reports = self.hid_reports.clone();
+        Some(device)
+    }
+}
+
+/// Builds one root hub per bus with all devices of the capture. The ports are not
+/// part of the capture, all devices are listed with port 0.
+pub fn import(data: &[u8]) -> Result<Vec<Hub>, String> {
+    let mut hubs: Vec<Hub> = Vec::new();
+    for captured in read_capture(data)? {
+        let hub_id = format!("usb{}", captured.bus);
+        let index = match hubs.iter().position(|h| h.hub_id == hub_id) {
+            Some(index) => index,
+            None => {
+                hubs.push(Hub {
+                    hub_id,
+                    number_of_ports: 0,
+                    devices: Vec::new(),
+                    ports: Vec::new(),
+                    parent_hub: None,
+                    parent_port: None,
+                    host_controller: None,
+                    descriptor: None,
+                });
+                hubs.len() - 1
+            }
+        };
+        let device = match captured.to_device() {
+            Some(device) => device,
+            None => continue,
+        };
+        // Linux root hubs answer requests like a device
+        if device.descriptor.idVendor == LINUX_FOUNDATION_VID && captured.address == 1 {
+            hubs[index].descriptor = Some(device.descriptor);
+        } else {
+            hubs[index].devices.push(device);
+        }
+    }
+    Ok(hubs)
+}
@@ -0,0 +1,114 @@
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+struct Queue<J> {
+    jobs: VecDeque<J>,
+    /// Jobs taken by a worker and not finished yet, they may still add jobs
+    running: usize,
+}
+
+/// Runs the jobs on up to `threads` worker threads. A job can add further jobs,
+/// e.g. the hubs found on the ports of a hub. Results are in completion order.
+/// A panicking job stops the pool and the panic is raised again.
+pub(crate) fn run<J, R, F>(threads: usize, jobs: Vec<J>, work: F) -> Vec<R>
+where
+    J: Send,
+    R: Send,
+    F: Fn(J, &mut dyn FnMut(J)) -> Option<R> + Sync,
+{
+    let queue = Mutex::new(Queue {
+        jobs: VecDeque::from(jobs),
+        running: 0,
+    });
+    let changed = Condvar::new();
+    let results = Mutex::new(Vec::new());
+    let panicked = Mutex::new(None);
+
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| loop {
+                let job = {
+                    let mut state = queue.lock().unwrap();
+                    loop {
+                        if let Some(job) = state.jobs.pop_front() {
+                            state.running += 1;
+                            break job;
+                        }
+                        if state.running == 0 {
+                            return;
+                        }
+                        state = changed.wait(state).unwrap();
+                    }
+                };
+
+                // The job is finished even if it panicked, otherwise the other
+                // workers would wait for it forever
+                let mut added = Vec::new();
+                let result =
+                    panic::catch_unwind(AssertUnwindSafe(|| work(job, &mut |job| added.push(job))));
+                let mut state = queue.lock().unwrap();
+                match result {
+                    Ok(result) => {
+                        state.jobs.extend(added);
+                        if let Some(result) = result {
+                            results.lock().unwrap().push(result);
+                        }
+                    }
+                    Err(payload) => {
+                        state.jobs.clear();
+                        panicked.lock().unwrap().get_or_insert(payload);
+                    }
+                }
+                state.running -= 1;
+                changed.notify_all();
+            });
+        }
+    });
+    if let Some(payload) = panicked.into_inner().unwrap() {
+        panic::resume_unwind(payload);
+    }
+    results.into_inner().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_added_jobs() {
+        // Every job n > 0 adds the jobs n - 1 and n - 2, like a tree of hubs
+        let mut results = run(4, vec![6u32], |n, add| {
+            if n > 1 {
+                add(n - 1);
+                add(n - 2);
+            }
+            Some(n)
+        });
+        results.sort();
+        assert_eq!(results.len(), 25);
+        assert_eq!(results.iter().filter(|n| **n == 6).count(), 1);
+        assert_eq!(results.iter().filter(|n| **n == 0).count(), 5);
+    }
+
+    #[test]
+    fn raises_panics_of_jobs() {
+        for threads in [1, 4] {
+            let result = panic::catch_unwind(|| {
+                run(threads, (0..16).collect(), |n: u32, add| {
+                    if n == 3 {
+                        panic!("job {} failed", n);
+                    }
+                    if n < 8 {
+                        add(n + 16);
+                    }
+                    Some(n)
+                })
+            });
+            let err = result.unwrap_err();
+            let message = err.downcast_ref::<String>().unwrap();
+            assert_eq!(message, "job 3 failed");
+        }
+    }
+}
@@ -0,0 +1,297 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::guid::Guid;
+
+// https://learn.microsoft.com/en-us/windows-hardware/drivers/install/property-data-type-identifiers
+
+pub const DEVPROP_TYPE_UINT32: u32 = 0x00000007;
+pub const DEVPROP_TYPE_GUID: u32 = 0x0000000D;
+pub const DEVPROP_TYPE_FILETIME: u32 = 0x00000010;
+pub const DEVPROP_TYPE_BOOLEAN: u32 = 0x00000011;
+pub const DEVPROP_TYPE_STRING: u32 = 0x00000012;
+pub const DEVPROP_TYPE_STRING_LIST: u32 = 0x00002012;
+
+/// A decoded device property value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PropertyValue {
+    String(String),
+    StringList(Vec<String>),
+    Guid(Guid),
+    Uint32(u32),
+    /// 100 ns intervals since 1601-01-01
+    FileTime(u64),
+    Boolean(bool),
+}
+
+impl PropertyValue {
+    pub fn into_string(self) -> Option<String> {
+        match self {
+            PropertyValue::String(value) => Some(value),
+            PropertyValue::Guid(value) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+
+    /// A single string is returned as a list with one element
+    pub fn into_string_list(self) -> Option<Vec<String>> {
+        match self {
+            PropertyValue::StringList(value) => Some(value),
+            PropertyValue::String(value) => Some(vec![value]),
+            _ => None,
+        }
+    }
+
+    pub fn as_guid(&self) -> Option<Guid> {
+        match self {
+            PropertyValue::Guid(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            PropertyValue::Uint32(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_filetime(&self) -> Option<u64> {
+        match self {
+            PropertyValue::FileTime(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+fn decode_utf16(data: &[u8]) -> String {
+    let vec_u16: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    String::from_utf16_lossy(&vec_u16)
+}
+
+fn expect_size(property_type: u32, data: &[u8], size: usize) -> Result<(), String> {
+    if data.len() < size {
+        return Err(format!(
+            "Property of type {:#x} is {} bytes, expected {}",
+            property_type,
+            data.len(),
+            size
+        ));
+    }
+    Ok(())
+}
+
+/// Decodes the buffer returned by SetupDiGetDevicePropertyW for the given DEVPROPTYPE
+pub fn decode_property(property_type: u32, data: &[u8]) -> Result<PropertyValue, String> {
+    match property_type {
+        DEVPROP_TYPE_STRING => {
+            let value = decode_utf16(data);
+            Ok(PropertyValue::String(
+                value.trim_end_matches('\0').to_string(),
+            ))
+        }
+        DEVPROP_TYPE_STRING_LIST => {
+            // REG_MULTI_SZ, every string is terminated and the list ends with an empty string
+            let value = decode_utf16(data);
+            Ok(PropertyValue::StringList(
+                value
+                    .split('\0')
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.to_string())
+                    .collect(),
+            ))
+        }
+        DEVPROP_TYPE_GUID => {
+            expect_size(property_type, data, 16)?;
+            Ok(PropertyValue::Guid(Guid::from_bytes(
+                data[0..16].try_into().unwrap(),
+            )))
+        }
+        DEVPROP_TYPE_UINT32 => {
+            expect_size(property_type, data, 4)?;
+            Ok(PropertyValue::Uint32(u32::from_le_bytes(
+                data[0..4].try_into().unwrap(),
+            )))
+        }
+        DEVPROP_TYPE_FILETIME => {
+            expect_size(property_type, data, 8)?;
+            Ok(PropertyValue::FileTime(u64::from_le_bytes(
+                data[0..8].try_into().unwrap(),
+            )))
+        }
+        DEVPROP_TYPE_BOOLEAN => {
+            expect_size(property_type, data, 1)?;
+            // DEVPROP_TRUE is 0xFF, but anything non zero is treated as true
+            Ok(PropertyValue::Boolean(data[0] != 0))
+        }
+        _ => Err(format!("Unsupported property type {:#x}", property_type)),
+    }
+}
+
+/// Converts a FILETIME to a `YYYY-MM-DD` date (UTC)
+pub fn filetime_to_date(filetime: u64) -> String {
+    // Days between 1601-01-01 and 1970-01-01
+    let days = (filetime / 10_000_000 / 86_400) as i64 - 134_774;
+
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Plug and Play properties of a device node
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PnpProperties {
+    pub instance_id: Option<String>,
+    pub friendly_name: Option<String>,
+    pub device_description: Option<String>,
+    pub hardware_ids: Vec<String>,
+    pub compatible_ids: Vec<String>,
+    /// Name of the bound service (driver), e.g. `WinUSB` or `usbser`
+    pub service: Option<String>,
+    pub driver_provider: Option<String>,
+    pub driver_version: Option<String>,
+    /// `YYYY-MM-DD`
+    pub driver_date: Option<String>,
+    pub inf_name: Option<String>,
+    /// ConfigManager problem code (CM_PROB_*), None if the device works
+    pub problem_code: Option<u32>,
+    /// DEVPKEY_Device_LocationInfo, e.g. `Port_#0002.Hub_#0003`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub location_info: Option<String>,
+    /// DEVPKEY_Device_LocationPaths, e.g. `PCIROOT(0)#PCI(1400)#USBROOT(0)#USB(2)`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub location_paths: Vec<String>,
+}
+
+/// Properties of a USB devnode, read once per enumeration
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceProperties {
+    pub driver_key: Option<String>,
+    /// Instance ID of the parent devnode, e.g. the composite device of an interface
+    pub parent: Option<String>,
+    pub container_id: Option<Guid>,
+    pub pnp: PnpProperties,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// UTF-16LE with the terminators SetupDiGetDevicePropertyW returns
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn decodes_string() {
+        let data = utf16("WinUSB\0");
+        assert_eq!(
+            decode_property(DEVPROP_TYPE_STRING, &data),
+            Ok(PropertyValue::String("WinUSB".to_string()))
+        );
+    }
+
+    #[test]
+    fn decodes_string_list() {
+        let data = utf16("USB\\VID_0781&PID_5583&REV_0100\0USB\\VID_0781&PID_5583\0\0");
+        let value = decode_property(DEVPROP_TYPE_STRING_LIST, &data).unwrap();
+        assert_eq!(
+            value.into_string_list(),
+            Some(vec![
+                r"USB\VID_0781&PID_5583&REV_0100".to_string(),
+                r"USB\VID_0781&PID_5583".to_string(),
+            ])
+        );
+
+        let empty = decode_property(DEVPROP_TYPE_STRING_LIST, &[0, 0]).unwrap();
+        assert_eq!(empty, PropertyValue::StringList(Vec::new()));
+
+        // A single string is accepted where a list is expected
+        let single = decode_property(DEVPROP_TYPE_STRING, &utf16("usbccgp\0")).unwrap();
+        assert_eq!(single.into_string_list(), Some(vec!["usbccgp".to_string()]));
+    }
+
+    #[test]
+    fn decodes_guid() {
+        // {4d36e96c-e325-11ce-bfc1-08002be10318} in memory order
+        let data = [
+            0x6c, 0xe9, 0x36, 0x4d, 0x25, 0xe3, 0xce, 0x11, 0xbf, 0xc1, 0x08, 0x00, 0x2b, 0xe1,
+            0x03, 0x18,
+        ];
+        let value = decode_property(DEVPROP_TYPE_GUID, &data).unwrap();
+        assert_eq!(
+            value.as_guid().map(|g| g.to_string()),
+            Some("{4d36e96c-e325-11ce-bfc1-08002be10318}".to_string())
+        );
+        assert_eq!(
+            value.into_string().as_deref(),
+            Some("{4d36e96c-e325-11ce-bfc1-08002be10318}")
+        );
+        assert!(decode_property(DEVPROP_TYPE_GUID, &data[..15]).is_err());
+    }
+
+    #[test]
+    fn decodes_filetime() {
+        // 2023-06-21 00:00 UTC
+        let data = [0x00, 0x40, 0x66, 0x52, 0xd3, 0xa3, 0xd9, 0x01];
+        let value = decode_property(DEVPROP_TYPE_FILETIME, &data).unwrap();
+        assert_eq!(value.as_filetime(), Some(133_317_792_000_000_000));
+        assert_eq!(filetime_to_date(133_317_792_000_000_000), "2023-06-21");
+        assert!(decode_property(DEVPROP_TYPE_FILETIME, &data[..7]).is_err());
+    }
+
+    #[test]
+    fn converts_filetime_dates() {
+        assert_eq!(filetime_to_date(0), "1601-01-01");
+        // Leap day, one second before midnight
+        assert_eq!(
+            filetime_to_date(125_962_560_000_000_000 + 86_399 * 10_000_000),
+            "2000-02-29"
+        );
+        assert_eq!(
+            filetime_to_date(125_962_560_000_000_000 + 86_400 * 10_000_000),
+            "2000-03-01"
+        );
+    }
+
+    #[test]
+    fn decodes_boolean() {
+        // DEVPROP_TRUE and DEVPROP_FALSE
+        assert_eq!(
+            decode_property(DEVPROP_TYPE_BOOLEAN, &[0xff]),
+            Ok(PropertyValue::Boolean(true))
+        );
+        assert_eq!(
+            decode_property(DEVPROP_TYPE_BOOLEAN, &[0x00]),
+            Ok(PropertyValue::Boolean(false))
+        );
+        assert_eq!(
+            decode_property(DEVPROP_TYPE_BOOLEAN, &[0x01]),
+            Ok(PropertyValue::Boolean(true))
+        );
+        assert!(decode_property(DEVPROP_TYPE_BOOLEAN, &[]).is_err());
+    }
+
+    #[test]
+    fn decodes_uint32_and_rejects_other_types() {
+        let value = decode_property(DEVPROP_TYPE_UINT32, &[0x1c, 0, 0, 0]).unwrap();
+        assert_eq!(value.as_u32(), Some(28));
+        assert_eq!(value.as_guid(), None);
+        assert!(decode_property(DEVPROP_TYPE_UINT32, &[0x1c, 0]).is_err());
+        // DEVPROP_TYPE_BINARY
+        assert!(decode_property(0x1003, &[0]).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use crate::guid::Guid;
+use crate::topology::{bus_port_path, windows_location_path};
+
+/// Selects a single device (or a few) without enumerating the full topology
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceQuery {
+    /// The serial number is only requested from devices with matching VID/PID
+    VidPid {
+        vid: u16,
+        pid: u16,
+        serial: Option<String>,
+    },
+    /// Device instance ID, e.g. `USB\VID_046D&PID_C52B\6&2A3B&0&4`
+    InstanceId(String),
+    ContainerId(Guid),
+    /// Bus/port path like `1-4.2` or a Windows location path like
+    /// `PCIROOT(0)#PCI(1400)#USBROOT(0)#USB(4)#USB(2)`
+    Location(String),
+}
+
+impl DeviceQuery {
+    /// Windows location paths need the location of the host controller, which is a SetupDi lookup
+    pub fn needs_controller_path(&self) -> bool {
+        match self {
+            DeviceQuery::Location(location) => location.contains('#'),
+            _ => false,
+        }
+    }
+
+    /// True if a device at the given position can match, without requesting any strings
+    pub fn matches_location(&self, bus: u8, ports: &[u8], controller_path: Option<&str>) -> bool {
+        match self {
+            DeviceQuery::Location(location) => {
+                if location.contains('#') {
+                    match controller_path {
+                        Some(path) => {
+                            windows_location_path(path, ports).eq_ignore_ascii_case(location)
+                        }
+                        None => false,
+                    }
+                } else {
+                    bus_port_path(bus, ports) == *location
+                }
+            }
+            _ => true,
+        }
+    }
+
+    /// True if the location is a prefix of the query, i.e. the device is behind this hub port
+    pub fn may_be_behind(&self, bus: u8, ports: &[u8], controller_path: Option<&str>) -> bool {
+        match self {
+            DeviceQuery::Location(location) => {
+                if location.contains('#') {
+                    match controller_path {
+                        Some(path) => {
+                            let prefix = windows_location_path(path, ports).to_uppercase();
+                            location.to_uppercase().starts_with(&format!("{}#", prefix))
+                        }
+                        None => false,
+                    }
+                } else {
+                    location.starts_with(&format!("{}.", bus_port_path(bus, ports)))
+                }
+            }
+            _ => true,
+        }
+    }
+
+    pub fn matches_ids(&self, vid: u16, pid: u16) -> bool {
+        match self {
+            DeviceQuery::VidPid {
+                vid: query_vid,
+                pid: query_pid,
+                ..
+            } => *query_vid == vid && *query_pid == pid,
+            _ => true,
+        }
+    }
+
+    pub fn matches_serial(&self, serial: Option<&str>) -> bool {
+        match self {
+            DeviceQuery::VidPid {
+                serial: Some(query_serial),
+                ..
+            } => serial == Some(query_serial.as_str()),
+            _ => true,
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use windows::Win32::Foundation::HANDLE;
+
+use crate::backend::Backend;
+use crate::property::DeviceProperties;
+
+/// Incremented whenever the layout of `Recording` changes
+pub const RECORDING_VERSION: u32 = 1;
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenRecord {
+    pub name: String,
+    pub result: Result<(), String>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IoctlRecord {
+    /// Name the handle was opened with
+    pub device: String,
+    pub code: u32,
+    /// Hex encoded request
+    pub input: String,
+    /// Size of the output buffer passed by the caller
+    pub output_size: usize,
+    /// Hex encoded bytes returned by the driver
+    pub result: Result<String, String>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PciRecord {
+    pub driver_key: String,
+    /// Hardware IDs and location paths
+    pub result: Result<(Vec<String>, Vec<String>), String>,
+}
+
+/// Every raw response of one enumeration, in request order.
+/// This is enough to run the enumeration again without the hardware.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recording {
+    pub version: u32,
+    /// Language the strings were requested in, the replay has to use the same
+    pub lang_id: Option<u16>,
+    pub opens: Vec<OpenRecord>,
+    pub ioctls: Vec<IoctlRecord>,
+    pub devices: Option<Result<Vec<DeviceProperties>, String>>,
+    pub pci_devices: Vec<PciRecord>,
+}
+
+impl Default for Recording {
+    fn default() -> Recording {
+        Recording {
+            version: RECORDING_VERSION,
+            lang_id: None,
+            opens: Vec::new(),
+            ioctls: Vec::new(),
+            devices: None,
+            pci_devices: Vec::new(),
+        }
+    }
+}
+
+pub fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Result<Vec<u8>, String> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return Err(format!("Invalid hex data {}", hex));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_err| format!("Invalid hex data {}", hex))
+        })
+        .collect()
+}
+
+/// Wraps a backend and records everything passing through it
+pub struct Recorder<B: Backend> {
+    inner: B,
+    /// Open handles by their value, the handle values differ on replay
+    names: Mutex<HashMap<isize, String>>,
+    recording: Mutex<Recording>,
+}
+
+impl<B: Backend> Recorder<B> {
+    pub fn new(inner: B) -> Recorder<B> {
+        Recorder {
+            inner,
+            names: Mutex::new(HashMap::new()),
+            recording: Mutex::new(Recording::default()),
+        }
+    }
+
+    pub fn into_recording(self) -> Recording {
+        self.recording.into_inner().unwrap()
+    }
+}
+
+impl<B: Backend> Backend for Recorder<B> {
+    fn open(&self, name: &str) -> Result<HANDLE, String> {
+        let result = self.inner.open(name);
+        if let Ok(handle) = result {
+            self.names
+                .lock()
+                .unwrap()
+                .insert(handle.0, name.to_string());
+        }
+        self.recording.lock().unwrap().opens.push(OpenRecord {
+            name: name.to_string(),
+            result: result.as_ref().map(|_| ()).map_err(|err| err.clone()),
+        });
+        result
+    }
+
+    fn close(&self, handle: HANDLE) {
+        self.names.lock().unwrap().remove(&handle.0);
+        self.inner.close(handle);
+    }
+
+    fn ioctl(
+        &self,
+        handle: HANDLE,
+        code: u32,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<usize, String> {
+        let result = self.inner.ioctl(handle, code, input, output);
+        let device = self
+            .names
+            .lock()
+            .unwrap()
+            .get(&handle.0)
+            .cloned()
+            .unwrap_or_default();
+        self.recording.lock().unwrap().ioctls.push(IoctlRecord {
+            device,
+            code,
+            input: to_hex(input),
+            output_size: output.len(),
+            result: match &result {
+                Ok(returned) => Ok(to_hex(&output[..(*returned).min(output.len())])),
+                Err(err) => Err(err.clone()),
+            },
+        });
+        result
+    }
+
+    fn device_properties(&self) -> Result<Vec<DeviceProperties>, String> {
+        let result = self.inner.device_properties();
+        self.recording.lock().unwrap().devices = Some(result.clone());
+        result
+    }
+
+    fn pci_device_ids(&self, driver_key: &str) -> Result<(Vec<String>, Vec<String>), String> {
+        let result = self.inner.pci_device_ids(driver_key);
+        self.recording.lock().unwrap().pci_devices.push(PciRecord {
+            driver_key: driver_key.to_string(),
+            result: result.clone(),
+        });
+        result
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use windows::Win32::Foundation::HANDLE;
+
+use crate::backend::Backend;
+use crate::property::DeviceProperties;
+use crate::record::{from_hex, to_hex, Recording, RECORDING_VERSION};
+
+struct ReplayState {
+    /// Names of the open handles, handle values are assigned on replay
+    names: HashMap<isize, String>,
+    next_handle: isize,
+    /// How often every IOCTL record was served
+    served: Vec<usize>,
+    unmatched: Vec<String>,
+}
+
+/// Answers all requests from a recording, e.g. to run the enumeration of a
+/// user's machine on any platform. Identical requests are answered in recorded order,
+/// the last answer is repeated when they run out.
+pub struct ReplayBackend {
+    recording: Recording,
+    state: Mutex<ReplayState>,
+}
+
+impl ReplayBackend {
+    pub fn new(recording: Recording) -> Result<ReplayBackend, String> {
+        if recording.version != RECORDING_VERSION {
+            return Err(format!(
+                "Recording version {} is not supported, expected {}",
+                recording.version, RECORDING_VERSION
+            ));
+        }
+        let served = vec![0; recording.ioctls.len()];
+        Ok(ReplayBackend {
+            recording,
+            state: Mutex::new(ReplayState {
+                names: HashMap::new(),
+                next_handle: 1,
+                served,
+                unmatched: Vec::new(),
+            }),
+        })
+    }
+
+    /// Language of the recorded string requests
+    pub fn lang_id(&self) -> Option<u16> {
+        self.recording.lang_id
+    }
+
+    /// Requests without a recorded answer, a changed walk shows up here
+    pub fn unmatched(&self) -> Vec<String> {
+        self.state.lock().unwrap().unmatched.clone()
+    }
+
+    /// Recorded IOCTLs which were never requested
+    pub fn unused(&self) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        self.recording
+            .ioctls
+            .iter()
+            .zip(state.served.iter())
+            .filter(|(_, served)| **served == 0)
+            .map(|(record, _)| {
+                format!(
+                    "IOCTL {:#x} on {} with {}",
+                    record.code, record.device, record.input
+                )
+            })
+            .collect()
+    }
+
+    fn fail<T>(&self, request: String) -> Result<T, String> {
+        let message = format!("No recorded answer for {}", request);
+        self.state.lock().unwrap().unmatched.push(request);
+        Err(message)
+    }
+}
+
+impl Backend for ReplayBackend {
+    fn open(&self, name: &str) -> Result<HANDLE, String> {
+        let record = match self.recording.opens.iter().find(|r| r.name == name) {
+            Some(record) => record,
+            None => return self.fail(format!("open {}", name)),
+        };
+        match &record.result {
+            Ok(()) => {
+                let mut state = self.state.lock().unwrap();
+                let handle = HANDLE(state.next_handle);
+                state.next_handle += 1;
+                state.names.insert(handle.0, name.to_string());
+                Ok(handle)
+            }
+            Err(err) => Err(err.clone()),
+        }
+    }
+
+    fn close(&self, handle: HANDLE) {
+        self.state.lock().unwrap().names.remove(&handle.0);
+    }
+
+    fn ioctl(
+        &self,
+        handle: HANDLE,
+        code: u32,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<usize, String> {
+        let input_hex = to_hex(input);
+        let mut state = self.state.lock().unwrap();
+        let device = match state.names.get(&handle.0) {
+            Some(device) => device.clone(),
+            None => {
+                drop(state);
+                return self.fail(format!("IOCTL {:#x} on a closed handle", code));
+            }
+        };
+        let candidates: Vec<usize> = self
+            .recording
+            .ioctls
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.device == device && r.code == code && r.input == input_hex)
+            .map(|(i, _)| i)
+            .collect();
+        let index = match candidates.iter().find(|i| state.served[**i] == 0) {
+            Some(index) => *index,
+            None => match candidates.last() {
+                Some(index) => *index,
+                None => {
+                    drop(state);
+                    return self.fail(format!(
+                        "IOCTL {:#x} on {} with {}",
+                        code, device, input_hex
+                    ));
+                }
+            },
+        };
+        state.served[index] += 1;
+        drop(state);
+
+        match &self.recording.ioctls[index].result {
+            Ok(data) => {
+                let data = from_hex(data)?;
+                // A smaller buffer gets the beginning, like a truncated answer of the driver
+                let length = data.len().min(output.len());
+                output[..length].copy_from_slice(&data[..length]);
+                Ok(length)
+            }
+            Err(err) => Err(err.clone()),
+        }
+    }
+
+    fn device_properties(&self) -> Result<Vec<DeviceProperties>, String> {
+        match &self.recording.devices {
+            Some(result) => result.clone(),
+            None => self.fail("device properties".to_string()),
+        }
+    }
+
+    fn pci_device_ids(&self, driver_key: &str) -> Result<(Vec<String>, Vec<String>), String> {
+        match self
+            .recording
+            .pci_devices
+            .iter()
+            .find(|r| r.driver_key.eq_ignore_ascii_case(driver_key))
+        {
+            Some(record) => record.result.clone(),
+            None => self.fail(format!("PCI device {}", driver_key)),
+        }
+    }
+}
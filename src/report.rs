@@ -0,0 +1,311 @@
+use crate::connection::UsbSpeed;
+use crate::descriptor::{
+    Device, Hub, UsbConfigurationDescriptor, UsbDeviceDescriptor, UsbEndpointDescriptor,
+    UsbInterfaceAssociationDescriptor, UsbInterfaceDescriptor,
+};
+use crate::topology::assign_locations;
+
+// Descriptors printed field by field by other tools (lsusb, USBView), the field names
+// follow the USB specification in all of them.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Section {
+    Device,
+    Configuration,
+    Interface,
+    Endpoint,
+    Association,
+    Hub,
+    /// Class specific descriptors, status and qualifiers
+    Other,
+}
+
+/// A descriptor field, e.g. `idVendor` with `0x1d6b`
+pub(crate) struct Field<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+    /// String of a string index field
+    pub string: Option<&'a str>,
+}
+
+impl<'a> Field<'a> {
+    pub fn number(&self) -> Option<u32> {
+        match self
+            .value
+            .strip_prefix("0x")
+            .or_else(|| self.value.strip_prefix("0X"))
+        {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => self.value.parse().ok(),
+        }
+    }
+
+    pub fn byte(&self) -> Option<u8> {
+        self.number().and_then(|n| u8::try_from(n).ok())
+    }
+
+    pub fn word(&self) -> Option<u16> {
+        self.number().and_then(|n| u16::try_from(n).ok())
+    }
+
+    /// Versions are either raw or printed with a dot, e.g. `2.10` is 0x0210
+    pub fn bcd(&self) -> Option<u16> {
+        let (major, minor) = match self.value.split_once('.') {
+            Some(parts) => parts,
+            None => return self.word(),
+        };
+        let major = u16::from_str_radix(major, 16).ok()?;
+        let minor = u16::from_str_radix(minor, 16).ok()?;
+        Some((major << 8) | minor)
+    }
+
+    pub fn string(&self) -> Option<(u8, Option<String>)> {
+        Some((self.byte()?, self.string.map(|s| s.to_string())))
+    }
+
+    /// bMaxPower, either raw or in mA. The unit is 8 mA for SuperSpeed and 2 mA otherwise.
+    pub fn power(&self, bcd_usb: u16) -> Option<u8> {
+        match self.value.strip_suffix("mA") {
+            Some(milliamps) => {
+                let unit = if bcd_usb >= 0x0300 { 8 } else { 2 };
+                u8::try_from(milliamps.parse::<u32>().ok()? / unit).ok()
+            }
+            None => self.byte(),
+        }
+    }
+}
+
+fn set<T>(target: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *target = value;
+    }
+}
+
+fn apply_device(desc: &mut UsbDeviceDescriptor, field: &Field) {
+    match field.name {
+        "bLength" => set(&mut desc.bLength, field.byte()),
+        "bDescriptorType" => set(&mut desc.bDescriptorType, field.byte()),
+        "bcdUSB" => set(&mut desc.bcdUSB, field.bcd()),
+        "bDeviceClass" => set(&mut desc.bDeviceClass, field.byte()),
+        "bDeviceSubClass" => set(&mut desc.bDeviceSubClass, field.byte()),
+        "bDeviceProtocol" => set(&mut desc.bDeviceProtocol, field.byte()),
+        "bMaxPacketSize0" => set(&mut desc.bMaxPacketSize0, field.byte()),
+        "idVendor" => set(&mut desc.idVendor, field.word()),
+        "idProduct" => set(&mut desc.idProduct, field.word()),
+        "bcdDevice" => set(&mut desc.bcdDevice, field.bcd()),
+        "iManufacturer" => set(&mut desc.iManufacturer, field.string()),
+        "iProduct" => set(&mut desc.iProduct, field.string()),
+        "iSerial" | "iSerialNumber" => set(&mut desc.iSerialNumber, field.string()),
+        "bNumConfigurations" => set(&mut desc.bNumConfigurations, field.byte()),
+        _ => {}
+    }
+}
+
+fn apply_configuration(config: &mut UsbConfigurationDescriptor, field: &Field, bcd_usb: u16) {
+    match field.name {
+        "bLength" => set(&mut config.bLength, field.byte()),
+        "bDescriptorType" => set(&mut config.bDescriptorType, field.byte()),
+        "wTotalLength" => set(&mut config.wTotalLength, field.word()),
+        "bNumInterfaces" => set(&mut config.bNumInterfaces, field.byte()),
+        "bConfigurationValue" => set(&mut config.bConfigurationValue, field.byte()),
+        "iConfiguration" => set(&mut config.iConfiguration, field.string()),
+        "bmAttributes" => set(&mut config.bmAttributes, field.byte()),
+        "MaxPower" | "bMaxPower" => set(&mut config.bMaxPower, field.power(bcd_usb)),
+        _ => {}
+    }
+}
+
+fn apply_interface(interface: &mut UsbInterfaceDescriptor, field: &Field) {
+    match field.name {
+        "bLength" => set(&mut interface.bLength, field.byte()),
+        "bDescriptorType" => set(&mut interface.bDescriptorType, field.byte()),
+        "bInterfaceNumber" => set(&mut interface.bInterfaceNumber, field.byte()),
+        "bAlternateSetting" => set(&mut interface.bAlternateSetting, field.byte()),
+        "bNumEndpoints" => set(&mut interface.bNumEndpoints, field.byte()),
+        "bInterfaceClass" => set(&mut interface.bInterfaceClass, field.byte()),
+        "bInterfaceSubClass" => set(&mut interface.bInterfaceSubClass, field.byte()),
+        "bInterfaceProtocol" => set(&mut interface.bInterfaceProtocol, field.byte()),
+        "iInterface" => set(&mut interface.iInterface, field.string()),
+        _ => {}
+    }
+}
+
+fn apply_endpoint(endpoint: &mut UsbEndpointDescriptor, field: &Field) {
+    match field.name {
+        "bLength" => set(&mut endpoint.bLength, field.byte()),
+        "bDescriptorType" => set(&mut endpoint.bDescriptorType, field.byte()),
+        "bEndpointAddress" => set(&mut endpoint.bEndpointAddress, field.byte()),
+        "bmAttributes" => set(&mut endpoint.bmAttributes, field.byte()),
+        "wMaxPacketSize" => set(&mut endpoint.wMaxPacketSize, field.word()),
+        "bInterval" => set(&mut endpoint.bInterval, field.byte()),
+        _ => {}
+    }
+}
+
+fn apply_association(association: &mut UsbInterfaceAssociationDescriptor, field: &Field) {
+    match field.name {
+        "bLength" => set(&mut association.bLength, field.byte()),
+        "bDescriptorType" => set(&mut association.bDescriptorType, field.byte()),
+        "bFirstInterface" => set(&mut association.bFirstInterface, field.byte()),
+        "bInterfaceCount" => set(&mut association.bInterfaceCount, field.byte()),
+        "bFunctionClass" => set(&mut association.bFunctionClass, field.byte()),
+        "bFunctionSubClass" => set(&mut association.bFunctionSubClass, field.byte()),
+        "bFunctionProtocol" => set(&mut association.bFunctionProtocol, field.byte()),
+        "iFunction" => set(&mut association.iFunction, field.string()),
+        _ => {}
+    }
+}
+
+/// Descriptors of one device, filled section by section in report order
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Descriptors {
+    pub descriptor: UsbDeviceDescriptor,
+    pub configurations: Vec<UsbConfigurationDescriptor>,
+    /// Port count of the hub descriptor
+    pub number_of_ports: Option<u8>,
+}
+
+impl Descriptors {
+    /// Starts a new descriptor, it belongs to the last configuration and interface
+    pub fn begin(&mut self, section: Section) {
+        if section == Section::Configuration {
+            self.configurations
+                .push(UsbConfigurationDescriptor::default());
+        }
+        let config = self.configurations.last_mut();
+        match section {
+            Section::Interface => {
+                if let Some(config) = config {
+                    config.interfaces.push(UsbInterfaceDescriptor::default());
+                }
+            }
+            Section::Endpoint => {
+                if let Some(interface) = config.and_then(|c| c.interfaces.last_mut()) {
+                    interface.endpoints.push(UsbEndpointDescriptor::default());
+                }
+            }
+            Section::Association => {
+                if let Some(config) = config {
+                    config
+                        .associations
+                        .push(UsbInterfaceAssociationDescriptor::default());
+                }
+            }
+            Section::Device | Section::Configuration | Section::Hub | Section::Other => {}
+        }
+    }
+
+    /// Sets a field of the last started descriptor of the section, unknown fields are ignored
+    pub fn apply(&mut self, section: Section, field: &Field) {
+        let bcd_usb = self.descriptor.bcdUSB;
+        let config = self.configurations.last_mut();
+        match section {
+            Section::Device => apply_device(&mut self.descriptor, field),
+            Section::Configuration => {
+                if let Some(config) = config {
+                    apply_configuration(config, field, bcd_usb);
+                }
+            }
+            Section::Interface => {
+                if let Some(interface) = config.and_then(|c| c.interfaces.last_mut()) {
+                    apply_interface(interface, field);
+                }
+            }
+            Section::Endpoint => {
+                let interface = config.and_then(|c| c.interfaces.last_mut());
+                if let Some(endpoint) = interface.and_then(|i| i.endpoints.last_mut()) {
+                    apply_endpoint(endpoint, field);
+                }
+            }
+            Section::Association => {
+                if let Some(association) = config.and_then(|c| c.associations.last_mut()) {
+                    apply_association(association, field);
+                }
+            }
+            Section::Hub => {
+                if field.name == "nNbrPorts" || field.name == "bNumberOfPorts" {
+                    set(&mut self.number_of_ports, field.byte().map(Some));
+                }
+            }
+            Section::Other => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum NodeKind {
+    RootHub,
+    Hub,
+    Device,
+}
+
+/// Hub or device of a report which shows the topology as a tree
+#[derive(Debug, Clone)]
+pub(crate) struct ReportNode {
+    pub kind: NodeKind,
+    /// Port on the parent hub
+    pub port_number: Option<u8>,
+    /// Hub name, a name is generated if the report has none
+    pub name: Option<String>,
+    pub speed: Option<UsbSpeed>,
+    pub descriptors: Descriptors,
+    pub children: Vec<ReportNode>,
+}
+
+impl ReportNode {
+    pub fn new(kind: NodeKind) -> ReportNode {
+        ReportNode {
+            kind,
+            port_number: None,
+            name: None,
+            speed: None,
+            descriptors: Descriptors::default(),
+            children: Vec::new(),
+        }
+    }
+}
+
+/// Flattens the tree into hubs like the enumeration does, child hubs come before their parent
+pub(crate) fn to_hubs(roots: Vec<ReportNode>) -> Vec<Hub> {
+    fn visit(node: ReportNode, parent: Option<(String, u8)>, hubs: &mut Vec<Hub>) {
+        let hub_id = node
+            .name
+            .unwrap_or_else(|| format!("Hub{}", hubs.len() + 1));
+        let mut hub = Hub {
+            hub_id: hub_id.clone(),
+            number_of_ports: node.descriptors.number_of_ports.unwrap_or(0),
+            devices: Vec::new(),
+            ports: Vec::new(),
+            parent_hub: parent.as_ref().map(|(parent_hub, _)| parent_hub.clone()),
+            parent_port: parent.map(|(_, parent_port)| parent_port),
+            host_controller: None,
+            descriptor: match node.kind {
+                NodeKind::RootHub => None,
+                NodeKind::Hub | NodeKind::Device => Some(node.descriptors.descriptor),
+            },
+        };
+        for child in node.children {
+            let port_number = child.port_number.unwrap_or(0);
+            hub.number_of_ports = hub.number_of_ports.max(port_number);
+            match child.kind {
+                NodeKind::Device => hub.devices.push(Device::from_descriptors(
+                    port_number,
+                    child.speed,
+                    child.descriptors.descriptor,
+                    child.descriptors.configurations.into_iter().next(),
+                )),
+                NodeKind::RootHub | NodeKind::Hub => {
+                    visit(child, Some((hub_id.clone(), port_number)), hubs)
+                }
+            }
+        }
+        hubs.push(hub);
+    }
+
+    let mut hubs = Vec::new();
+    for root in roots {
+        visit(root, None, &mut hubs);
+    }
+    assign_locations(&mut hubs);
+    hubs
+}
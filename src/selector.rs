@@ -0,0 +1,589 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::connection::UsbSpeed;
+use crate::descriptor::{Device, Hub};
+
+// Selector syntax: whitespace separated terms which all have to match, e.g.
+// vid=0x0483 pid=0xdf11 class=hid speed>=high serial~"AB*" path=1-4.*
+//
+// Every term is <field><op><value>, the operators are = != ~ < <= > >=.
+// String values may contain the wildcards * and ? and can be quoted with "...".
+// `~` is a case insensitive match, `=` and `!=` are case sensitive
+// except for IDs Windows treats case insensitive (instance, container, service).
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Vid,
+    Pid,
+    /// Device class or class of any interface
+    Class,
+    Subclass,
+    Protocol,
+    Speed,
+    Serial,
+    Manufacturer,
+    Product,
+    /// Bus/port path like `1-4.2`, or the Windows location path if the value contains `#`
+    Path,
+    Service,
+    Instance,
+    Container,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        match name {
+            "vid" => Some(Field::Vid),
+            "pid" => Some(Field::Pid),
+            "class" => Some(Field::Class),
+            "subclass" => Some(Field::Subclass),
+            "protocol" => Some(Field::Protocol),
+            "speed" => Some(Field::Speed),
+            "serial" => Some(Field::Serial),
+            "manufacturer" => Some(Field::Manufacturer),
+            "product" => Some(Field::Product),
+            "path" => Some(Field::Path),
+            "service" => Some(Field::Service),
+            "instance" => Some(Field::Instance),
+            "container" => Some(Field::Container),
+            _ => None,
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Field::Vid | Field::Pid | Field::Class | Field::Subclass | Field::Protocol
+        )
+    }
+
+    fn is_case_insensitive(&self) -> bool {
+        matches!(self, Field::Service | Field::Instance | Field::Container)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Like,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn is_ordering(&self) -> bool {
+        matches!(self, Op::Lt | Op::Le | Op::Gt | Op::Ge)
+    }
+
+    fn compare<T: Ord>(&self, value: T, expected: T) -> bool {
+        match self {
+            Op::Eq | Op::Like => value == expected,
+            Op::Ne => value != expected,
+            Op::Lt => value < expected,
+            Op::Le => value <= expected,
+            Op::Gt => value > expected,
+            Op::Ge => value >= expected,
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Like => "~",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Number(u16),
+    Speed(UsbSpeed),
+    Pattern(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Term {
+    pub field: Field,
+    pub op: Op,
+    pub value: Value,
+}
+
+/// Parsed selector, all terms have to match
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selector {
+    pub terms: Vec<Term>,
+}
+
+fn class_from_name(name: &str) -> Option<u16> {
+    // https://www.usb.org/defined-class-codes
+    match name {
+        "audio" => Some(0x01),
+        "cdc" | "comm" => Some(0x02),
+        "hid" => Some(0x03),
+        "physical" => Some(0x05),
+        "image" => Some(0x06),
+        "printer" => Some(0x07),
+        "storage" | "msc" => Some(0x08),
+        "hub" => Some(0x09),
+        "cdc-data" => Some(0x0A),
+        "smartcard" => Some(0x0B),
+        "video" => Some(0x0E),
+        "billboard" => Some(0x11),
+        "diagnostic" => Some(0xDC),
+        "wireless" => Some(0xE0),
+        "misc" => Some(0xEF),
+        "app" => Some(0xFE),
+        "vendor" => Some(0xFF),
+        _ => None,
+    }
+}
+
+fn speed_from_name(name: &str) -> Option<UsbSpeed> {
+    match name {
+        "low" => Some(UsbSpeed::Low),
+        "full" => Some(UsbSpeed::Full),
+        "high" => Some(UsbSpeed::High),
+        "super" => Some(UsbSpeed::Super),
+        _ => None,
+    }
+}
+
+/// VID/PID are hex like in `lsusb -d`, the other numbers decimal unless prefixed with 0x
+fn parse_number(field: Field, value: &str) -> Option<u16> {
+    let lower = value.to_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        return u16::from_str_radix(hex, 16).ok();
+    }
+    match field {
+        Field::Vid | Field::Pid => u16::from_str_radix(&lower, 16).ok(),
+        Field::Class => class_from_name(&lower).or_else(|| lower.parse().ok()),
+        _ => lower.parse().ok(),
+    }
+}
+
+/// Matches `*` (any sequence) and `?` (any character)
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // Let the last star consume one more character
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn error(message: String, column: usize) -> String {
+    format!("{} at column {}", message, column + 1)
+}
+
+/// Splits the input into terms, honoring quotes. Returns the term and its start column,
+/// columns count characters.
+fn split_terms(input: &str) -> Result<Vec<(usize, String)>, String> {
+    let mut terms = Vec::new();
+    let mut chars = input.chars().enumerate().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut term = String::new();
+        let mut current = Some((start, c));
+        let mut quote_start: Option<usize> = None;
+        while let Some((index, c)) = current {
+            match c {
+                '"' if quote_start.is_none() => quote_start = Some(index),
+                '"' => quote_start = None,
+                '\\' if quote_start.is_some() => match chars.next() {
+                    Some((_, escaped)) => term.push(escaped),
+                    None => break,
+                },
+                c if c.is_whitespace() && quote_start.is_none() => break,
+                c => term.push(c),
+            }
+            current = chars.next();
+        }
+        if let Some(index) = quote_start {
+            return Err(error("Unterminated quote".to_string(), index));
+        }
+        terms.push((start, term));
+    }
+    Ok(terms)
+}
+
+fn parse_term(column: usize, term: &str) -> Result<Term, String> {
+    // Field names are ASCII, so byte offsets up to the value are columns as well
+    let name_end = term
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(term.len());
+    let name = &term[..name_end];
+    if name.is_empty() {
+        return Err(error(
+            format!("Expected a field name in '{}'", term),
+            column,
+        ));
+    }
+    let field = match Field::from_name(&name.to_lowercase()) {
+        Some(field) => field,
+        None => return Err(error(format!("Unknown field '{}'", name), column)),
+    };
+
+    let rest = &term[name_end..];
+    let (op, op_len) = if rest.starts_with("!=") {
+        (Op::Ne, 2)
+    } else if rest.starts_with("<=") {
+        (Op::Le, 2)
+    } else if rest.starts_with(">=") {
+        (Op::Ge, 2)
+    } else if rest.starts_with('=') {
+        (Op::Eq, 1)
+    } else if rest.starts_with('~') {
+        (Op::Like, 1)
+    } else if rest.starts_with('<') {
+        (Op::Lt, 1)
+    } else if rest.starts_with('>') {
+        (Op::Gt, 1)
+    } else {
+        return Err(error(
+            format!("Expected one of = != ~ < <= > >= after '{}'", name),
+            column + name_end,
+        ));
+    };
+
+    let value_column = column + name_end + op_len;
+    let value = &rest[op_len..];
+    if value.is_empty() {
+        return Err(error(format!("Missing value for '{}'", name), value_column));
+    }
+    let value = if field.is_numeric() {
+        match parse_number(field, value) {
+            Some(number) => Value::Number(number),
+            None => {
+                return Err(error(
+                    format!("Invalid number '{}' for '{}'", value, name),
+                    value_column,
+                ))
+            }
+        }
+    } else if field == Field::Speed {
+        match speed_from_name(&value.to_lowercase()) {
+            Some(speed) => Value::Speed(speed),
+            None => {
+                return Err(error(
+                    format!(
+                        "Invalid speed '{}', expected low, full, high or super",
+                        value
+                    ),
+                    value_column,
+                ))
+            }
+        }
+    } else {
+        if op.is_ordering() {
+            return Err(error(
+                format!("Operator {} is not supported for '{}'", op, name),
+                column + name_end,
+            ));
+        }
+        Value::Pattern(value.to_string())
+    };
+    Ok(Term { field, op, value })
+}
+
+impl Selector {
+    pub fn parse(input: &str) -> Result<Selector, String> {
+        let mut terms = Vec::new();
+        for (column, term) in split_terms(input)? {
+            terms.push(parse_term(column, &term)?);
+        }
+        Ok(Selector { terms })
+    }
+
+    pub fn matches(&self, device: &Device) -> bool {
+        self.terms.iter().all(|term| term.matches(device))
+    }
+
+    /// All devices of the topology matching the selector
+    pub fn select<'a>(&self, hubs: &'a [Hub]) -> Vec<&'a Device> {
+        hubs.iter()
+            .flat_map(|hub| hub.devices.iter())
+            .filter(|device| self.matches(device))
+            .collect()
+    }
+}
+
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Selector, String> {
+        Selector::parse(value)
+    }
+}
+
+impl Term {
+    fn numbers(&self, device: &Device) -> Vec<u16> {
+        let desc = &device.descriptor;
+        let interfaces = device
+            .configuration
+            .iter()
+            .flat_map(|config| config.interfaces.iter());
+        match self.field {
+            Field::Vid => vec![desc.idVendor],
+            Field::Pid => vec![desc.idProduct],
+            Field::Class => std::iter::once(desc.bDeviceClass)
+                .chain(interfaces.map(|i| i.bInterfaceClass))
+                .map(u16::from)
+                .collect(),
+            Field::Subclass => std::iter::once(desc.bDeviceSubClass)
+                .chain(interfaces.map(|i| i.bInterfaceSubClass))
+                .map(u16::from)
+                .collect(),
+            Field::Protocol => std::iter::once(desc.bDeviceProtocol)
+                .chain(interfaces.map(|i| i.bInterfaceProtocol))
+                .map(u16::from)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn text(&self, device: &Device) -> Option<String> {
+        let pnp = device.pnp.as_ref();
+        match self.field {
+            Field::Serial => device.descriptor.iSerialNumber.1.clone(),
+            Field::Manufacturer => device.descriptor.iManufacturer.1.clone(),
+            Field::Product => device.descriptor.iProduct.1.clone(),
+            Field::Path => match &self.value {
+                Value::Pattern(pattern) if pattern.contains('#') => device
+                    .location
+                    .as_ref()
+                    .and_then(|l| l.location_path.clone()),
+                _ => device.location.as_ref().map(|l| l.bus_path.clone()),
+            },
+            Field::Service => pnp.and_then(|p| p.service.clone()),
+            Field::Instance => pnp.and_then(|p| p.instance_id.clone()),
+            Field::Container => device.container_id.map(|id| id.to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn matches(&self, device: &Device) -> bool {
+        match &self.value {
+            Value::Number(expected) => {
+                let numbers = self.numbers(device);
+                match self.op {
+                    // A composite device does not have a class, none of the interfaces may match
+                    Op::Ne => numbers.iter().all(|n| *n != *expected),
+                    op => numbers.iter().any(|n| op.compare(*n, *expected)),
+                }
+            }
+            Value::Speed(expected) => match device.speed {
+                Some(speed) => self.op.compare(speed, *expected),
+                None => self.op == Op::Ne,
+            },
+            Value::Pattern(pattern) => {
+                let text = match self.text(device) {
+                    Some(text) => text,
+                    None => return self.op == Op::Ne,
+                };
+                let (pattern, text) = if self.field == Field::Container {
+                    // Accept the GUID with or without braces
+                    (
+                        pattern.trim_matches(|c| c == '{' || c == '}'),
+                        text.trim_matches(|c| c == '{' || c == '}'),
+                    )
+                } else {
+                    (pattern.as_str(), text.as_str())
+                };
+                let matched = if self.op == Op::Like || self.field.is_case_insensitive() {
+                    glob_match(&pattern.to_lowercase(), &text.to_lowercase())
+                } else {
+                    glob_match(pattern, text)
+                };
+                matched != (self.op == Op::Ne)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::UsbInterfaceDescriptor;
+    use crate::property::PnpProperties;
+    use crate::topology::DeviceLocation;
+
+    fn device(vid: u16, pid: u16, speed: UsbSpeed, serial: Option<&str>, path: &str) -> Device {
+        let descriptor = crate::descriptor::UsbDeviceDescriptor {
+            idVendor: vid,
+            idProduct: pid,
+            iProduct: (2, Some("Gerät".to_string())),
+            iSerialNumber: (3, serial.map(String::from)),
+            ..Default::default()
+        };
+        let mut device = Device::from_descriptors(1, Some(speed), descriptor, None);
+        device.location = Some(DeviceLocation {
+            bus_path: path.to_string(),
+            location_path: Some(format!(
+                "PCIROOT(0)#PCI(1400)#USBROOT(0)#USB({})",
+                &path[2..]
+            )),
+            location_info: String::new(),
+        });
+        device
+    }
+
+    /// A keyboard, a DFU device and a composite device with a HID interface
+    fn hubs() -> Vec<Hub> {
+        let keyboard = device(0x046d, 0xc31c, UsbSpeed::Low, None, "1-2");
+        let mut dfu = device(0x0483, 0xdf11, UsbSpeed::Full, Some("AB12"), "1-3");
+        dfu.pnp = Some(PnpProperties {
+            service: Some("WinUSB".to_string()),
+            instance_id: Some(r"USB\VID_0483&PID_DF11\AB12".to_string()),
+            ..Default::default()
+        });
+        let mut composite = device(0x0bda, 0x8153, UsbSpeed::Super, Some("cd34"), "1-4");
+        composite.descriptor.bDeviceClass = 0xEF;
+        let mut config = crate::descriptor::UsbConfigurationDescriptor::default();
+        config.interfaces.push(UsbInterfaceDescriptor {
+            bInterfaceClass: 0x03,
+            ..Default::default()
+        });
+        composite.configuration = Some(config);
+        let mut keyboard_hub = Hub {
+            hub_id: "root".to_string(),
+            number_of_ports: 4,
+            devices: vec![keyboard, dfu],
+            ports: Vec::new(),
+            parent_hub: None,
+            parent_port: None,
+            host_controller: None,
+            descriptor: None,
+        };
+        let mut second = keyboard_hub.clone();
+        second.devices = vec![composite];
+        keyboard_hub.hub_id = "hub".to_string();
+        vec![keyboard_hub, second]
+    }
+
+    fn select(selector: &str) -> Vec<u16> {
+        let hubs = hubs();
+        Selector::parse(selector)
+            .unwrap()
+            .select(&hubs)
+            .iter()
+            .map(|d| d.descriptor.idProduct)
+            .collect()
+    }
+
+    #[test]
+    fn parses_terms() {
+        let selector = Selector::parse(r#"vid=0483  serial~"a b*" speed>=high"#).unwrap();
+        assert_eq!(
+            selector.terms,
+            vec![
+                Term {
+                    field: Field::Vid,
+                    op: Op::Eq,
+                    value: Value::Number(0x0483),
+                },
+                Term {
+                    field: Field::Serial,
+                    op: Op::Like,
+                    value: Value::Pattern("a b*".to_string()),
+                },
+                Term {
+                    field: Field::Speed,
+                    op: Op::Ge,
+                    value: Value::Speed(UsbSpeed::High),
+                },
+            ]
+        );
+        assert_eq!(Selector::parse("  ").unwrap(), Selector::default());
+    }
+
+    #[test]
+    fn selects_devices() {
+        assert_eq!(select(""), vec![0xc31c, 0xdf11, 0x8153]);
+        assert_eq!(select("vid=0x0483 pid=df11"), vec![0xdf11]);
+        assert_eq!(select("class=hid"), vec![0x8153]);
+        assert_eq!(select("class!=hid"), vec![0xc31c, 0xdf11]);
+        assert_eq!(select("speed<high"), vec![0xc31c, 0xdf11]);
+        assert_eq!(select("serial=CD34"), Vec::<u16>::new());
+        assert_eq!(select("serial~CD34"), vec![0x8153]);
+        assert_eq!(select("serial!=AB12"), vec![0xc31c, 0x8153]);
+        assert_eq!(select("service=winusb"), vec![0xdf11]);
+        assert_eq!(select(r"instance=usb\vid_0483*"), vec![0xdf11]);
+        assert_eq!(select("path=1-?"), vec![0xc31c, 0xdf11, 0x8153]);
+        assert_eq!(select("path=*#USB(4)"), vec![0x8153]);
+        assert_eq!(
+            select(r#"product="Gerät" pid>0xc000 pid<0xd000"#),
+            vec![0xc31c]
+        );
+    }
+
+    #[test]
+    fn reports_character_columns() {
+        assert_eq!(
+            Selector::parse("vid=0483 vdi=1").unwrap_err(),
+            "Unknown field 'vdi' at column 10"
+        );
+        // Multi-byte characters before the error count once
+        assert_eq!(
+            Selector::parse(r#"product="Gerät" vdi=1"#).unwrap_err(),
+            "Unknown field 'vdi' at column 17"
+        );
+        assert_eq!(
+            Selector::parse("product=Gerät speed=fast").unwrap_err(),
+            "Invalid speed 'fast', expected low, full, high or super at column 21"
+        );
+        assert_eq!(
+            Selector::parse(r#"serial="ä pid"#).unwrap_err(),
+            "Unterminated quote at column 8"
+        );
+        assert_eq!(
+            Selector::parse("ä vid").unwrap_err(),
+            "Expected a field name in 'ä' at column 1"
+        );
+        assert_eq!(
+            Selector::parse("é=1 product<x").unwrap_err(),
+            "Expected a field name in 'é=1' at column 1"
+        );
+        assert_eq!(
+            Selector::parse("pid=1 product<x").unwrap_err(),
+            "Operator < is not supported for 'product' at column 14"
+        );
+    }
+
+    #[test]
+    fn glob_matches() {
+        assert!(glob_match("a*c", "abbbc"));
+        assert!(glob_match("a?c", "aäc"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("a*d", "abc"));
+        assert!(glob_match("*b*b", "abcbb"));
+    }
+}
@@ -0,0 +1,561 @@
+use std::cmp::Ordering;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::descriptor::Hub;
+
+/// A single port on a hub
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortLocation {
+    pub hub_id: String,
+    pub port_number: u8,
+}
+
+/// A socket on the machine. USB 3 connectors are exposed by Windows as a USB 2.0 and
+/// a SuperSpeed port on two different hubs, both are merged into one connector here.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct PhysicalConnector {
+    /// All hub ports wired to this connector, the first one is the port it was discovered on
+    pub ports: Vec<PortLocation>,
+    pub user_connectable: bool,
+    pub debug_capable: bool,
+    pub type_c: bool,
+    /// Ports of this connector which have a device or hub attached
+    pub attached: Vec<PortLocation>,
+}
+
+impl PhysicalConnector {
+    pub fn contains(&self, hub_id: &str, port_number: u8) -> bool {
+        let hub_id = normalize_hub_id(hub_id);
+        self.ports
+            .iter()
+            .any(|p| p.port_number == port_number && normalize_hub_id(&p.hub_id) == hub_id)
+    }
+}
+
+/// Hub names are reported with and without the `\\?\` or `\\.\` prefix and in varying case
+pub fn normalize_hub_id(hub_id: &str) -> String {
+    let id = hub_id
+        .strip_prefix(r"\\?\")
+        .or_else(|| hub_id.strip_prefix(r"\\.\"))
+        .unwrap_or(hub_id);
+    id.to_lowercase()
+}
+
+fn find_hub<'a>(hubs: &'a [Hub], hub_id: &str) -> Option<&'a Hub> {
+    let hub_id = normalize_hub_id(hub_id);
+    hubs.iter().find(|h| normalize_hub_id(&h.hub_id) == hub_id)
+}
+
+fn is_port_attached(hubs: &[Hub], hub_id: &str, port_number: u8) -> bool {
+    let normalized = normalize_hub_id(hub_id);
+    let has_device = match find_hub(hubs, hub_id) {
+        Some(hub) => hub.devices.iter().any(|d| d.port_number == port_number),
+        None => false,
+    };
+    has_device
+        || hubs.iter().any(|h| {
+            h.parent_port == Some(port_number)
+                && match &h.parent_hub {
+                    Some(parent) => normalize_hub_id(parent) == normalized,
+                    None => false,
+                }
+        })
+}
+
+/// Merges companion ports of all hubs into physical connectors.
+/// Hubs without port connector properties (Windows 7) get one connector per port.
+pub fn physical_connectors(hubs: &[Hub]) -> Vec<PhysicalConnector> {
+    let mut results: Vec<PhysicalConnector> = Vec::new();
+    for hub in hubs {
+        for port_number in 1..=hub.number_of_ports {
+            if results.iter().any(|c| c.contains(&hub.hub_id, port_number)) {
+                continue; // Already added as companion of another port
+            }
+            let mut connector = PhysicalConnector {
+                ports: vec![PortLocation {
+                    hub_id: hub.hub_id.clone(),
+                    port_number,
+                }],
+                user_connectable: false,
+                debug_capable: false,
+                type_c: false,
+                attached: Vec::new(),
+            };
+            if let Some(props) = hub.ports.iter().find(|p| p.port_number == port_number) {
+                connector.user_connectable = props.user_connectable;
+                connector.debug_capable = props.debug_capable;
+                connector.type_c = props.type_c;
+                if let Some(companion_hub) = &props.companion_hub {
+                    if props.companion_port_number != 0 && props.companion_port_number <= 0xff {
+                        let companion_id = match find_hub(hubs, companion_hub) {
+                            Some(companion) => companion.hub_id.clone(),
+                            None => companion_hub.clone(),
+                        };
+                        connector.ports.push(PortLocation {
+                            hub_id: companion_id,
+                            port_number: props.companion_port_number as u8,
+                        });
+                    }
+                }
+            }
+            for port in &connector.ports {
+                if is_port_attached(hubs, &port.hub_id, port.port_number) {
+                    connector.attached.push(port.clone());
+                }
+            }
+            results.push(connector);
+        }
+    }
+    results
+}
+
+/// Returns the physical connector a device on the given hub port is plugged into
+pub fn find_connector<'a>(
+    connectors: &'a [PhysicalConnector],
+    hub_id: &str,
+    port_number: u8,
+) -> Option<&'a PhysicalConnector> {
+    connectors.iter().find(|c| c.contains(hub_id, port_number))
+}
+
+/// Fills the physical connector of every device in the hub list
+pub fn assign_connectors(hubs: &mut [Hub]) {
+    let connectors = physical_connectors(hubs);
+    for hub in hubs.iter_mut() {
+        for device in hub.devices.iter_mut() {
+            device.connector =
+                find_connector(&connectors, &hub.hub_id, device.port_number).cloned();
+        }
+    }
+}
+
+/// Stable identifiers of the port a device is connected to
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceLocation {
+    /// Linux style bus/port path, e.g. `1-4.2.3`
+    pub bus_path: String,
+    /// Windows location path, e.g. `PCIROOT(0)#PCI(1400)#USBROOT(0)#USB(4)#USB(2)`.
+    /// Only available if the location path of the host controller is known.
+    pub location_path: Option<String>,
+    /// Windows location information, e.g. `Port_#0002.Hub_#0003`
+    pub location_info: String,
+}
+
+/// Linux style path, the bus number is followed by the port of every hop
+pub fn bus_port_path(bus: u8, ports: &[u8]) -> String {
+    let ports: Vec<String> = ports.iter().map(|p| p.to_string()).collect();
+    format!("{}-{}", bus, ports.join("."))
+}
+
+/// Appends the USB hops to the location path of the host controller
+pub fn windows_location_path(controller_path: &str, ports: &[u8]) -> String {
+    let mut path = format!("{}#USBROOT(0)", controller_path);
+    for port in ports {
+        path.push_str(format!("#USB({})", port).as_str());
+    }
+    path
+}
+
+pub fn location_information(port_number: u8, hub_number: u32) -> String {
+    format!("Port_#{:04}.Hub_#{:04}", port_number, hub_number)
+}
+
+fn is_child_of(hub: &Hub, parent_id: &str) -> bool {
+    match &hub.parent_hub {
+        Some(parent) => normalize_hub_id(parent) == normalize_hub_id(parent_id),
+        None => false,
+    }
+}
+
+fn root_hub_indices(hubs: &[Hub]) -> Vec<usize> {
+    let mut roots: Vec<usize> = (0..hubs.len())
+        .filter(|i| hubs[*i].parent_hub.is_none())
+        .collect();
+    roots.sort_by_key(|i| hubs[*i].host_controller.as_ref().map(|c| c.index));
+    roots
+}
+
+/// Order of the Windows enumeration: controllers (or buses) in order, downstream hubs by port
+/// and every hub after all hubs behind it
+pub(crate) fn sequential_order(a: &(u8, Vec<u8>), b: &(u8, Vec<u8>)) -> Ordering {
+    a.0.cmp(&b.0).then_with(
+        || match a.1.iter().zip(b.1.iter()).find(|(pa, pb)| pa != pb) {
+            Some((pa, pb)) => pa.cmp(pb),
+            None => b.1.len().cmp(&a.1.len()),
+        },
+    )
+}
+
+/// Numbers hubs depth first, root hubs ordered by host controller and child hubs by port.
+/// This follows the order the hub driver starts hubs in, but Windows may number differently
+/// if hubs were attached after boot. Only a fallback for devices without the location
+/// information of their device node.
+pub fn hub_numbers(hubs: &[Hub]) -> Vec<u32> {
+    hub_numbers_from(hubs, &root_hub_indices(hubs))
+}
+
+fn hub_numbers_from(hubs: &[Hub], roots: &[usize]) -> Vec<u32> {
+    fn visit(hubs: &[Hub], index: usize, next: &mut u32, numbers: &mut Vec<u32>) {
+        *next += 1;
+        numbers[index] = *next;
+        let mut children: Vec<usize> = (0..hubs.len())
+            .filter(|i| is_child_of(&hubs[*i], &hubs[index].hub_id))
+            .collect();
+        children.sort_by_key(|i| hubs[*i].parent_port);
+        for child in children {
+            if numbers[child] == 0 {
+                visit(hubs, child, next, numbers);
+            }
+        }
+    }
+
+    let mut numbers = vec![0; hubs.len()];
+    let mut next = 0;
+    for root in roots {
+        visit(hubs, *root, &mut next, &mut numbers);
+    }
+    numbers
+}
+
+/// Bus number of the root hub of a host controller (`\\.\HCD<n>`), the index of the
+/// controller plus one. A controller whose root hub could not be read keeps its number,
+/// so the buses of the other controllers do not shift.
+pub(crate) fn bus_number(controller_index: u8) -> u8 {
+    controller_index.saturating_add(1)
+}
+
+/// Root hubs of imports have no host controller and are numbered in order
+fn root_bus(hubs: &[Hub], roots: &[usize], position: usize) -> u8 {
+    match &hubs[roots[position]].host_controller {
+        Some(controller) => bus_number(controller.index),
+        None => (position + 1) as u8,
+    }
+}
+
+/// Walks up to the root hub and returns the bus number and the ports of every hop.
+/// Buses are numbered by host controller starting at 1 like on Linux.
+pub fn port_chain(hubs: &[Hub], hub_id: &str, port_number: u8) -> Option<(u8, Vec<u8>)> {
+    port_chain_from(hubs, &root_hub_indices(hubs), hub_id, port_number)
+}
+
+fn port_chain_from(
+    hubs: &[Hub],
+    roots: &[usize],
+    hub_id: &str,
+    port_number: u8,
+) -> Option<(u8, Vec<u8>)> {
+    let mut ports = vec![port_number];
+    let mut hub = find_hub(hubs, hub_id)?;
+    let mut hops = 0;
+    while let (Some(parent), Some(parent_port)) = (&hub.parent_hub, hub.parent_port) {
+        ports.insert(0, parent_port);
+        hub = find_hub(hubs, parent)?;
+        hops += 1;
+        if hops > hubs.len() {
+            return None; // Broken topology with a cycle
+        }
+    }
+    let root_id = normalize_hub_id(&hub.hub_id);
+    let position = roots
+        .iter()
+        .position(|i| normalize_hub_id(&hubs[*i].hub_id) == root_id)?;
+    Some((root_bus(hubs, roots, position), ports))
+}
+
+/// Hub numbers and root hubs, computed once for all devices of a hub list
+struct Locator<'a> {
+    hubs: &'a [Hub],
+    roots: Vec<usize>,
+    numbers: Vec<u32>,
+}
+
+impl<'a> Locator<'a> {
+    fn new(hubs: &'a [Hub]) -> Locator<'a> {
+        let roots = root_hub_indices(hubs);
+        let numbers = hub_numbers_from(hubs, &roots);
+        Locator {
+            hubs,
+            roots,
+            numbers,
+        }
+    }
+
+    fn controller_path(&self, bus: u8) -> Option<String> {
+        let position =
+            (0..self.roots.len()).find(|p| root_bus(self.hubs, &self.roots, *p) == bus)?;
+        self.hubs[self.roots[position]]
+            .host_controller
+            .as_ref()?
+            .location_path
+            .clone()
+    }
+
+    /// Topology derived location, used where the device node does not tell
+    fn location(&self, hub_index: usize, port_number: u8) -> Option<DeviceLocation> {
+        let hub_id = &self.hubs[hub_index].hub_id;
+        let (bus, ports) = port_chain_from(self.hubs, &self.roots, hub_id, port_number)?;
+        Some(DeviceLocation {
+            bus_path: bus_port_path(bus, &ports),
+            location_path: self
+                .controller_path(bus)
+                .map(|p| windows_location_path(&p, &ports)),
+            location_info: location_information(port_number, self.numbers[hub_index]),
+        })
+    }
+}
+
+/// Computes the location of a device connected to the given hub port
+pub fn device_location(hubs: &[Hub], hub_id: &str, port_number: u8) -> Option<DeviceLocation> {
+    let hub_index = hubs
+        .iter()
+        .position(|h| normalize_hub_id(&h.hub_id) == normalize_hub_id(hub_id))?;
+    Locator::new(hubs).location(hub_index, port_number)
+}
+
+/// Fills the location of every device in the hub list. DEVPKEY_Device_LocationInfo and
+/// DEVPKEY_Device_LocationPaths of the device node are preferred over the topology.
+pub fn assign_locations(hubs: &mut [Hub]) {
+    let locator = Locator::new(hubs);
+    let mut locations = Vec::new();
+    for (hub_index, hub) in hubs.iter().enumerate() {
+        for (device_index, device) in hub.devices.iter().enumerate() {
+            let mut location = locator.location(hub_index, device.port_number);
+            if let (Some(location), Some(pnp)) = (&mut location, &device.pnp) {
+                if let Some(info) = &pnp.location_info {
+                    location.location_info = info.clone();
+                }
+                // Devices described by ACPI also have an ACPI path, the USB one is used
+                if let Some(path) = pnp.location_paths.iter().find(|p| p.contains("#USBROOT(")) {
+                    location.location_path = Some(path.clone());
+                }
+            }
+            locations.push((hub_index, device_index, location));
+        }
+    }
+    for (hub_index, device_index, location) in locations {
+        hubs[hub_index].devices[device_index].location = location;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::PortConnectorProperties;
+    use crate::controller::{ControllerFlavor, HostController};
+    use crate::descriptor::Device;
+    use crate::property::PnpProperties;
+
+    fn hub(hub_id: &str, number_of_ports: u8, parent: Option<(&str, u8)>) -> Hub {
+        Hub {
+            hub_id: hub_id.to_string(),
+            number_of_ports,
+            devices: Vec::new(),
+            ports: Vec::new(),
+            parent_hub: parent.map(|(id, _)| id.to_string()),
+            parent_port: parent.map(|(_, port)| port),
+            host_controller: None,
+            descriptor: None,
+        }
+    }
+
+    fn device(port_number: u8) -> Device {
+        Device::from_descriptors(port_number, None, Default::default(), None)
+    }
+
+    fn companion(
+        port_number: u8,
+        hub: &str,
+        companion_port_number: u16,
+    ) -> PortConnectorProperties {
+        PortConnectorProperties {
+            port_number,
+            user_connectable: true,
+            companion_port_number,
+            companion_hub: Some(hub.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Root hub of an xHCI controller: ports 1-2 are USB 2.0, 3-4 SuperSpeed. The
+    /// companions are reported with the `\\?\` prefix and in upper case.
+    fn usb3_root_hub() -> Vec<Hub> {
+        let mut root = hub("USB#ROOT_HUB30#4&1", 4, None);
+        root.ports = vec![
+            companion(1, r"\\?\USB#ROOT_HUB30#4&1", 3),
+            companion(2, r"\\?\USB#ROOT_HUB30#4&1", 4),
+            companion(3, r"\\?\USB#ROOT_HUB30#4&1", 1),
+            companion(4, r"\\?\USB#ROOT_HUB30#4&1", 2),
+        ];
+        root.devices.push(device(4));
+        vec![root]
+    }
+
+    /// Separate USB 2.0 and SuperSpeed hubs of an external USB 3 hub
+    fn split_hub() -> Vec<Hub> {
+        let mut root = hub("root", 2, None);
+        root.ports = vec![companion(1, "ss", 0), companion(2, "hs", 0)];
+        let mut hs = hub("hs", 2, Some(("root", 2)));
+        hs.ports = vec![companion(1, "SS", 1), companion(2, "SS", 2)];
+        hs.devices.push(device(1));
+        let mut ss = hub("ss", 2, Some(("root", 1)));
+        ss.ports = vec![companion(1, "HS", 1), companion(2, "HS", 2)];
+        ss.devices.push(device(2));
+        vec![root, hs, ss]
+    }
+
+    #[test]
+    fn merges_companion_ports_of_one_hub() {
+        let connectors = physical_connectors(&usb3_root_hub());
+        assert_eq!(connectors.len(), 2);
+        assert_eq!(connectors[0].ports.len(), 2);
+        assert!(connectors[0].contains("usb#root_hub30#4&1", 1));
+        assert!(connectors[0].contains("USB#ROOT_HUB30#4&1", 3));
+        assert!(connectors[0].attached.is_empty());
+        assert!(connectors[1].contains("USB#ROOT_HUB30#4&1", 4));
+        assert_eq!(connectors[1].attached.len(), 1);
+        assert_eq!(connectors[1].attached[0].port_number, 4);
+    }
+
+    #[test]
+    fn merges_companion_ports_of_two_hubs() {
+        let connectors = physical_connectors(&split_hub());
+        // Two root hub ports without companions, two merged downstream connectors
+        assert_eq!(connectors.len(), 4);
+        let first = find_connector(&connectors, "ss", 1).unwrap();
+        assert!(first.contains("hs", 1));
+        assert_eq!(first.ports[1].hub_id, "ss");
+        assert_eq!(
+            first.attached,
+            vec![PortLocation {
+                hub_id: "hs".to_string(),
+                port_number: 1,
+            }]
+        );
+        let second = find_connector(&connectors, "hs", 2).unwrap();
+        assert!(second.contains("ss", 2));
+        assert_eq!(second.attached.len(), 1);
+        assert_eq!(second.attached[0].hub_id, "ss");
+
+        // The root hub ports have the downstream hubs attached
+        assert_eq!(
+            find_connector(&connectors, "root", 1)
+                .unwrap()
+                .attached
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn hubs_without_properties_get_one_connector_per_port() {
+        let connectors = physical_connectors(&[hub("root", 3, None)]);
+        assert_eq!(connectors.len(), 3);
+        assert!(connectors
+            .iter()
+            .all(|c| c.ports.len() == 1 && !c.user_connectable));
+    }
+
+    #[test]
+    fn assigns_connectors_to_devices() {
+        let mut hubs = split_hub();
+        assign_connectors(&mut hubs);
+        let connector = hubs[1].devices[0].connector.as_ref().unwrap();
+        assert!(connector.contains("ss", 1));
+        let connector = hubs[2].devices[0].connector.as_ref().unwrap();
+        assert!(connector.contains("hs", 2));
+    }
+
+    fn controller(index: u8, location_path: Option<&str>) -> Option<HostController> {
+        Some(HostController {
+            index,
+            driver_key_name: String::new(),
+            pci: None,
+            flavor: ControllerFlavor::Xhci,
+            number_of_root_ports: None,
+            location_path: location_path.map(String::from),
+        })
+    }
+
+    /// Two controllers listed in reverse order. HCD0 has a hub on port 3 with another
+    /// hub on its port 2 and a device on port 1, HCD1 a device on port 4.
+    fn tree() -> Vec<Hub> {
+        let mut second = hub("root1", 4, None);
+        second.host_controller = controller(1, None);
+        second.devices.push(device(4));
+        let mut first = hub("root0", 4, None);
+        first.host_controller = controller(0, Some("PCIROOT(0)#PCI(1400)"));
+        let mut inner = hub("inner", 4, Some(("outer", 2)));
+        inner.devices.push(device(3));
+        let mut outer = hub("outer", 4, Some((r"\\?\ROOT0", 3)));
+        outer.devices.push(device(1));
+        vec![second, inner, first, outer]
+    }
+
+    #[test]
+    fn numbers_hubs_depth_first_in_controller_order() {
+        assert_eq!(hub_numbers(&tree()), vec![4, 3, 1, 2]);
+        assert_eq!(port_chain(&tree(), "inner", 3), Some((1, vec![3, 2, 3])));
+        assert_eq!(port_chain(&tree(), "root1", 4), Some((2, vec![4])));
+    }
+
+    #[test]
+    fn guesses_locations_from_the_topology() {
+        let mut hubs = tree();
+        assign_locations(&mut hubs);
+        let location = hubs[1].devices[0].location.clone().unwrap();
+        assert_eq!(location.bus_path, "1-3.2.3");
+        assert_eq!(location.location_info, "Port_#0003.Hub_#0003");
+        assert_eq!(
+            location.location_path.as_deref(),
+            Some("PCIROOT(0)#PCI(1400)#USBROOT(0)#USB(3)#USB(2)#USB(3)")
+        );
+        let location = hubs[0].devices[0].location.clone().unwrap();
+        assert_eq!(location.bus_path, "2-4");
+        assert_eq!(location.location_info, "Port_#0004.Hub_#0004");
+        assert_eq!(location.location_path, None);
+    }
+
+    #[test]
+    fn prefers_the_location_of_the_device_node() {
+        let mut hubs = tree();
+        hubs[0].devices[0].pnp = Some(PnpProperties {
+            location_info: Some("Port_#0004.Hub_#0001".to_string()),
+            location_paths: vec![
+                "PCIROOT(0)#PCI(0D00)#USBROOT(0)#USB(4)".to_string(),
+                "ACPI(_SB_)#ACPI(PCI0)#ACPI(XHC_)#ACPI(RHUB)#ACPI(HS04)".to_string(),
+            ],
+            ..Default::default()
+        });
+        // A device node without location keeps the guess
+        hubs[3].devices[0].pnp = Some(PnpProperties::default());
+        assign_locations(&mut hubs);
+
+        let location = hubs[0].devices[0].location.clone().unwrap();
+        assert_eq!(location.bus_path, "2-4");
+        assert_eq!(location.location_info, "Port_#0004.Hub_#0001");
+        assert_eq!(
+            location.location_path.as_deref(),
+            Some("PCIROOT(0)#PCI(0D00)#USBROOT(0)#USB(4)")
+        );
+        let location = hubs[3].devices[0].location.clone().unwrap();
+        assert_eq!(location.location_info, "Port_#0001.Hub_#0002");
+        assert_eq!(
+            location.location_path.as_deref(),
+            Some("PCIROOT(0)#PCI(1400)#USBROOT(0)#USB(3)#USB(1)")
+        );
+    }
+
+    #[test]
+    fn device_location_matches_assigned_location() {
+        let mut hubs = tree();
+        let expected = device_location(&hubs, "INNER", 3);
+        assign_locations(&mut hubs);
+        assert_eq!(hubs[1].devices[0].location, expected);
+        assert_eq!(device_location(&hubs, "missing", 1), None);
+    }
+}
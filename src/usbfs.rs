@@ -0,0 +1,314 @@
+use std::fs;
+use std::path::Path;
+
+use crate::connection::UsbSpeed;
+use crate::descriptor::{
+    UsbConfigurationDescriptor, UsbDeviceDescriptor, USB_CONFIGURATION_DESCRIPTOR_TYPE,
+    USB_DEVICE_DESCRIPTOR_SIZE,
+};
+use crate::lsusb::{build_hubs, import_flat, LsusbDevice, LsusbTreeNode};
+use crate::topology::bus_port_path;
+use crate::Enumeration;
+
+// Binary descriptors of Linux. Reading a device file of usbfs (`/dev/bus/usb/001/002`) or
+// the `descriptors` attribute of sysfs returns the device descriptor followed by all
+// configuration descriptors, each with its interfaces and endpoints.
+
+/// Default directories, pass another root to read a copy of them
+pub const SYSFS_ROOT: &str = "/sys/bus/usb/devices";
+pub const USBFS_ROOT: &str = "/dev/bus/usb";
+
+/// One device with the descriptors of its `descriptors` file
+#[derive(Debug, Clone)]
+pub struct UsbfsDevice {
+    pub bus: u8,
+    pub address: u8,
+    /// Port of every hop from the root hub, None if the device was read from usbfs
+    pub ports: Option<Vec<u8>>,
+    pub speed: Option<UsbSpeed>,
+    /// bConfigurationValue of the active configuration, only known from sysfs
+    pub active_configuration: Option<u8>,
+    pub descriptor: UsbDeviceDescriptor,
+    /// All configurations in the order of the device
+    pub configurations: Vec<UsbConfigurationDescriptor>,
+    /// maxchild of hubs, only known from sysfs
+    pub number_of_ports: Option<u8>,
+}
+
+/// Splits the content of a `descriptors` file into the device and its configurations
+pub fn parse_descriptors(
+    data: &[u8],
+) -> Result<(UsbDeviceDescriptor, Vec<UsbConfigurationDescriptor>), String> {
+    let descriptor = UsbDeviceDescriptor::from_bytes(data)?;
+    let mut configurations = Vec::new();
+    let mut offset = USB_DEVICE_DESCRIPTOR_SIZE;
+    while offset < data.len() {
+        let rest = &data[offset..];
+        if rest.len() < 4 || rest[1] != USB_CONFIGURATION_DESCRIPTOR_TYPE {
+            return Err(format!("No configuration descriptor at offset {}", offset));
+        }
+        let total_length = u16::from_le_bytes([rest[2], rest[3]]) as usize;
+        if total_length < 9 || total_length > rest.len() {
+            return Err(format!(
+                "Invalid wTotalLength {} at offset {}",
+                total_length, offset
+            ));
+        }
+        configurations.push(UsbConfigurationDescriptor::from_bytes(
+            &rest[..total_length],
+        )?);
+        offset += total_length;
+    }
+    Ok((descriptor, configurations))
+}
+
+fn read_descriptors(
+    path: &Path,
+) -> Result<(UsbDeviceDescriptor, Vec<UsbConfigurationDescriptor>), String> {
+    let data = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    parse_descriptors(&data).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Attributes end with a newline, missing and empty ones are None
+fn read_attribute(dir: &Path, name: &str) -> Option<String> {
+    let text = fs::read_to_string(dir.join(name)).ok()?;
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+fn read_number(dir: &Path, name: &str) -> Option<u8> {
+    read_attribute(dir, name)?.trim().parse().ok()
+}
+
+/// The speed attribute is in Mbit/s
+fn parse_speed(speed: &str) -> Option<UsbSpeed> {
+    match speed.trim() {
+        "1.5" => Some(UsbSpeed::Low),
+        "12" => Some(UsbSpeed::Full),
+        "480" => Some(UsbSpeed::High),
+        speed => speed
+            .parse::<u32>()
+            .ok()
+            .filter(|mbits| *mbits >= 5000)
+            .map(|_| UsbSpeed::Super),
+    }
+}
+
+/// `usb1` for root hubs and the bus/port path for other devices, e.g. `1-4.2`.
+/// Interfaces like `1-4.2:1.0` are None.
+fn parse_device_name(name: &str) -> Option<(u8, Vec<u8>)> {
+    if name.contains(':') {
+        return None;
+    }
+    if let Some(bus) = name.strip_prefix("usb") {
+        return Some((bus.parse().ok()?, Vec::new()));
+    }
+    let (bus, ports) = name.split_once('-')?;
+    let ports = ports
+        .split('.')
+        .map(|port| port.parse().ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some((bus.parse().ok()?, ports))
+}
+
+fn read_sysfs_device(dir: &Path, bus: u8, ports: Vec<u8>) -> Result<UsbfsDevice, String> {
+    let (mut descriptor, mut configurations) = read_descriptors(&dir.join("descriptors"))?;
+    let address =
+        read_number(dir, "devnum").ok_or_else(|| format!("Missing devnum in {}", dir.display()))?;
+    descriptor.iManufacturer.1 = read_attribute(dir, "manufacturer");
+    descriptor.iProduct.1 = read_attribute(dir, "product");
+    descriptor.iSerialNumber.1 = read_attribute(dir, "serial");
+
+    // Strings are only available for the active configuration and the current
+    // alternate settings
+    let active_configuration = read_number(dir, "bConfigurationValue");
+    let active = configurations
+        .iter_mut()
+        .find(|c| Some(c.bConfigurationValue) == active_configuration);
+    if let Some(config) = active {
+        config.iConfiguration.1 = read_attribute(dir, "configuration");
+        // Interfaces are subdirectories of the device, the ones of root hubs are named
+        // after port 0, e.g. `1-0:1.0`
+        let device_name = bus_port_path(bus, if ports.is_empty() { &[0] } else { &ports });
+        for interface in config.interfaces.iter_mut() {
+            let interface_dir = dir.join(format!(
+                "{}:{}.{}",
+                device_name, config.bConfigurationValue, interface.bInterfaceNumber
+            ));
+            if read_number(&interface_dir, "bAlternateSetting") == Some(interface.bAlternateSetting)
+            {
+                interface.iInterface.1 = read_attribute(&interface_dir, "interface");
+            }
+        }
+    }
+
+    Ok(UsbfsDevice {
+        bus: read_number(dir, "busnum").unwrap_or(bus),
+        address,
+        ports: Some(ports),
+        speed: read_attribute(dir, "speed").and_then(|s| parse_speed(&s)),
+        active_configuration,
+        descriptor,
+        configurations,
+        number_of_ports: read_number(dir, "maxchild").filter(|n| *n > 0),
+    })
+}
+
+/// Reads all devices of a sysfs device directory, by default [`SYSFS_ROOT`]. Devices
+/// which cannot be read are skipped and their errors added to `errors`.
+pub fn read_sysfs(root: &Path, errors: &mut Vec<String>) -> Result<Vec<UsbfsDevice>, String> {
+    let entries =
+        fs::read_dir(root).map_err(|e| format!("Failed to read {}: {}", root.display(), e))?;
+    let mut devices = Vec::new();
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                errors.push(format!("Failed to read {}: {}", root.display(), e));
+                continue;
+            }
+        };
+        let name = entry.file_name();
+        if let Some((bus, ports)) = name.to_str().and_then(parse_device_name) {
+            match read_sysfs_device(&entry.path(), bus, ports) {
+                Ok(device) => devices.push(device),
+                Err(e) => errors.push(e),
+            }
+        }
+    }
+    // Hubs come before the devices behind them
+    devices.sort_by(|a, b| (a.bus, &a.ports).cmp(&(b.bus, &b.ports)));
+    Ok(devices)
+}
+
+/// Reads all device files of usbfs, by default [`USBFS_ROOT`]. Bus directories and
+/// device files are named after the numbers, e.g. `001/002`. Like [`read_sysfs`],
+/// files which cannot be read are skipped and their errors added to `errors`.
+pub fn read_usbfs(root: &Path, errors: &mut Vec<String>) -> Result<Vec<UsbfsDevice>, String> {
+    let read_dir = |dir: &Path| {
+        let entries =
+            fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        let mut numbered = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+            if let Some(number) = entry.file_name().to_str().and_then(|n| n.parse().ok()) {
+                numbered.push((number, entry.path()));
+            }
+        }
+        numbered.sort();
+        Ok::<Vec<(u8, _)>, String>(numbered)
+    };
+
+    let mut devices = Vec::new();
+    for (bus, bus_dir) in read_dir(root)? {
+        for (address, path) in read_dir(&bus_dir)? {
+            let (descriptor, configurations) = match read_descriptors(&path) {
+                Ok(descriptors) => descriptors,
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+            devices.push(UsbfsDevice {
+                bus,
+                address,
+                ports: None,
+                speed: None,
+                active_configuration: None,
+                descriptor,
+                configurations,
+                number_of_ports: None,
+            });
+        }
+    }
+    Ok(devices)
+}
+
+impl UsbfsDevice {
+    /// The hub builders of lsusb use the first configuration, the active one is moved there
+    fn to_lsusb(&self) -> LsusbDevice {
+        let mut configurations = self.configurations.clone();
+        if let Some(index) = configurations
+            .iter()
+            .position(|c| Some(c.bConfigurationValue) == self.active_configuration)
+        {
+            let active = configurations.remove(index);
+            configurations.insert(0, active);
+        }
+        LsusbDevice {
+            bus: self.bus,
+            address: self.address,
+            descriptor: self.descriptor.clone(),
+            configurations,
+            number_of_ports: self.number_of_ports,
+        }
+    }
+}
+
+/// No devices is an error, with the reasons if all of them failed
+fn no_devices(root: &Path, errors: Vec<String>) -> String {
+    if errors.is_empty() {
+        format!("No USB devices found in {}", root.display())
+    } else {
+        errors.join("\n")
+    }
+}
+
+/// Builds the hubs from sysfs with the topology of the device names. Devices behind a
+/// hub which could not be read are skipped.
+pub fn import_sysfs(root: &Path) -> Result<Enumeration, String> {
+    let mut errors = Vec::new();
+    let mut devices = read_sysfs(root, &mut errors)?;
+    if devices.is_empty() {
+        return Err(no_devices(root, errors));
+    }
+    // Sorted, so the upstream hub of a device is checked before the device itself
+    let mut index = 0;
+    while index < devices.len() {
+        let ports = devices[index].ports.clone().unwrap_or_default();
+        let bus = devices[index].bus;
+        let connected = ports.split_last().is_none_or(|(_, upstream)| {
+            devices[..index]
+                .iter()
+                .any(|d| d.bus == bus && d.ports.as_deref() == Some(upstream))
+        });
+        if connected {
+            index += 1;
+        } else {
+            errors.push(format!(
+                "Skipped {}, its hub could not be read",
+                bus_port_path(bus, &ports)
+            ));
+            devices.remove(index);
+        }
+    }
+
+    let tree: Vec<LsusbTreeNode> = devices
+        .iter()
+        .map(|device| LsusbTreeNode {
+            bus: device.bus,
+            ports: device.ports.clone().unwrap_or_default(),
+            address: device.address,
+            speed: device.speed,
+            number_of_ports: device.number_of_ports,
+        })
+        .collect();
+    let devices: Vec<LsusbDevice> = devices.iter().map(UsbfsDevice::to_lsusb).collect();
+    let hubs = build_hubs(&devices, &tree)?;
+    Ok(Enumeration { hubs, errors })
+}
+
+/// Builds the hubs from usbfs. The device files have no topology, every bus becomes a
+/// root hub with all other devices on port 0.
+pub fn import_usbfs(root: &Path) -> Result<Enumeration, String> {
+    let mut errors = Vec::new();
+    let devices = read_usbfs(root, &mut errors)?;
+    if devices.is_empty() {
+        return Err(no_devices(root, errors));
+    }
+    let devices: Vec<LsusbDevice> = devices.iter().map(UsbfsDevice::to_lsusb).collect();
+    Ok(Enumeration {
+        hubs: import_flat(&devices),
+        errors,
+    })
+}
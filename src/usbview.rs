@@ -0,0 +1,340 @@
+use crate::connection::UsbSpeed;
+use crate::descriptor::Hub;
+use crate::report::{to_hubs, Field, NodeKind, ReportNode, Section};
+use crate::xml::{self, Element};
+
+// Reports of Microsoft's USBView sample:
+// https://learn.microsoft.com/en-us/windows-hardware/drivers/debugger/usbview
+//
+// The text report lists the nodes of the tree one after the other. A hub starts with
+// `Root Hub: <name>` or has an `External Hub: <name>` line, devices start with `[Port<n>]`.
+
+fn section(name: &str) -> Section {
+    match name {
+        "Device Descriptor" => Section::Device,
+        "Configuration Descriptor" => Section::Configuration,
+        "Interface Descriptor" => Section::Interface,
+        "Endpoint Descriptor" => Section::Endpoint,
+        "IAD Descriptor" | "Interface Association Descriptor" => Section::Association,
+        "Hub Descriptor" => Section::Hub,
+        _ => Section::Other,
+    }
+}
+
+fn parse_speed(speed: &str) -> Option<UsbSpeed> {
+    let speed = speed.split_whitespace().next()?;
+    if speed.starts_with("Super") {
+        return Some(UsbSpeed::Super);
+    }
+    match speed {
+        "Low" => Some(UsbSpeed::Low),
+        "Full" => Some(UsbSpeed::Full),
+        "High" => Some(UsbSpeed::High),
+        _ => speed.parse().ok().and_then(UsbSpeed::from_raw),
+    }
+}
+
+/// `[Port3]  :  USB Composite Device`
+fn parse_port_line(line: &str) -> Option<u8> {
+    let rest = line.strip_prefix("[Port")?;
+    let (port, _) = rest.split_once(']')?;
+    port.trim().parse().ok()
+}
+
+/// String of a string index field, on the following line: `English (United States)  "Logitech"`
+fn parse_string_line(line: &str) -> Option<&str> {
+    let start = line.find('"')?;
+    let end = line.rfind('"')?;
+    if end <= start || line[..start].contains(':') {
+        return None;
+    }
+    Some(&line[start + 1..end])
+}
+
+/// Node of the text report with the index of its parent hub
+struct TextNode {
+    node: ReportNode,
+    parent: Option<usize>,
+    has_descriptor: bool,
+    /// Highest port number of the children seen so far
+    last_port: u8,
+}
+
+fn assemble(mut nodes: Vec<TextNode>) -> Vec<ReportNode> {
+    let mut roots = Vec::new();
+    while let Some(text_node) = nodes.pop() {
+        let node = text_node.node;
+        match text_node.parent {
+            Some(parent) => nodes[parent].node.children.insert(0, node),
+            None => roots.insert(0, node),
+        }
+    }
+    roots
+}
+
+/// Parses the text report of USBView, e.g. from `File > Save As`
+pub fn import_text(text: &str) -> Result<Vec<Hub>, String> {
+    let mut nodes: Vec<TextNode> = Vec::new();
+    // Open hubs, innermost last
+    let mut hubs: Vec<usize> = Vec::new();
+    let mut section_kind = Section::Other;
+    // The endpoints of the open pipes repeat the ones of the configuration descriptor
+    let mut in_open_pipes = false;
+    // String index field waiting for its string
+    let mut pending: Option<(Section, String, String)> = None;
+
+    for (number, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if let Some(name) = trimmed.strip_prefix("Root Hub:") {
+            let mut node = ReportNode::new(NodeKind::RootHub);
+            node.name = Some(name.trim().to_string());
+            nodes.push(TextNode {
+                node,
+                parent: None,
+                has_descriptor: false,
+                last_port: 0,
+            });
+            hubs.clear();
+            hubs.push(nodes.len() - 1);
+            section_kind = Section::Other;
+            continue;
+        }
+        if let Some(port_number) = parse_port_line(trimmed) {
+            // The port belongs to the innermost hub which has not listed it yet
+            while let Some(hub) = hubs.last() {
+                let hub = &nodes[*hub];
+                let number_of_ports = hub.node.descriptors.number_of_ports.unwrap_or(u8::MAX);
+                if port_number > hub.last_port && port_number <= number_of_ports {
+                    break;
+                }
+                hubs.pop();
+            }
+            let parent = *hubs
+                .last()
+                .ok_or_else(|| format!("Port on line {} does not belong to a hub", number + 1))?;
+            nodes[parent].last_port = port_number;
+            let mut node = ReportNode::new(NodeKind::Device);
+            node.port_number = Some(port_number);
+            nodes.push(TextNode {
+                node,
+                parent: Some(parent),
+                has_descriptor: false,
+                last_port: 0,
+            });
+            section_kind = Section::Other;
+            in_open_pipes = false;
+            continue;
+        }
+        let current = match nodes.last_mut() {
+            Some(current) => current,
+            None => continue, // Host controller information
+        };
+
+        let header = trimmed.find("===>").and_then(|start| {
+            let name = &trimmed[start + 4..];
+            name.find("<===").map(|end| name[..end].trim())
+        });
+        if let Some(name) = header {
+            match name {
+                "Open Pipes" => in_open_pipes = true,
+                "Full Configuration Descriptor" => in_open_pipes = false,
+                _ => {}
+            }
+            section_kind = if in_open_pipes {
+                Section::Other
+            } else {
+                section(name)
+            };
+            if section_kind == Section::Device {
+                current.has_descriptor = true;
+            }
+            current.node.descriptors.begin(section_kind);
+            pending = None;
+            continue;
+        }
+        if let Some(string) = parse_string_line(trimmed) {
+            if let Some((kind, name, value)) = pending.take() {
+                let field = Field {
+                    name: &name,
+                    value: &value,
+                    string: Some(string),
+                };
+                current.node.descriptors.apply(kind, &field);
+            }
+            continue;
+        }
+        pending = None;
+
+        let (name, rest) = match trimmed.split_once(':') {
+            Some((name, rest)) => (name.trim(), rest.trim()),
+            None => continue,
+        };
+        if name == "External Hub" {
+            current.node.kind = NodeKind::Hub;
+            current.node.name = Some(rest.to_string());
+            let index = nodes.len() - 1;
+            hubs.push(index);
+            continue;
+        }
+        if let Some(position) = trimmed.find("Device Bus Speed:") {
+            current.node.speed = parse_speed(trimmed[position + 17..].trim());
+        }
+        if name == "Number of Ports" || name == "Number of downstream ports" {
+            current.node.descriptors.number_of_ports = rest.parse().ok();
+            continue;
+        }
+        if name.contains(char::is_whitespace) {
+            continue; // Connection information
+        }
+        // `bMaxPacketSize0:                   0x20 = (32) Bytes`
+        let value = rest.split_whitespace().next().unwrap_or("");
+        let field = Field {
+            name,
+            value,
+            string: None,
+        };
+        current.node.descriptors.apply(section_kind, &field);
+        if name.starts_with('i') {
+            pending = Some((section_kind, name.to_string(), value.to_string()));
+        }
+    }
+
+    // Ports without a device only show the connector information
+    let mut kept = Vec::new();
+    let mut new_index = vec![None; nodes.len()];
+    for (index, node) in nodes.into_iter().enumerate() {
+        if node.node.kind == NodeKind::Device && !node.has_descriptor {
+            continue;
+        }
+        let parent = match node.parent {
+            Some(parent) => match new_index[parent] {
+                Some(parent) => Some(parent),
+                None => continue,
+            },
+            None => None,
+        };
+        new_index[index] = Some(kept.len());
+        kept.push(TextNode { parent, ..node });
+    }
+    if kept.is_empty() {
+        return Err("No hubs found in the USBView report".to_string());
+    }
+    Ok(to_hubs(assemble(kept)))
+}
+
+fn node_kind(element: &Element) -> Option<NodeKind> {
+    match element.name.to_ascii_lowercase().as_str() {
+        "roothub" => Some(NodeKind::RootHub),
+        "externalhub" => Some(NodeKind::Hub),
+        "usbdevice" => Some(NodeKind::Device),
+        _ => None,
+    }
+}
+
+fn xml_section(name: &str) -> Option<Section> {
+    let section = match name.to_ascii_lowercase().as_str() {
+        "devicedescriptor" => Section::Device,
+        "configurationdescriptor" => Section::Configuration,
+        "interfacedescriptor" => Section::Interface,
+        "endpointdescriptor" => Section::Endpoint,
+        "iaddescriptor" | "interfaceassociationdescriptor" => Section::Association,
+        "hubdescriptor" => Section::Hub,
+        "openpipes" | "pipeinfo" | "otherspeedconfigurationdescriptor" => Section::Other,
+        _ => return None,
+    };
+    Some(section)
+}
+
+/// Node attributes and leaf elements outside of descriptors
+fn apply_node_field(node: &mut ReportNode, name: &str, value: &str) {
+    match name.to_ascii_lowercase().as_str() {
+        "portnumber" | "connectionindex" | "port" => {
+            node.port_number = xml_field(name, value).byte();
+        }
+        "hubname" | "roothubname" | "symboliclinkname" => node.name = Some(value.to_string()),
+        "speed" | "devicebusspeed" => node.speed = parse_speed(value),
+        "numberofports" | "bnumberofports" => {
+            node.descriptors.number_of_ports = xml_field(name, value).byte();
+        }
+        _ => {}
+    }
+}
+
+/// Fields of a descriptor are attributes or leaf elements, descriptors may be nested
+fn read_descriptor(node: &mut ReportNode, element: &Element, kind: Section) {
+    node.descriptors.begin(kind);
+    for (name, value) in &element.attributes {
+        node.descriptors.apply(kind, &xml_field(name, value));
+    }
+    for child in &element.children {
+        match xml_section(&child.name) {
+            Some(nested) => read_descriptor(node, child, nested),
+            None => {
+                // `<iProduct String="USB Receiver">0x02</iProduct>`
+                let value = child.text.split_whitespace().next().unwrap_or("");
+                let field = Field {
+                    name: &child.name,
+                    value,
+                    string: child.attribute("String"),
+                };
+                node.descriptors.apply(kind, &field);
+            }
+        }
+    }
+}
+
+fn xml_field<'a>(name: &'a str, value: &'a str) -> Field<'a> {
+    Field {
+        name,
+        value: value.split_whitespace().next().unwrap_or(""),
+        string: None,
+    }
+}
+
+fn read_node(node: &mut ReportNode, element: &Element) {
+    for (name, value) in &element.attributes {
+        apply_node_field(node, name, value);
+    }
+    for child in &element.children {
+        if let Some(kind) = node_kind(child) {
+            let mut child_node = ReportNode::new(kind);
+            read_node(&mut child_node, child);
+            node.children.push(child_node);
+        } else if let Some(kind) = xml_section(&child.name) {
+            read_descriptor(node, child, kind);
+        } else if child.children.is_empty() {
+            apply_node_field(node, &child.name, &child.text);
+        } else {
+            // Groups like the connection information
+            read_node(node, child);
+        }
+    }
+}
+
+fn find_roots(element: &Element, roots: &mut Vec<ReportNode>) {
+    for child in &element.children {
+        match node_kind(child) {
+            Some(kind) => {
+                let mut node = ReportNode::new(kind);
+                read_node(&mut node, child);
+                roots.push(node);
+            }
+            None => find_roots(child, roots), // Host controllers
+        }
+    }
+}
+
+/// Parses the XML export of USBView. Hubs are `RootHub` and `ExternalHub` elements,
+/// devices `UsbDevice` elements. Descriptor fields are read from attributes or leaf elements.
+pub fn import_xml(text: &str) -> Result<Vec<Hub>, String> {
+    let document = xml::parse(text)?;
+    let mut roots = Vec::new();
+    find_roots(&document, &mut roots);
+    if roots.is_empty() {
+        return Err("No hubs found in the USBView report".to_string());
+    }
+    Ok(to_hubs(roots))
+}
@@ -0,0 +1,147 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::descriptor::Hub;
+use crate::diff::{diff, DeviceEvent};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchOptions {
+    /// Time between two enumerations
+    pub interval: Duration,
+    /// Number of polls the topology has to stay unchanged before events are reported.
+    /// Devices in transition (resetting, re-enumerating) are missing from single snapshots,
+    /// this avoids reporting them as detached and attached again.
+    pub debounce: u32,
+}
+
+impl Default for WatchOptions {
+    fn default() -> WatchOptions {
+        WatchOptions {
+            interval: Duration::from_secs(1),
+            debounce: 1,
+        }
+    }
+}
+
+/// Polls a snapshot source and reports the differences as events.
+/// The devices present on the first poll are the baseline and not reported.
+pub struct Watcher<F: FnMut() -> Vec<Hub>> {
+    source: F,
+    options: WatchOptions,
+    /// State of the last reported events
+    reported: Option<Vec<Hub>>,
+    /// Last snapshot and for how many polls it is unchanged
+    last: Vec<Hub>,
+    stable: u32,
+    pending: VecDeque<DeviceEvent>,
+}
+
+impl<F: FnMut() -> Vec<Hub>> Watcher<F> {
+    pub fn new(source: F, options: WatchOptions) -> Watcher<F> {
+        Watcher {
+            source,
+            options,
+            reported: None,
+            last: Vec::new(),
+            stable: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Takes one snapshot without waiting, returns the events which settled with it
+    pub fn poll(&mut self) -> Vec<DeviceEvent> {
+        let snapshot = (self.source)();
+        let reported = match &self.reported {
+            Some(reported) => reported,
+            None => {
+                self.reported = Some(snapshot.clone());
+                self.last = snapshot;
+                return Vec::new();
+            }
+        };
+
+        if diff(&self.last, &snapshot).is_empty() {
+            self.stable += 1;
+        } else {
+            self.stable = 0;
+        }
+        let events = if self.stable >= self.options.debounce {
+            diff(reported, &snapshot)
+        } else {
+            Vec::new()
+        };
+        if !events.is_empty() {
+            self.reported = Some(snapshot.clone());
+        }
+        self.last = snapshot;
+        events
+    }
+
+    /// Moves the watcher to a thread which sends the events until the handle
+    /// or the receiver is dropped
+    pub fn spawn(mut self) -> WatchHandle
+    where
+        F: Send + 'static,
+    {
+        let (sender, events) = channel();
+        let (stop, stopped) = channel::<()>();
+        let thread = thread::spawn(move || loop {
+            for event in self.poll() {
+                if sender.send(event).is_err() {
+                    return;
+                }
+            }
+            // Sleeps for the interval, but wakes up as soon as the handle is dropped
+            match stopped.recv_timeout(self.options.interval) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => return,
+            }
+        });
+        WatchHandle {
+            events,
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Events of a spawned watcher. Dropping the handle stops the thread and waits
+/// for the snapshot in progress.
+pub struct WatchHandle {
+    pub events: Receiver<DeviceEvent>,
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WatchHandle {
+    pub fn stop(self) {}
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Blocks until the next event, polling at the configured interval
+impl<F: FnMut() -> Vec<Hub>> Iterator for Watcher<F> {
+    type Item = DeviceEvent;
+
+    fn next(&mut self) -> Option<DeviceEvent> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+            if self.reported.is_some() {
+                thread::sleep(self.options.interval);
+            }
+            let events = self.poll();
+            self.pending.extend(events);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+// Just enough XML for the reports we import: elements, attributes, text and CDATA.
+// Declarations, comments, processing instructions and DOCTYPEs are skipped.
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    /// Text directly inside the element, trimmed
+    pub text: String,
+}
+
+impl Element {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn unescape(text: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find(';')
+            .ok_or_else(|| format!("Unterminated entity in {}", text))?;
+        let entity = &rest[start + 1..start + end];
+        let c = match entity {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = match entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => entity.strip_prefix('#').and_then(|d| d.parse().ok()),
+                };
+                code.and_then(char::from_u32)
+                    .ok_or_else(|| format!("Unknown entity &{};", entity))?
+            }
+        };
+        result.push(c);
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn parse_tag(tag: &str) -> Result<Element, String> {
+    let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+    let mut element = Element {
+        name: tag[..name_end].to_string(),
+        ..Default::default()
+    };
+    let mut rest = tag[name_end..].trim_start();
+    while !rest.is_empty() {
+        let (key, value) = rest
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid attribute in <{}>", tag))?;
+        let value = value.trim_start();
+        let quote = value
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+            .ok_or_else(|| format!("Unquoted attribute in <{}>", tag))?;
+        let end = value[1..]
+            .find(quote)
+            .ok_or_else(|| format!("Unterminated attribute in <{}>", tag))?;
+        element
+            .attributes
+            .push((key.trim().to_string(), unescape(&value[1..end + 1])?));
+        rest = value[end + 2..].trim_start();
+    }
+    Ok(element)
+}
+
+/// Parses a document and returns its root element
+pub(crate) fn parse(text: &str) -> Result<Element, String> {
+    // Open elements, the first one collects the root
+    let mut stack: Vec<Element> = vec![Element::default()];
+    let mut rest = text;
+    while !rest.is_empty() {
+        let start = match rest.find('<') {
+            Some(start) => start,
+            None => {
+                if !rest.trim().is_empty() && stack.len() > 1 {
+                    return Err("Unterminated element".to_string());
+                }
+                break;
+            }
+        };
+        let text = unescape(&rest[..start])?;
+        if let Some(open) = stack.last_mut() {
+            open.text.push_str(text.trim());
+        }
+        rest = &rest[start..];
+
+        let skip = [("<?", "?>"), ("<!--", "-->"), ("<!DOCTYPE", ">")];
+        if let Some((_, end)) = skip.iter().find(|(begin, _)| rest.starts_with(begin)) {
+            let position = rest.find(end).ok_or_else(|| {
+                format!("Unterminated {}", rest.chars().take(20).collect::<String>())
+            })?;
+            rest = &rest[position + end.len()..];
+            continue;
+        }
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = cdata
+                .find("]]>")
+                .ok_or_else(|| "Unterminated CDATA".to_string())?;
+            if let Some(open) = stack.last_mut() {
+                open.text.push_str(cdata[..end].trim());
+            }
+            rest = &cdata[end + 3..];
+            continue;
+        }
+
+        let end = rest
+            .find('>')
+            .ok_or_else(|| "Unterminated tag".to_string())?;
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if let Some(name) = tag.strip_prefix('/') {
+            let element = stack
+                .pop()
+                .filter(|_| !stack.is_empty())
+                .ok_or_else(|| format!("Unexpected </{}>", name.trim()))?;
+            if element.name != name.trim() {
+                return Err(format!(
+                    "Expected </{}>, found </{}>",
+                    element.name,
+                    name.trim()
+                ));
+            }
+            stack.last_mut().unwrap().children.push(element);
+        } else if let Some(tag) = tag.strip_suffix('/') {
+            let element = parse_tag(tag.trim())?;
+            stack.last_mut().unwrap().children.push(element);
+        } else {
+            stack.push(parse_tag(tag.trim())?);
+        }
+    }
+    if stack.len() > 1 {
+        return Err(format!("Unterminated <{}>", stack.last().unwrap().name));
+    }
+    stack
+        .pop()
+        .and_then(|document| document.children.into_iter().next())
+        .ok_or_else(|| "Empty document".to_string())
+}
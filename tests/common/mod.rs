@@ -0,0 +1,64 @@
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+use lswinusb::connection::UsbSpeed;
+use lswinusb::mock::{MockBackend, MockController, MockDevice, MockHub, MockPort};
+use lswinusb::record::{Recorder, Recording};
+use lswinusb::replay::ReplayBackend;
+
+pub const LANG_ID: u16 = 0x0409;
+
+/// File or directory `name` in `tests/fixtures/<dir>`
+pub fn fixture_path(dir: &str, name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(dir)
+        .join(name)
+}
+
+pub fn driver_key(index: u32) -> String {
+    format!(r"{{36fc9e60-c465-11cf-8056-444553540000}}\{:04}", index)
+}
+
+/// One xHCI controller with a drive on port 1 and a hub on port 3. The hub has a keyboard
+/// on port 2 and two identical serial adapters, which only differ in the serial number.
+pub fn machine() -> MockBackend {
+    let mut hub = MockHub::new("USB#VID_05E3&PID_0608#5&1#hub", 4);
+    let keyboard = MockDevice::new(0x046d, 0xc31c, &driver_key(3)).with_strings(
+        "Logitech",
+        "USB Keyboard",
+        None,
+    );
+    hub.attach(2, MockPort::Device(Box::new(keyboard)));
+    for (port, serial) in [(3, "A10K1A2B"), (4, "A10K1C3D")] {
+        let adapter = MockDevice::new(0x0403, 0x6001, &driver_key(port as u32 + 1)).with_strings(
+            "FTDI",
+            "FT232R USB UART",
+            Some(serial),
+        );
+        hub.attach(port, MockPort::Device(Box::new(adapter)));
+    }
+    let mut root = MockHub::new("USB#ROOT_HUB30#4&1#root", 4);
+    let drive = MockDevice::new(0x0781, 0x5583, &driver_key(1))
+        .with_strings("SanDisk", "Ultra Fit", Some("4C530001"))
+        .with_speed(UsbSpeed::Super);
+    root.attach(1, MockPort::Device(Box::new(drive)));
+    root.attach(3, MockPort::Hub(hub));
+    let mut controller = MockController::new(&driver_key(0), root);
+    controller.location_paths = vec!["PCIROOT(0)#PCI(1400)".to_string()];
+    MockBackend::new(vec![controller])
+}
+
+/// Runs the enumeration against the backend and returns everything it answered
+pub fn record(backend: MockBackend) -> Recording {
+    let recorder = Recorder::new(backend);
+    lswinusb::get_all_hubs_with_devices_with(&recorder, LANG_ID);
+    let mut recording = recorder.into_recording();
+    recording.lang_id = Some(LANG_ID);
+    recording
+}
+
+pub fn replay(recording: Recording) -> ReplayBackend {
+    ReplayBackend::new(recording).unwrap()
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use common::{machine, record, replay, LANG_ID};
+use lswinusb::connection::UsbSpeed;
+use lswinusb::mock::{MockBackend, MockController, MockDevice, MockHub, MockPort};
+use lswinusb::{enumerate_parallel_with, enumerate_with, get_all_hubs_with_devices_with};
+
+#[test]
+fn enumerates_mock_machine() {
+    let backend = machine();
+    let hubs = get_all_hubs_with_devices_with(&backend, LANG_ID);
+    assert_eq!(backend.scan_count(), 1);
+    assert_eq!(hubs.len(), 2);
+
+    // Downstream hubs come before their parent
+    let hub = &hubs[0];
+    assert_eq!(hub.hub_id, "USB#VID_05E3&PID_0608#5&1#hub");
+    assert_eq!(hub.parent_port, Some(3));
+    assert_eq!(hub.devices.len(), 3);
+    let keyboard = &hub.devices[0];
+    assert_eq!(
+        keyboard.descriptor.iProduct.1.as_deref(),
+        Some("USB Keyboard")
+    );
+    assert_eq!(keyboard.location.as_ref().unwrap().bus_path, "1-3.2");
+
+    let root = &hubs[1];
+    assert_eq!(root.number_of_ports, 4);
+    let controller = root.host_controller.as_ref().unwrap();
+    assert_eq!(
+        controller.location_path.as_deref(),
+        Some("PCIROOT(0)#PCI(1400)")
+    );
+    let drive = &root.devices[0];
+    assert_eq!(drive.speed, Some(UsbSpeed::Super));
+    assert_eq!(
+        drive.descriptor.iSerialNumber.1.as_deref(),
+        Some("4C530001")
+    );
+    assert_eq!(
+        drive.pnp.as_ref().unwrap().instance_id.as_deref(),
+        Some(r"USB\VID_0781&PID_5583\4C530001")
+    );
+    assert_eq!(drive.configuration.as_ref().unwrap().interfaces.len(), 1);
+    assert_eq!(
+        drive.location.as_ref().unwrap().location_path.as_deref(),
+        Some("PCIROOT(0)#PCI(1400)#USBROOT(0)#USB(1)")
+    );
+    assert!(!drive.speed_downgrade);
+}
+
+#[test]
+fn reads_the_device_index_once_per_enumeration() {
+    let backend = MockBackend::generate(2, 3, 5);
+    let hubs = get_all_hubs_with_devices_with(&backend, LANG_ID);
+    assert_eq!(hubs.len(), 8);
+    assert_eq!(hubs.iter().map(|h| h.devices.len()).sum::<usize>(), 40);
+    assert!(hubs
+        .iter()
+        .flat_map(|h| &h.devices)
+        .all(|d| d.pnp.is_some()));
+    assert_eq!(backend.scan_count(), 1);
+}
+
+#[test]
+fn reads_the_active_configuration() {
+    let mut device = MockDevice::new(
+        0x0bda,
+        0x8153,
+        r"{36fc9e60-c465-11cf-8056-444553540000}\0001",
+    );
+    // Second configuration with a vendor specific and a CDC interface
+    let mut second = device.configurations[0].clone();
+    second[5] = 2;
+    second.extend([9, 4, 1, 0, 0, 0x02, 0x06, 0, 0]);
+    second[2] = second.len() as u8;
+    second[4] = 2;
+    device.configurations.push(second);
+    device.descriptor.bNumConfigurations = 2;
+    device.active_configuration = 2;
+    let mut root = MockHub::new("USB#ROOT_HUB30#4&1#root", 2);
+    root.attach(2, MockPort::Device(Box::new(device.clone())));
+    let backend = MockBackend::new(vec![MockController::new("hcd", root.clone())]);
+
+    let hubs = get_all_hubs_with_devices_with(&backend, LANG_ID);
+    let configuration = hubs[0].devices[0].configuration.as_ref().unwrap();
+    assert_eq!(configuration.bConfigurationValue, 2);
+    assert_eq!(configuration.interfaces.len(), 2);
+
+    // Unconfigured devices report the first configuration
+    device.active_configuration = 0;
+    root.attach(2, MockPort::Device(Box::new(device)));
+    let backend = MockBackend::new(vec![MockController::new("hcd", root)]);
+    let hubs = get_all_hubs_with_devices_with(&backend, LANG_ID);
+    let configuration = hubs[0].devices[0].configuration.as_ref().unwrap();
+    assert_eq!(configuration.bConfigurationValue, 1);
+}
+
+#[test]
+fn parallel_enumeration_matches_sequential() {
+    let backend = MockBackend::generate(3, 3, 4);
+    let sequential = enumerate_with(&backend, LANG_ID, |_| {});
+    for threads in [1, 2, 8] {
+        let parallel = enumerate_parallel_with(&backend, LANG_ID, threads);
+        assert!(parallel.errors.is_empty());
+        assert_eq!(
+            format!("{:?}", parallel.hubs),
+            format!("{:?}", sequential.hubs)
+        );
+    }
+}
+
+#[test]
+fn returns_errors_with_the_hubs_which_could_be_read() {
+    // IOCTL_USB_GET_ROOT_HUB_NAME fails and the device index is not available
+    let mut recording = record(MockBackend::generate(2, 1, 2));
+    recording
+        .ioctls
+        .retain(|r| !(r.device == "HCD1" && r.code == 0x220408));
+    recording.devices = Some(Err("SetupDiGetClassDevsW failed".to_string()));
+    let backend = replay(recording);
+
+    let sequential = enumerate_with(&backend, LANG_ID, |_| {});
+    let parallel = enumerate_parallel_with(&backend, LANG_ID, 4);
+    for enumeration in [sequential, parallel] {
+        assert_eq!(enumeration.hubs.len(), 2);
+        assert!(
+            enumeration
+                .hubs
+                .iter()
+                .all(|h| h.host_controller.is_none()
+                    || h.host_controller.as_ref().unwrap().index == 0)
+        );
+        assert_eq!(enumeration.errors.len(), 2);
+        assert_eq!(enumeration.errors[0], "SetupDiGetClassDevsW failed");
+        assert!(
+            enumeration.errors[1].contains("HCD1"),
+            "{:?}",
+            enumeration.errors
+        );
+    }
+}
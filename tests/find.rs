@@ -0,0 +1,140 @@
+mod common;
+
+use common::{driver_key, machine, record, replay, LANG_ID};
+use lswinusb::mock::{MockController, MockDevice, MockHub, MockPort};
+use lswinusb::query::DeviceQuery;
+use lswinusb::record::Recording;
+use lswinusb::{enumerate_with, find_device_with, find_devices_with};
+
+fn vid_pid(vid: u16, pid: u16, serial: Option<&str>) -> DeviceQuery {
+    DeviceQuery::VidPid {
+        vid,
+        pid,
+        serial: serial.map(String::from),
+    }
+}
+
+#[test]
+fn finds_devices_by_vid_pid_and_serial() {
+    let backend = replay(record(machine()));
+    let adapters = find_devices_with(&backend, &vid_pid(0x0403, 0x6001, None), LANG_ID).unwrap();
+    assert_eq!(adapters.len(), 2);
+    assert_eq!(adapters[0].port_number, 3);
+    assert_eq!(adapters[1].port_number, 4);
+
+    let query = vid_pid(0x0403, 0x6001, Some("A10K1C3D"));
+    let adapter = find_device_with(&backend, &query, LANG_ID)
+        .unwrap()
+        .unwrap();
+    assert_eq!(adapter.port_number, 4);
+    assert_eq!(
+        adapter.descriptor.iSerialNumber.1.as_deref(),
+        Some("A10K1C3D")
+    );
+
+    let query = vid_pid(0x0403, 0x6001, Some("missing"));
+    assert!(find_device_with(&backend, &query, LANG_ID)
+        .unwrap()
+        .is_none());
+    assert!(backend.unmatched().is_empty(), "{:?}", backend.unmatched());
+}
+
+#[test]
+fn finds_devices_by_instance_id_and_location() {
+    let backend = replay(record(machine()));
+    let query = DeviceQuery::InstanceId(r"usb\vid_0781&pid_5583\4C530001".to_string());
+    let drive = find_device_with(&backend, &query, LANG_ID)
+        .unwrap()
+        .unwrap();
+    assert_eq!(drive.descriptor.iProduct.1.as_deref(), Some("Ultra Fit"));
+
+    let query = DeviceQuery::Location("1-3.2".to_string());
+    let keyboard = find_device_with(&backend, &query, LANG_ID)
+        .unwrap()
+        .unwrap();
+    assert_eq!(keyboard.descriptor.idProduct, 0xc31c);
+
+    let query = DeviceQuery::Location("PCIROOT(0)#PCI(1400)#USBROOT(0)#USB(3)#USB(4)".to_string());
+    let adapter = find_device_with(&backend, &query, LANG_ID)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        adapter.descriptor.iSerialNumber.1.as_deref(),
+        Some("A10K1C3D")
+    );
+
+    // Unknown instance IDs are answered from the device index without walking the bus
+    let query = DeviceQuery::InstanceId(r"USB\VID_1234&PID_5678\0".to_string());
+    assert!(find_device_with(&backend, &query, LANG_ID)
+        .unwrap()
+        .is_none());
+    assert!(backend.unmatched().is_empty(), "{:?}", backend.unmatched());
+}
+
+#[test]
+fn returns_errors_instead_of_nothing() {
+    let recording = record(machine());
+
+    // Device properties could not be read
+    let mut broken = recording.clone();
+    broken.devices = Some(Err("Access is denied.".to_string()));
+    let query = vid_pid(0x046d, 0xc31c, None);
+    assert_eq!(
+        find_device_with(&replay(broken), &query, LANG_ID).unwrap_err(),
+        "Access is denied."
+    );
+
+    // The root hub did not answer, so the device may be there
+    let mut broken = recording.clone();
+    broken
+        .ioctls
+        .retain(|r| r.device != "USB#ROOT_HUB30#4&1#root");
+    assert!(find_device_with(&replay(broken), &query, LANG_ID).is_err());
+
+    // Nothing to search at all is not an error
+    let empty = Recording {
+        devices: Some(Ok(Vec::new())),
+        ..Default::default()
+    };
+    assert!(find_devices_with(&replay(empty), &query, LANG_ID)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn numbers_buses_like_the_enumeration_if_a_root_hub_fails() {
+    // The machine behind a second controller, the root hub of the first one cannot be opened
+    let mut backend = machine();
+    let mut root = MockHub::new("USB#ROOT_HUB20#4&0#root", 2);
+    let mouse = MockDevice::new(0x093a, 0x2510, &driver_key(11));
+    root.attach(1, MockPort::Device(Box::new(mouse)));
+    backend
+        .controllers
+        .insert(0, MockController::new(&driver_key(10), root));
+    let mut recording = record(backend);
+    for open in recording.opens.iter_mut() {
+        if open.name == "USB#ROOT_HUB20#4&0#root" {
+            open.result = Err("Access is denied.".to_string());
+        }
+    }
+    let backend = replay(recording);
+
+    let enumeration = enumerate_with(&backend, LANG_ID, |_| {});
+    assert_eq!(enumeration.errors.len(), 1, "{:?}", enumeration.errors);
+    let keyboard = enumeration
+        .hubs
+        .iter()
+        .flat_map(|h| h.devices.iter())
+        .find(|d| d.descriptor.idProduct == 0xc31c)
+        .unwrap();
+    let bus_path = &keyboard.location.as_ref().unwrap().bus_path;
+    assert_eq!(bus_path, "2-3.2");
+
+    let query = DeviceQuery::Location(bus_path.clone());
+    let found = find_device_with(&backend, &query, LANG_ID)
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.descriptor.idProduct, 0xc31c);
+    let query = DeviceQuery::Location("1-3.2".to_string());
+    assert!(find_device_with(&backend, &query, LANG_ID).is_err());
+}
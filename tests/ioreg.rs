@@ -0,0 +1,131 @@
+// `ioreg -p IOUSB -l -a` output in `fixtures/ioreg`: an xHCI controller with
+// IOUSBHostDevice entries behind two hubs, and an EHCI controller of the legacy
+// IOUSBDevice family. Some devices have no `PortNum`, their port is taken from
+// the `locationID`.
+
+mod common;
+
+use std::fs;
+
+use common::fixture_path;
+use lswinusb::connection::UsbSpeed;
+use lswinusb::descriptor::Hub;
+use lswinusb::ioreg;
+
+fn import() -> Vec<Hub> {
+    let text = fs::read_to_string(fixture_path("ioreg", "ioreg.plist")).unwrap();
+    ioreg::import(&text).unwrap()
+}
+
+/// Hub, parent port and the port, VID:PID, speed and bus path of its devices
+fn summary(hubs: &[Hub]) -> Vec<String> {
+    hubs.iter()
+        .map(|hub| {
+            let devices: Vec<String> = hub
+                .devices
+                .iter()
+                .map(|d| {
+                    format!(
+                        "{} {:04x}:{:04x} {:?} {}",
+                        d.port_number,
+                        d.descriptor.idVendor,
+                        d.descriptor.idProduct,
+                        d.speed,
+                        d.location.as_ref().unwrap().bus_path
+                    )
+                })
+                .collect();
+            format!(
+                "{} ({:?} port {:?}): {}",
+                hub.hub_id,
+                hub.parent_hub,
+                hub.parent_port,
+                devices.join(", ")
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn imports_hub_chains() {
+    let hubs = import();
+    assert_eq!(
+        summary(&hubs),
+        vec![
+            r#"USB2.0 Hub@01140000 (Some("USB2.1 Hub@01100000") port Some(4)): 3 1050:0407 Some(Full) 1-1.4.3"#,
+            r#"USB2.1 Hub@01100000 (Some("AppleT8103USBXHCI@01000000") port Some(1)): 2 046d:c52b Some(Full) 1-1.2"#,
+            "AppleT8103USBXHCI@01000000 (None port None): 2 0781:5583 Some(Super) 1-2",
+            r#"BRCM20702 Hub@fa100000 (Some("EHC1@fa000000") port Some(1)): 3 05ac:828f Some(Full) 2-1.3"#,
+            "EHC1@fa000000 (None port None): 2 05ac:8510 Some(High) 2-2, 3 093a:2510 Some(Low) 2-3",
+        ]
+    );
+
+    // Root hubs are the controllers, other hubs keep their own descriptor
+    assert!(hubs[2].descriptor.is_none());
+    let hub = hubs[1].descriptor.as_ref().unwrap();
+    assert_eq!((hub.idVendor, hub.idProduct), (0x05e3, 0x0610));
+    assert_eq!(hub.bDeviceClass, 0x09);
+}
+
+#[test]
+fn reads_descriptors_and_strings() {
+    let hubs = import();
+
+    // IOUSBHostDevice with kUSB*String and the older names
+    let receiver = &hubs[1].devices[0].descriptor;
+    assert_eq!(receiver.bcdDevice, 0x1211);
+    assert_eq!(receiver.bcdUSB, 0x0200);
+    assert_eq!(receiver.iManufacturer.1.as_deref(), Some("Logitech"));
+    assert_eq!(receiver.iProduct.1.as_deref(), Some("USB Receiver"));
+    assert_eq!(receiver.iSerialNumber.1, None);
+
+    let drive = &hubs[2].devices[0].descriptor;
+    assert_eq!(drive.bcdUSB, 0x0320);
+    assert_eq!(
+        drive.iSerialNumber.1.as_deref(),
+        Some("4C530001220528117490")
+    );
+
+    // IOUSBDevice with the USB * Name keys only
+    let camera = &hubs[4].devices[0].descriptor;
+    assert_eq!(camera.bcdDevice, 0x0904);
+    assert_eq!(camera.iManufacturer.1.as_deref(), Some("Apple Inc."));
+    assert_eq!(
+        camera.iProduct.1.as_deref(),
+        Some("FaceTime HD Camera (Built-in)")
+    );
+    assert_eq!(camera.iSerialNumber.1.as_deref(), Some("CC2B1234ABCDEFGH"));
+}
+
+#[test]
+fn speeds_of_both_families() {
+    let speeds: Vec<Option<UsbSpeed>> = import()
+        .iter()
+        .flat_map(|h| h.devices.iter().map(|d| d.speed))
+        .collect();
+    assert_eq!(
+        speeds,
+        vec![
+            Some(UsbSpeed::Full),
+            Some(UsbSpeed::Full),
+            Some(UsbSpeed::Super),
+            Some(UsbSpeed::Full),
+            Some(UsbSpeed::High),
+            Some(UsbSpeed::Low),
+        ]
+    );
+}
+
+#[test]
+fn rejects_other_plists() {
+    let empty = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+	<key>IOObjectClass</key>
+	<string>IORegistryEntry</string>
+</dict>
+</plist>"#;
+    assert!(ioreg::import(empty).is_err());
+    assert!(ioreg::import("<dict></dict>").is_err());
+    assert!(ioreg::import("<plist><dict><key>a</key></dict></plist>").is_err());
+}
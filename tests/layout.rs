@@ -0,0 +1,70 @@
+// IOCTL outputs in `fixtures/ioctl/<arch>`, laid out as usbioctl.h declares them for
+// each architecture. The structures are packed, so one layout decodes all of them.
+
+mod common;
+
+use std::fs;
+
+use common::fixture_path;
+use lswinusb::connection::UsbSpeed;
+use lswinusb::layout::{self, DEVICE_CONNECTED, LAYOUT};
+
+const ARCHS: [&str; 3] = ["x86", "x64", "arm64"];
+
+fn fixture(arch: &str, name: &str) -> Vec<u8> {
+    fs::read(fixture_path("ioctl", &format!("{}/{}.bin", arch, name))).unwrap()
+}
+
+#[test]
+fn layout_matches_usbioctl() {
+    assert_eq!(LAYOUT.connection_status_offset, 31);
+    assert_eq!(LAYOUT.connection_information_size, 46);
+    assert_eq!(LAYOUT.node_information_size, 76);
+    assert_eq!(LAYOUT.number_of_ports_offset, 6);
+    for arch in ARCHS {
+        let connection = fixture(arch, "connection_information_ex");
+        assert_eq!(
+            connection.len(),
+            LAYOUT.connection_information_size,
+            "{}",
+            arch
+        );
+        let node = fixture(arch, "node_information");
+        assert_eq!(node.len(), LAYOUT.node_information_size, "{}", arch);
+    }
+}
+
+#[test]
+fn decodes_connection_information_ex() {
+    // Port, VID, PID, configuration, speed, hub
+    let expected = [
+        (2, 0x0403, 0x6001, 1, UsbSpeed::Full, false),
+        (1, 0x0781, 0x5583, 1, UsbSpeed::Super, false),
+        (3, 0x05e3, 0x0608, 1, UsbSpeed::High, true),
+    ];
+    for (arch, (port, vid, pid, configuration, speed, is_hub)) in ARCHS.iter().zip(expected) {
+        let buf = fixture(arch, "connection_information_ex");
+        assert_eq!(u32::from_le_bytes(buf[0..4].try_into().unwrap()), port);
+        let info = layout::connection_information(&buf).unwrap();
+        assert_eq!(info.connection_status, DEVICE_CONNECTED, "{}", arch);
+        assert_eq!(info.descriptor.idVendor, vid, "{}", arch);
+        assert_eq!(info.descriptor.idProduct, pid, "{}", arch);
+        assert_eq!(info.current_configuration_value, configuration, "{}", arch);
+        assert_eq!(UsbSpeed::from_raw(info.speed), Some(speed), "{}", arch);
+        assert_eq!(info.device_is_hub, is_hub, "{}", arch);
+
+        // ConnectionStatus is the last field before the pipe list
+        assert!(layout::connection_information(&buf[..34]).is_err());
+    }
+}
+
+#[test]
+fn decodes_node_information() {
+    for (arch, ports) in ARCHS.iter().zip([4, 10, 7]) {
+        let buf = fixture(arch, "node_information");
+        assert_eq!(layout::number_of_ports(&buf), Ok(ports), "{}", arch);
+        // bDescriptorType of the hub descriptor precedes bNumberOfPorts
+        assert_eq!(buf[5], 0x29, "{}", arch);
+    }
+    assert!(layout::number_of_ports(&[0; 6]).is_err());
+}
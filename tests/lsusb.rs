@@ -0,0 +1,142 @@
+// `lsusb -v` and `lsusb -t` output in `fixtures/lsusb/<machine>`: a desktop with
+// usbutils 017 and a USB 2.0 machine with the older tree format.
+
+mod common;
+
+use std::fs;
+
+use common::fixture_path;
+use lswinusb::connection::UsbSpeed;
+use lswinusb::descriptor::Hub;
+use lswinusb::lsusb;
+
+fn fixture(machine: &str, name: &str) -> String {
+    fs::read_to_string(fixture_path("lsusb", &format!("{}/{}.txt", machine, name))).unwrap()
+}
+
+fn import(machine: &str) -> Vec<Hub> {
+    let verbose = fixture(machine, "verbose");
+    let tree = fixture(machine, "tree");
+    lsusb::import(&verbose, Some(&tree)).unwrap()
+}
+
+/// Hub and the port, VID:PID, speed and bus path of its devices
+fn summary(hubs: &[Hub]) -> Vec<String> {
+    hubs.iter()
+        .map(|hub| {
+            let devices: Vec<String> = hub
+                .devices
+                .iter()
+                .map(|d| {
+                    format!(
+                        "{}={:04x}:{:04x}/{:?}@{}",
+                        d.port_number,
+                        d.descriptor.idVendor,
+                        d.descriptor.idProduct,
+                        d.speed.unwrap(),
+                        d.location.as_ref().unwrap().bus_path
+                    )
+                })
+                .collect();
+            format!("{} [{}]", hub.hub_id, devices.join(" "))
+        })
+        .collect()
+}
+
+#[test]
+fn imports_desktop() {
+    let hubs = import("desktop");
+    // Child hubs come before their parent like in the Windows enumeration
+    assert_eq!(
+        summary(&hubs),
+        vec![
+            "1-3 [2=046d:c31c/Low@1-3.2]",
+            "usb1 [10=8087:0026/Full@1-10]",
+            "2-2 [4=0781:5583/Super@2-2.4]",
+            "usb2 [1=174c:2362/Super@2-1 3=0bda:8156/Super@2-3]",
+        ]
+    );
+    assert_eq!(hubs[0].parent_hub.as_deref(), Some("usb1"));
+    assert_eq!(hubs[0].parent_port, Some(3));
+    assert_eq!(hubs[0].number_of_ports, 4);
+    assert_eq!(hubs[1].number_of_ports, 12);
+    assert_eq!(
+        hubs[2].descriptor.as_ref().unwrap().iProduct.1.as_deref(),
+        Some("USB3.2 Hub")
+    );
+
+    let keyboard = &hubs[0].devices[0];
+    assert_eq!(
+        keyboard.descriptor.iProduct.1.as_deref(),
+        Some("USB Keyboard")
+    );
+    assert_eq!(keyboard.descriptor.bcdUSB, 0x0110);
+    let configuration = keyboard.configuration.as_ref().unwrap();
+    assert_eq!(configuration.bMaxPower, 45);
+    assert_eq!(configuration.interfaces.len(), 2);
+    assert_eq!(configuration.interfaces[0].endpoints[0].bInterval, 10);
+
+    let drive = &hubs[2].devices[0];
+    assert_eq!(
+        drive.descriptor.iSerialNumber.1.as_deref(),
+        Some("4C530001220418112321")
+    );
+    // 8 mA units at SuperSpeed
+    assert_eq!(drive.configuration.as_ref().unwrap().bMaxPower, 112);
+}
+
+#[test]
+fn imports_the_old_tree_format() {
+    let hubs = import("ehci");
+    assert_eq!(
+        summary(&hubs),
+        vec!["3-1 [4=0403:6001/Full@3-1.4]", "usb3 []"]
+    );
+    assert_eq!(hubs[0].number_of_ports, 8);
+    assert_eq!(hubs[1].number_of_ports, 2);
+
+    // lsusb could not open the adapter, the string indexes are known but not the strings
+    let adapter = &hubs[0].devices[0];
+    assert_eq!(adapter.descriptor.iSerialNumber, (3, None));
+    assert_eq!(adapter.descriptor.iProduct, (2, None));
+}
+
+#[test]
+fn parses_superspeed_plus_lanes() {
+    let tree = lsusb::parse_tree(&fixture("desktop", "tree")).unwrap();
+    let speeds: Vec<(String, Option<UsbSpeed>)> = tree
+        .iter()
+        .map(|node| {
+            let ports: Vec<String> = node.ports.iter().map(|p| p.to_string()).collect();
+            (format!("{}-{}", node.bus, ports.join(".")), node.speed)
+        })
+        .collect();
+    assert_eq!(
+        speeds,
+        vec![
+            ("1-".to_string(), Some(UsbSpeed::High)),
+            ("1-3".to_string(), Some(UsbSpeed::High)),
+            ("1-3.2".to_string(), Some(UsbSpeed::Low)),
+            ("1-10".to_string(), Some(UsbSpeed::Full)),
+            ("2-".to_string(), Some(UsbSpeed::Super)),
+            ("2-1".to_string(), Some(UsbSpeed::Super)),
+            ("2-2".to_string(), Some(UsbSpeed::Super)),
+            ("2-2.4".to_string(), Some(UsbSpeed::Super)),
+            ("2-3".to_string(), Some(UsbSpeed::Super)),
+        ]
+    );
+}
+
+#[test]
+fn imports_without_the_tree() {
+    let hubs = lsusb::import(&fixture("desktop", "verbose"), None).unwrap();
+    assert_eq!(hubs.len(), 2);
+    assert_eq!(hubs[0].hub_id, "usb1");
+    assert_eq!(hubs[0].number_of_ports, 12);
+    assert_eq!(hubs[0].devices.len(), 3);
+    assert_eq!(hubs[1].devices.len(), 4);
+    assert!(hubs
+        .iter()
+        .flat_map(|h| &h.devices)
+        .all(|d| d.port_number == 0));
+}
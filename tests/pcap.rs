@@ -0,0 +1,123 @@
+// Captures in `fixtures/pcap`: a usbmon pcap of a Linux machine enumerating a
+// keyboard receiver and a flash drive which stalls a string request, and a
+// USBPcap pcapng of the keyboard receiver and a serial adapter on Windows.
+
+mod common;
+
+use std::fs;
+
+use common::fixture_path;
+use lswinusb::pcap;
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(fixture_path("pcap", name)).unwrap()
+}
+
+const KEYBOARD_REPORT_LENGTH: usize = 63;
+const MOUSE_REPORT_LENGTH: usize = 52;
+
+#[test]
+fn reads_usbmon_capture() {
+    let devices = pcap::read_capture(&fixture("usbmon.pcap")).unwrap();
+    let addresses: Vec<(u16, u8)> = devices.iter().map(|d| (d.bus, d.address)).collect();
+    assert_eq!(addresses, vec![(1, 1), (1, 4), (1, 5)]);
+
+    // Enumerated at address 0 and moved by SET_ADDRESS
+    let receiver = &devices[1];
+    let descriptor = receiver.descriptor.as_ref().unwrap();
+    assert_eq!(
+        (descriptor.idVendor, descriptor.idProduct),
+        (0x046d, 0xc52b)
+    );
+    assert_eq!(receiver.languages, vec![0x0409]);
+    assert_eq!(receiver.string(1).as_deref(), Some("Logitech"));
+    assert_eq!(receiver.string(2).as_deref(), Some("USB Receiver"));
+    let reports: Vec<(u8, usize)> = receiver
+        .hid_reports
+        .iter()
+        .map(|(interface, report)| (*interface, report.len()))
+        .collect();
+    assert_eq!(
+        reports,
+        vec![(0, KEYBOARD_REPORT_LENGTH), (1, MOUSE_REPORT_LENGTH)]
+    );
+
+    let flash = &devices[2];
+    assert_eq!(flash.string(2), None);
+    assert!(flash.hid_reports.is_empty());
+}
+
+#[test]
+fn imports_usbmon_capture() {
+    let hubs = pcap::import(&fixture("usbmon.pcap")).unwrap();
+    assert_eq!(hubs.len(), 1);
+    let hub = &hubs[0];
+    assert_eq!(hub.hub_id, "usb1");
+    assert_eq!(hub.descriptor.as_ref().map(|d| d.idVendor), Some(0x1d6b));
+
+    let ids: Vec<(u16, u16)> = hub
+        .devices
+        .iter()
+        .map(|d| (d.descriptor.idVendor, d.descriptor.idProduct))
+        .collect();
+    assert_eq!(ids, vec![(0x046d, 0xc52b), (0x0781, 0x5567)]);
+
+    let receiver = &hub.devices[0];
+    assert_eq!(
+        receiver.descriptor.iProduct.1.as_deref(),
+        Some("USB Receiver")
+    );
+    assert_eq!(receiver.configuration.as_ref().unwrap().interfaces.len(), 2);
+    let interfaces: Vec<u8> = receiver.hid_reports.iter().map(|(i, _)| *i).collect();
+    assert_eq!(interfaces, vec![0, 1]);
+    // Usage page generic desktop, usage keyboard
+    assert_eq!(receiver.hid_reports[0].1[..4], [0x05, 0x01, 0x09, 0x06]);
+    assert_eq!(receiver.hid_reports[1].1.len(), MOUSE_REPORT_LENGTH);
+
+    let flash = &hub.devices[1];
+    assert_eq!(flash.descriptor.iManufacturer.1, None);
+    assert_eq!(flash.descriptor.iProduct.1, None);
+}
+
+#[test]
+fn imports_usbpcap_capture() {
+    let hubs = pcap::import(&fixture("usbpcap.pcapng")).unwrap();
+    assert_eq!(hubs.len(), 1);
+    let hub = &hubs[0];
+    assert!(hub.descriptor.is_none());
+
+    let products: Vec<Option<&str>> = hub
+        .devices
+        .iter()
+        .map(|d| d.descriptor.iProduct.1.as_deref())
+        .collect();
+    assert_eq!(
+        products,
+        vec![Some("USB Receiver"), Some("FT232R USB UART")]
+    );
+
+    let receiver = &hub.devices[0];
+    let reports: Vec<(u8, usize)> = receiver
+        .hid_reports
+        .iter()
+        .map(|(interface, report)| (*interface, report.len()))
+        .collect();
+    assert_eq!(
+        reports,
+        vec![(0, KEYBOARD_REPORT_LENGTH), (1, MOUSE_REPORT_LENGTH)]
+    );
+
+    let serial = &hub.devices[1];
+    assert_eq!(
+        serial.descriptor.iSerialNumber.1.as_deref(),
+        Some("A10K1A2B")
+    );
+    assert!(serial.hid_reports.is_empty());
+}
+
+#[test]
+fn rejects_truncated_capture() {
+    let data = fixture("usbpcap.pcapng");
+    assert!(pcap::read_capture(&data[..20]).is_err());
+    assert!(pcap::read_capture(b"not a capture").is_err());
+}
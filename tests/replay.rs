@@ -0,0 +1,124 @@
+// Replays the recordings in `fixtures/recordings`. They were taken from the mock
+// machines below with `cargo test --test replay -- --ignored`, a changed walk
+// shows up as unmatched or unused requests.
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use common::{fixture_path, machine, record, replay, LANG_ID};
+use lswinusb::descriptor::Hub;
+use lswinusb::mock::MockBackend;
+use lswinusb::record::Recording;
+use lswinusb::replay::ReplayBackend;
+use lswinusb::{enumerate_parallel_with, enumerate_with};
+
+fn path(name: &str) -> PathBuf {
+    fixture_path("recordings", &format!("{}.json", name))
+}
+
+fn load(name: &str) -> ReplayBackend {
+    let data = fs::read_to_string(path(name)).unwrap();
+    replay(serde_json::from_str::<Recording>(&data).unwrap())
+}
+
+/// Enumerates the recording and checks that it was replayed completely
+fn enumerate(name: &str) -> Vec<Hub> {
+    let backend = load(name);
+    assert_eq!(backend.lang_id(), Some(LANG_ID));
+    let enumeration = enumerate_with(&backend, LANG_ID, |_| {});
+    assert!(enumeration.errors.is_empty(), "{:?}", enumeration.errors);
+    assert!(backend.unmatched().is_empty(), "{:?}", backend.unmatched());
+    assert!(backend.unused().is_empty(), "{:?}", backend.unused());
+    enumeration.hubs
+}
+
+/// Hub, parent port and the VID:PID of the devices on its ports
+fn summary(hubs: &[Hub]) -> Vec<String> {
+    hubs.iter()
+        .map(|hub| {
+            let devices: Vec<String> = hub
+                .devices
+                .iter()
+                .map(|d| {
+                    format!(
+                        "{}={:04x}:{:04x}",
+                        d.port_number, d.descriptor.idVendor, d.descriptor.idProduct
+                    )
+                })
+                .collect();
+            format!(
+                "{} {:?} [{}]",
+                hub.hub_id,
+                hub.parent_port,
+                devices.join(" ")
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn replays_desk() {
+    let hubs = enumerate("desk");
+    assert_eq!(
+        summary(&hubs),
+        vec![
+            "USB#VID_05E3&PID_0608#5&1#hub Some(3) [2=046d:c31c 3=0403:6001 4=0403:6001]",
+            "USB#ROOT_HUB30#4&1#root None [1=0781:5583]",
+        ]
+    );
+    let serials: Vec<Option<&str>> = hubs[0]
+        .devices
+        .iter()
+        .map(|d| d.descriptor.iSerialNumber.1.as_deref())
+        .collect();
+    assert_eq!(serials, vec![None, Some("A10K1A2B"), Some("A10K1C3D")]);
+    assert_eq!(
+        hubs[1].devices[0].location.as_ref().unwrap().bus_path,
+        "1-1"
+    );
+}
+
+#[test]
+fn replays_hub_chain() {
+    let hubs = enumerate("hub-chain");
+    assert_eq!(hubs.len(), 8);
+    assert_eq!(
+        hubs.iter()
+            .map(|h| h.host_controller.as_ref().map(|c| c.index))
+            .collect::<Vec<_>>(),
+        vec![None, None, None, Some(0), None, None, None, Some(1)]
+    );
+    assert_eq!(hubs.iter().map(|h| h.devices.len()).sum::<usize>(), 16);
+    // Every hub is reported after the hubs behind it
+    for (i, hub) in hubs.iter().enumerate() {
+        if let Some(parent) = &hub.parent_hub {
+            assert!(hubs[i + 1..].iter().any(|h| &h.hub_id == parent));
+        }
+    }
+}
+
+#[test]
+fn parallel_enumeration_replays_the_same_requests() {
+    for name in ["desk", "hub-chain"] {
+        let sequential = enumerate(name);
+        let backend = load(name);
+        let parallel = enumerate_parallel_with(&backend, LANG_ID, 4);
+        assert!(backend.unmatched().is_empty(), "{:?}", backend.unmatched());
+        assert!(backend.unused().is_empty(), "{:?}", backend.unused());
+        assert_eq!(format!("{:?}", parallel.hubs), format!("{:?}", sequential));
+    }
+}
+
+#[test]
+#[ignore]
+fn regenerate_recordings() {
+    for (name, backend) in [
+        ("desk", machine()),
+        ("hub-chain", MockBackend::generate(2, 3, 2)),
+    ] {
+        let recording = serde_json::to_string_pretty(&record(backend)).unwrap();
+        fs::write(path(name), recording + "\n").unwrap();
+    }
+}
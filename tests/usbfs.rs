@@ -0,0 +1,175 @@
+// Copies of `/sys/bus/usb/devices` and `/dev/bus/usb` in `fixtures/linux/sysfs` and
+// `fixtures/linux/usbfs`: a USB 2.0 bus with a hub, a keyboard receiver and a gadget
+// running its second configuration, a USB 3 bus with a flash drive, and a hub
+// whose descriptors are cut off. The interface directories like `1-4:2.0` are
+// not checked in, colons are not allowed in file names on Windows.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use common::fixture_path;
+use lswinusb::connection::UsbSpeed;
+use lswinusb::descriptor::Hub;
+use lswinusb::usbfs;
+
+fn fixture(name: &str) -> PathBuf {
+    fixture_path("linux", name)
+}
+
+/// Hub and the port, VID:PID and speed of its devices
+fn summary(hubs: &[Hub]) -> Vec<String> {
+    hubs.iter()
+        .map(|hub| {
+            let devices: Vec<String> = hub
+                .devices
+                .iter()
+                .map(|d| {
+                    format!(
+                        "{} {:04x}:{:04x} {:?}",
+                        d.port_number, d.descriptor.idVendor, d.descriptor.idProduct, d.speed
+                    )
+                })
+                .collect();
+            format!("{}: {}", hub.hub_id, devices.join(", "))
+        })
+        .collect()
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+    for entry in fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        let target = to.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &target);
+        } else {
+            fs::copy(entry.path(), target).unwrap();
+        }
+    }
+}
+
+#[test]
+fn imports_sysfs() {
+    let enumeration = usbfs::import_sysfs(&fixture("sysfs")).unwrap();
+    assert_eq!(
+        summary(&enumeration.hubs),
+        vec![
+            "1-1: 3 046d:c52b Some(Full)",
+            "usb1: 4 1d6b:0104 Some(High)",
+            "usb2: 1 0781:5581 Some(Super)",
+        ]
+    );
+    let hub = &enumeration.hubs[0];
+    assert_eq!(hub.parent_hub.as_deref(), Some("usb1"));
+    assert_eq!(hub.parent_port, Some(1));
+
+    let errors = &enumeration.errors;
+    assert_eq!(errors.len(), 2, "{:?}", errors);
+    assert!(errors[0].contains("1-2") && errors[0].contains("descriptors"));
+    assert_eq!(errors[1], "Skipped 1-2.1, its hub could not be read");
+
+    let root = enumeration.hubs[1].descriptor.as_ref().unwrap();
+    assert_eq!(root.iProduct.1.as_deref(), Some("xHCI Host Controller"));
+    assert_eq!(enumeration.hubs[1].number_of_ports, 12);
+
+    // The active configuration is used and has its string
+    let gadget = &enumeration.hubs[1].devices[0];
+    assert_eq!(
+        gadget.descriptor.iSerialNumber.1.as_deref(),
+        Some("0123456789")
+    );
+    let configuration = gadget.configuration.as_ref().unwrap();
+    assert_eq!(configuration.bConfigurationValue, 2);
+    assert_eq!(configuration.iConfiguration.1.as_deref(), Some("RNDIS"));
+    assert_eq!(
+        gadget.location.as_ref().map(|l| l.bus_path.as_str()),
+        Some("1-4")
+    );
+}
+
+#[test]
+fn reads_interface_strings() {
+    let root = std::env::temp_dir().join(format!("lswinusb-sysfs-{}", std::process::id()));
+    copy_dir(&fixture("sysfs"), &root);
+    // Interfaces of the active configuration, also listed in the device directory
+    for dir in [root.join("1-4/1-4:2.0"), root.join("1-4:2.0")] {
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("bAlternateSetting"), " 0\n").unwrap();
+        fs::write(dir.join("interface"), "RNDIS Communications Control\n").unwrap();
+    }
+
+    let mut errors = Vec::new();
+    let devices = usbfs::read_sysfs(&root, &mut errors);
+    fs::remove_dir_all(&root).unwrap();
+    let devices = devices.unwrap();
+    assert_eq!(devices.len(), 7);
+    assert_eq!(errors.len(), 1);
+
+    let gadget = devices
+        .iter()
+        .find(|d| d.ports.as_deref() == Some(&[4]))
+        .unwrap();
+    assert_eq!(gadget.active_configuration, Some(2));
+    let interfaces: Vec<Option<&str>> = gadget.configurations[1]
+        .interfaces
+        .iter()
+        .map(|i| i.iInterface.1.as_deref())
+        .collect();
+    assert_eq!(interfaces, vec![Some("RNDIS Communications Control"), None]);
+    // Strings of the inactive configuration are not available
+    assert_eq!(gadget.configurations[0].iConfiguration.1, None);
+}
+
+#[test]
+fn imports_usbfs() {
+    let enumeration = usbfs::import_usbfs(&fixture("usbfs")).unwrap();
+    assert_eq!(
+        summary(&enumeration.hubs),
+        vec![
+            "usb1: 0 05e3:0610 None, 0 1d6b:0104 None, 0 046d:c52b None, 0 093a:2510 None",
+            "usb2: 0 0781:5581 None",
+        ]
+    );
+    assert_eq!(enumeration.errors.len(), 1);
+    assert!(enumeration.errors[0].contains("004"));
+
+    // Device files have no strings and the first configuration comes first
+    let gadget = &enumeration.hubs[0].devices[1];
+    assert_eq!(gadget.descriptor.iProduct.1, None);
+    assert_eq!(
+        gadget.configuration.as_ref().map(|c| c.bConfigurationValue),
+        Some(1)
+    );
+    assert_eq!(
+        enumeration.hubs[1].descriptor.as_ref().map(|d| d.idProduct),
+        Some(0x0003)
+    );
+}
+
+#[test]
+fn rejects_missing_root() {
+    let missing = fixture("no-such-dir");
+    assert!(usbfs::import_sysfs(&missing).is_err());
+    assert!(usbfs::import_usbfs(&missing).is_err());
+}
+
+#[test]
+fn speeds_of_sysfs() {
+    let mut errors = Vec::new();
+    let devices = usbfs::read_sysfs(&fixture("sysfs"), &mut errors).unwrap();
+    let speeds: Vec<Option<UsbSpeed>> = devices.iter().map(|d| d.speed).collect();
+    assert_eq!(
+        speeds,
+        vec![
+            Some(UsbSpeed::High),
+            Some(UsbSpeed::High),
+            Some(UsbSpeed::Full),
+            Some(UsbSpeed::Low),
+            Some(UsbSpeed::High),
+            Some(UsbSpeed::Super),
+            Some(UsbSpeed::Super),
+        ]
+    );
+}
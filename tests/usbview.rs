@@ -0,0 +1,109 @@
+// USBView reports in `fixtures/usbview`: the same machine as text report and XML export
+
+mod common;
+
+use std::fs;
+
+use common::fixture_path;
+use lswinusb::descriptor::Hub;
+use lswinusb::usbview;
+
+fn fixture(name: &str) -> String {
+    fs::read_to_string(fixture_path("usbview", name)).unwrap()
+}
+
+/// Hub, parent port and the port, VID:PID, speed and product of its devices
+fn summary(hubs: &[Hub]) -> Vec<String> {
+    hubs.iter()
+        .map(|hub| {
+            let devices: Vec<String> = hub
+                .devices
+                .iter()
+                .map(|d| {
+                    format!(
+                        "{}={:04x}:{:04x}/{:?} {}",
+                        d.port_number,
+                        d.descriptor.idVendor,
+                        d.descriptor.idProduct,
+                        d.speed.unwrap(),
+                        d.descriptor.iProduct.1.as_deref().unwrap_or("-")
+                    )
+                })
+                .collect();
+            format!(
+                "{} {:?} [{}]",
+                hub.hub_id.split('#').nth(1).unwrap(),
+                hub.parent_port,
+                devices.join(", ")
+            )
+        })
+        .collect()
+}
+
+fn check(hubs: &[Hub]) {
+    assert_eq!(
+        summary(hubs),
+        vec![
+            "VID_05E3&PID_0608 Some(3) [2=1209:0001/High Messgerät]",
+            "ROOT_HUB30 None [1=046d:c52b/Full USB Receiver]",
+        ]
+    );
+    let hub = &hubs[0];
+    assert_eq!(hub.number_of_ports, 4);
+    assert_eq!(
+        hub.parent_hub.as_deref(),
+        Some("USB#ROOT_HUB30#4&1a2b3c4d&0&0#{f18a0e88-c30c-11d0-8815-00a0c906bed8}")
+    );
+    assert_eq!(hub.descriptor.as_ref().unwrap().bDeviceClass, 0x09);
+    assert_eq!(hubs[1].number_of_ports, 4);
+
+    let device = &hub.devices[0];
+    assert_eq!(
+        device.descriptor.iManufacturer,
+        (1, Some("Müller GmbH".to_string()))
+    );
+    assert_eq!(
+        device.descriptor.iSerialNumber,
+        (3, Some("MG-0042".to_string()))
+    );
+    assert_eq!(device.location.as_ref().unwrap().bus_path, "1-3.2");
+    let configuration = device.configuration.as_ref().unwrap();
+    assert_eq!(configuration.bMaxPower, 0xfa);
+    assert_eq!(configuration.associations.len(), 1);
+    assert_eq!(configuration.associations[0].bInterfaceCount, 2);
+    let endpoints: Vec<Vec<u8>> = configuration
+        .interfaces
+        .iter()
+        .map(|i| i.endpoints.iter().map(|e| e.bEndpointAddress).collect())
+        .collect();
+    assert_eq!(endpoints, vec![vec![0x83], vec![0x01, 0x82]]);
+
+    // The open pipes repeat the endpoints of the configuration
+    let receiver = &hubs[1].devices[0].configuration.as_ref().unwrap();
+    assert_eq!(receiver.iConfiguration.1.as_deref(), Some("RQR12.11_B0032"));
+    assert_eq!(receiver.interfaces.len(), 1);
+    assert_eq!(receiver.interfaces[0].endpoints.len(), 1);
+}
+
+#[test]
+fn imports_text_report() {
+    check(&usbview::import_text(&fixture("desk.txt")).unwrap());
+}
+
+#[test]
+fn imports_xml_export() {
+    check(&usbview::import_xml(&fixture("desk.xml")).unwrap());
+}
+
+#[test]
+fn rejects_malformed_reports_without_panicking() {
+    // Markers in the wrong order
+    let text = "Root Hub: root\nNumber of Ports: 2\n[Port1]\n<===Device Descriptor===>\nidVendor: 0x1234\n";
+    assert!(usbview::import_text(text).is_ok());
+    assert!(usbview::import_text("<=== ===>").is_err());
+
+    // Unterminated comment with multi-byte characters at the cut
+    let err = usbview::import_xml("<!-- Gerätebaum äöü äöü").unwrap_err();
+    assert_eq!(err, "Unterminated <!-- Gerätebaum äöü ");
+    assert!(usbview::import_xml("<RootHub><UsbDevice></RootHub>").is_err());
+}
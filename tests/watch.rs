@@ -0,0 +1,116 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{machine, LANG_ID};
+use lswinusb::diff::DeviceEvent;
+use lswinusb::get_all_hubs_with_devices_with;
+use lswinusb::mock::{MockBackend, MockPort};
+use lswinusb::watch::{WatchOptions, Watcher};
+use lswinusb::watch_with;
+
+const OPTIONS: WatchOptions = WatchOptions {
+    interval: Duration::from_millis(1),
+    debounce: 1,
+};
+
+/// The mock machine with the keyboard on port 2 of the external hub unplugged
+fn without_keyboard() -> MockBackend {
+    let mut backend = machine();
+    match &mut backend.controllers[0].root_hub.ports[2] {
+        Some(MockPort::Hub(hub)) => hub.ports[1] = None,
+        _ => unreachable!(),
+    }
+    backend
+}
+
+fn summary(events: &[DeviceEvent]) -> Vec<String> {
+    events
+        .iter()
+        .map(|event| match event {
+            DeviceEvent::Attached { identity, .. } => format!("attached {}", identity),
+            DeviceEvent::Detached { identity, .. } => format!("detached {}", identity),
+            event => format!("{:?}", event),
+        })
+        .collect()
+}
+
+#[test]
+fn does_not_report_the_baseline() {
+    let mut watcher = watch_with(machine(), LANG_ID, OPTIONS);
+    assert!(watcher.poll().is_empty());
+    assert!(watcher.poll().is_empty());
+}
+
+#[test]
+fn reports_events_once_the_topology_settled() {
+    let snapshots = [
+        machine(),
+        without_keyboard(),
+        without_keyboard(),
+        without_keyboard(),
+        machine(),
+        machine(),
+    ];
+    let mut snapshots = snapshots.iter();
+    let mut watcher = Watcher::new(
+        || get_all_hubs_with_devices_with(snapshots.next().unwrap(), LANG_ID),
+        OPTIONS,
+    );
+    let polls: Vec<Vec<String>> = (0..6).map(|_| summary(&watcher.poll())).collect();
+    assert_eq!(
+        polls,
+        vec![
+            vec![],
+            vec![],
+            vec!["detached 046d:c31c at 1-3.2".to_string()],
+            vec![],
+            vec![],
+            vec!["attached 046d:c31c at 1-3.2".to_string()],
+        ]
+    );
+}
+
+#[test]
+fn a_device_missing_from_one_snapshot_is_not_reported() {
+    let snapshots = [machine(), without_keyboard(), machine(), machine()];
+    let mut snapshots = snapshots.iter();
+    let mut watcher = Watcher::new(
+        || get_all_hubs_with_devices_with(snapshots.next().unwrap(), LANG_ID),
+        OPTIONS,
+    );
+    for _ in 0..4 {
+        assert!(watcher.poll().is_empty());
+    }
+}
+
+#[test]
+fn dropping_the_handle_stops_the_thread() {
+    let options = WatchOptions {
+        interval: Duration::from_secs(3600),
+        debounce: 1,
+    };
+    let handle = watch_with(machine(), LANG_ID, options).spawn();
+    let start = Instant::now();
+    handle.stop();
+    assert!(start.elapsed() < Duration::from_secs(60));
+}
+
+#[test]
+fn spawned_watcher_sends_events() {
+    let snapshots = vec![machine(), without_keyboard(), without_keyboard()];
+    let mut snapshots = snapshots.into_iter();
+    let mut last = None;
+    let watcher = Watcher::new(
+        move || {
+            if let Some(next) = snapshots.next() {
+                last = Some(next);
+            }
+            get_all_hubs_with_devices_with(last.as_ref().unwrap(), LANG_ID)
+        },
+        OPTIONS,
+    );
+    let handle = watcher.spawn();
+    let event = handle.events.recv_timeout(Duration::from_secs(60)).unwrap();
+    assert_eq!(summary(&[event]), vec!["detached 046d:c31c at 1-3.2"]);
+}